@@ -1,6 +1,12 @@
+use std::fmt::Write;
+
 use serde::{Deserialize, Serialize};
 
-use castep_dos_core::projectors::PDOSConfig;
+use castep_dos_core::{
+    fundamental::OrbitalState,
+    pdos_compute::{BroadeningKernel, DosMethod, EnergyReference, FermiLevelSolver},
+    projectors::{Mapping, PDOSConfig},
+};
 use thiserror::Error;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -10,6 +16,13 @@ pub struct ProgramConfig {
     pub pdos_config: PDOSConfig,
     #[serde(default)]
     pub energy_grid: EnergyGridConfig,
+    /// Recompute the Fermi energy from the electron count when present,
+    /// instead of the `.bands` header value
+    #[serde(default)]
+    pub fermi_level: Option<FermiLevelSolver>,
+    /// Band moments of every projector, printed when `[moments]` is present
+    #[serde(default)]
+    pub moments: Option<MomentsConfig>,
 }
 
 impl ProgramConfig {
@@ -17,8 +30,75 @@ impl ProgramConfig {
         Self {
             pdos_config: PDOSConfig::example(),
             energy_grid: EnergyGridConfig::default(),
+            fermi_level: None,
+            moments: None,
         }
     }
+
+    /// Starter config of a seed: a `mapping` entry and a projector for every
+    /// species, and a commented-out projector for every ion.
+    /// `toml` can not write comments, so the text is formatted by hand.
+    pub fn starter_toml(mapping: &[Mapping], orbital_states: &[OrbitalState]) -> String {
+        let mut mapping = mapping.iter().collect::<Vec<&Mapping>>();
+        mapping.sort_by_key(|m| m.rank());
+        let mut ions = orbital_states
+            .iter()
+            .map(|state| (state.species_id, state.ion_id))
+            .collect::<Vec<(u32, u32)>>();
+        ions.sort();
+        ions.dedup();
+        let mut output = String::new();
+        write_starter_toml(&mut output, &mapping, &ions).expect("Writing to `String` never fails");
+        output
+    }
+}
+
+fn write_starter_toml(
+    output: &mut String,
+    mapping: &[&Mapping],
+    ions: &[(u32, u32)],
+) -> std::fmt::Result {
+    writeln!(output, "[pdos]")?;
+    writeln!(output, "mapping = [")?;
+    mapping.iter().try_for_each(|m| {
+        writeln!(
+            output,
+            "    {{ species = \"{}\", rank = {} }},",
+            m.species().as_str(),
+            m.rank()
+        )
+    })?;
+    writeln!(output, "]")?;
+    mapping.iter().try_for_each(|m| {
+        let species = m.species().as_str();
+        writeln!(output)?;
+        writeln!(output, "[[pdos.projector]]")?;
+        writeln!(output, "name = \"{species}\"")?;
+        writeln!(output, "[[pdos.projector.selections]]")?;
+        writeln!(output, "species = \"{species}\"")
+    })?;
+    writeln!(output)?;
+    writeln!(output, "# Per-atom projectors, uncomment to use")?;
+    ions.iter()
+        .filter_map(|(species_id, ion_id)| {
+            mapping
+                .iter()
+                .find(|m| m.rank() == *species_id)
+                .map(|m| (m.species().as_str(), ion_id))
+        })
+        .try_for_each(|(species, ion_id)| {
+            writeln!(output)?;
+            writeln!(output, "# [[pdos.projector]]")?;
+            writeln!(output, "# name = \"{species}_{ion_id}\"")?;
+            writeln!(output, "# [[pdos.projector.selections]]")?;
+            writeln!(output, "# species = \"{species}\"")?;
+            writeln!(output, "# atoms = [{ion_id}]")
+        })?;
+    let energy_grid = EnergyGridConfig::default();
+    writeln!(output)?;
+    writeln!(output, "[energy_grid]")?;
+    writeln!(output, "points_per_ev = {}", energy_grid.points_per_ev)?;
+    writeln!(output, "smearing = {}", energy_grid.smearing)
 }
 
 #[derive(Debug, Clone, Copy, Deserialize, Serialize)]
@@ -27,7 +107,41 @@ pub struct EnergyGridConfig {
     pub min: Option<f64>,
     pub max: Option<f64>,
     pub points_per_ev: usize,
+    /// Width of the broadening kernel in eV
     pub smearing: f64,
+    /// Broadening line shape, `[energy_grid.kernel]` with `type = "gaussian"`,
+    /// `"lorentzian"`, `"voigt"` (with `gamma`), `"methfessel_paxton"` (with `order`)
+    /// or `"fermi_dirac"`
+    pub kernel: BroadeningKernel,
+    /// Integration method, `[energy_grid.method]` with `type = "smearing"`,
+    /// `"fast_smearing"` (with `subdivisions`, default 4),
+    /// `"adaptive"` (with `scale`, `min_width` and `max_width` in eV) or
+    /// `"tetrahedron"` (with `blochl_correction`, default `true`).
+    /// `smearing` is ignored by the adaptive and tetrahedron methods,
+    /// `kernel` by the tetrahedron method.
+    pub method: DosMethod,
+    /// Zero of the energy axis, `[energy_grid.reference]` with
+    /// `type = "per_spin"`, `"highest"`, `"common"` (solved with the
+    /// `[fermi_level]` settings), `"absolute"` or `"value"` (with `energy`
+    /// in eV). `min` and `max` are relative to it.
+    pub reference: EnergyReference,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+/// Energy window of the band moments in eV, on the energy grid
+pub struct MomentsConfig {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl MomentsConfig {
+    /// The window, unbounded on the sides without a value
+    pub fn window(&self) -> (f64, f64) {
+        (
+            self.min.unwrap_or(f64::NEG_INFINITY),
+            self.max.unwrap_or(f64::INFINITY),
+        )
+    }
 }
 
 #[derive(Debug, Error)]
@@ -43,12 +157,23 @@ pub enum ConfigError {
 }
 
 impl EnergyGridConfig {
-    pub fn new(min: Option<f64>, max: Option<f64>, points_per_ev: usize, smearing: f64) -> Self {
+    pub fn new(
+        min: Option<f64>,
+        max: Option<f64>,
+        points_per_ev: usize,
+        smearing: f64,
+        kernel: BroadeningKernel,
+        method: DosMethod,
+        reference: EnergyReference,
+    ) -> Self {
         Self {
             min,
             max,
             points_per_ev,
             smearing,
+            kernel,
+            method,
+            reference,
         }
     }
 
@@ -77,6 +202,9 @@ impl Default for EnergyGridConfig {
             max: None,
             points_per_ev: 100,
             smearing: 0.1,
+            kernel: BroadeningKernel::default(),
+            method: DosMethod::default(),
+            reference: EnergyReference::default(),
         }
     }
 }
@@ -84,7 +212,15 @@ impl Default for EnergyGridConfig {
 #[cfg(test)]
 mod test {
 
-    use super::ProgramConfig;
+    use castep_dos_core::{
+        fundamental::{AngularMomentum, OrbitalState},
+        pdos_compute::{
+            AdaptiveBroadening, BroadeningKernel, DosMethod, EnergyReference, FermiLevelSolver,
+        },
+        projectors::{Mapping, PDOSConfig},
+    };
+
+    use super::{MomentsConfig, ProgramConfig};
 
     const MOS2_CONFIG: &str = r#"
 [pdos]
@@ -101,6 +237,11 @@ atoms= [1]
 [energy_grid]
 points_per_ev=100
 smearing=0.2
+[energy_grid.kernel]
+type = "voigt"
+gamma = 0.05
+[energy_grid.method]
+type = "tetrahedron"
 "#;
     const PT_CONFIG: &str = r#"[[projector]]
 [[projector.selections]]
@@ -111,8 +252,6 @@ atoms = [1]
 species = "O"
 atoms= [1]
 "#;
-    const PDOS_FILE: &str = "/home/tony/Downloads/cosxmos2_DOS/cosxmos2_DOS.pdos_weights";
-    const PT_PDOS: &str = "/home/tony/Downloads/Pt_311/Pt_311_12lyr_v20_CO.pdos_weights";
     const CAO_CONFIG: &str = r#"[[projector]]
 [[projector.selections]]
 species = "Ca"
@@ -122,10 +261,110 @@ atoms = [1]
 species = "O"
 atoms= [1]
 "#;
-    const CAO_PDOS_FILE: &str = "/home/tony/Downloads/CaO_CASTEP_Energy/CaO.pdos_weights";
     #[test]
     fn test_run_config() {
         let config = toml::from_str::<ProgramConfig>(MOS2_CONFIG).unwrap();
-        dbg!(config);
+        assert_eq!(
+            config.energy_grid.kernel,
+            BroadeningKernel::Voigt { gamma: 0.05 }
+        );
+        assert_eq!(
+            config.energy_grid.method,
+            DosMethod::Tetrahedron {
+                blochl_correction: true
+            }
+        );
+        assert!(config.moments.is_none());
+        let config =
+            toml::from_str::<ProgramConfig>(PT_CONFIG.replace("[[", "[[pdos.").as_str()).unwrap();
+        assert_eq!(config.energy_grid.kernel, BroadeningKernel::Gaussian);
+        assert_eq!(config.energy_grid.method, DosMethod::Smearing);
+        let config = toml::from_str::<ProgramConfig>(&format!(
+            "[moments]\nmin = -10.0\n{}",
+            PT_CONFIG.replace("[[", "[[pdos.")
+        ))
+        .unwrap();
+        assert_eq!(
+            config.moments.map(|m| m.window()),
+            Some((-10.0, f64::INFINITY))
+        );
+        assert_eq!(toml::from_str::<MomentsConfig>("").unwrap().min, None);
+        assert!(config.fermi_level.is_none());
+        assert_eq!(config.energy_grid.reference, EnergyReference::PerSpin);
+        let config = toml::from_str::<ProgramConfig>(&MOS2_CONFIG.replace(
+            "[energy_grid.method]",
+            "[energy_grid.reference]\ntype = \"value\"\nenergy = -4.5\n[energy_grid.method]",
+        ))
+        .unwrap();
+        assert_eq!(
+            config.energy_grid.reference,
+            EnergyReference::Value { energy: -4.5 }
+        );
+        let config = toml::from_str::<ProgramConfig>(&format!(
+            "[fermi_level]\nsmearing = 0.05\n[fermi_level.occupation]\ntype = \"fermi_dirac\"\n{}",
+            PT_CONFIG.replace("[[", "[[pdos.")
+        ))
+        .unwrap();
+        assert_eq!(
+            config.fermi_level,
+            Some(FermiLevelSolver::new(BroadeningKernel::FermiDirac, 0.05))
+        );
+        let config = toml::from_str::<ProgramConfig>(
+            &MOS2_CONFIG.replace(r#"type = "tetrahedron""#, r#"type = "fast_smearing""#),
+        )
+        .unwrap();
+        assert_eq!(
+            config.energy_grid.method,
+            DosMethod::FastSmearing { subdivisions: 4 }
+        );
+        let config = toml::from_str::<ProgramConfig>(&MOS2_CONFIG.replace(
+            r#"type = "tetrahedron""#,
+            "type = \"adaptive\"\nscale = 0.3",
+        ))
+        .unwrap();
+        assert_eq!(
+            config.energy_grid.method,
+            DosMethod::Adaptive(AdaptiveBroadening {
+                scale: 0.3,
+                ..Default::default()
+            })
+        );
+    }
+    #[test]
+    fn test_starter_toml() {
+        let mapping = vec![Mapping::new("Mo", 2), Mapping::new("S", 1)];
+        let orbital_states = [(1, 1), (1, 2), (2, 1)]
+            .into_iter()
+            .flat_map(|(species, ion)| {
+                [AngularMomentum::S, AngularMomentum::P]
+                    .map(|am| OrbitalState::new(species, ion, am))
+            })
+            .collect::<Vec<OrbitalState>>();
+        let content = ProgramConfig::starter_toml(&mapping, &orbital_states);
+        let config = toml::from_str::<ProgramConfig>(&content).unwrap();
+        assert_eq!(config.pdos_config.projectors.len(), 2);
+        assert_eq!(config.pdos_config.projectors[0].name.as_deref(), Some("S"));
+        assert!(content.contains("# atoms = [2]"), "{content}");
+        let uncommented = content
+            .replace("# [[", "[[")
+            .replace("# name", "name")
+            .replace("# species", "species")
+            .replace("# atoms", "atoms");
+        let config = toml::from_str::<ProgramConfig>(&uncommented).unwrap();
+        assert_eq!(config.pdos_config.projectors.len(), 5);
+        assert_eq!(
+            config.pdos_config.projectors[4].name.as_deref(),
+            Some("Mo_1")
+        );
+    }
+
+    #[test]
+    fn test_config_without_mapping() {
+        let fallback = vec![Mapping::new("O", 1), Mapping::new("Ca", 2)];
+        [PT_CONFIG, CAO_CONFIG].iter().for_each(|content| {
+            let config = toml::from_str::<PDOSConfig>(content).unwrap();
+            assert!(config.species_mapping(None).is_none());
+            assert!(config.species_mapping(Some(&fallback)).is_some());
+        });
     }
 }
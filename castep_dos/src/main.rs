@@ -1,7 +1,8 @@
 use std::{
-    fs::{read, read_to_string, write},
+    fs::{File, read, read_to_string, write},
     io,
-    path::Path,
+    path::{Path, PathBuf},
+    process::ExitCode,
     time::Instant,
 };
 
@@ -10,10 +11,26 @@ use castep_dos::{
     plot::plot,
 };
 use castep_dos_core::{
-    bands::{BandsParser, BandsParsingError},
-    fundamental::{BandStructure, PDOSWeights, SpinData},
-    pdos_compute::{PDOSResult, calculate_pdos},
-    pdos_weights_parser::{ParsingError, parse_pdos_weight_file},
+    bands::{BandsFile, BandsParser, BandsParsingError},
+    castep_output::{CastepOutput, CastepOutputParser, CastepOutputParsingError},
+    cell::{CellFile, CellParser, CellParsingError},
+    fundamental::{
+        BandEdges, BandStructure, HATREE_TO_EV, KpointMismatchError, PDOSWeights, PDOSWeightsFile,
+        SpinData, SpinLayoutError, align_kpoints,
+    },
+    moments::{ChannelMoments, eigenvalue_moments, pdos_moments},
+    pdos_compute::{
+        BroadeningFactors, DosMethod, EnergyReference, FermiLevelError, HistogramError, KpointGrid,
+        KpointGridError, PDOSResult, TetrahedronMesh, calculate_pdos_histogram,
+        calculate_pdos_tetrahedron,
+    },
+    pdos_weights_parallel::parse_pdos_weight_mmap,
+    pdos_weights_parser::{ParsingError, parse_pdos_weight_records},
+    projectors::{Mapping, ProjectionError, ProjectorConfig},
+    spilling::{Spilling, TotalDOS, spilling_csv, total_dos_weights},
+    spin_analysis::{SpinMoments, SpinPolarization},
+    summary::SeedSummary,
+    validation::{check_sum_rule, validate_config, validate_seed},
 };
 use clap::{Parser, Subcommand};
 use plotters::prelude::DrawingAreaErrorKind;
@@ -27,8 +44,29 @@ pub struct ProgArgs {
 
 #[derive(Debug, Subcommand)]
 enum Commands {
-    Run { seed: String },
+    Run {
+        seed: String,
+    },
     Example,
+    /// Cross-check `.bands`, `.pdos_weights` and the config of the seed
+    Validate {
+        seed: String,
+    },
+    /// Print what the seed files contain
+    Info {
+        seed: String,
+    },
+    /// Report band edges and gaps from `.bands`
+    Gap {
+        seed: String,
+    },
+    /// Write a starter `<seed>.toml` from `.pdos_weights` and `.cell`
+    Init {
+        seed: String,
+        /// Overwrite an existing `<seed>.toml`
+        #[arg(long)]
+        force: bool,
+    },
 }
 
 #[derive(Debug, Error)]
@@ -41,52 +79,364 @@ pub enum ExeError {
     PDOSWeightsParsing(#[from] ParsingError),
     #[error("Error when parsing `.bands`: {0}")]
     BandsParsing(#[from] BandsParsingError),
+    #[error("Error when parsing `.cell`: {0}")]
+    CellParsing(#[from] CellParsingError),
+    #[error("Error when parsing `.castep`: {0}")]
+    CastepOutputParsing(#[from] CastepOutputParsingError),
+    #[error("K-points of `.bands` and `.pdos_weights` do not match: {0}")]
+    KpointMismatch(#[from] KpointMismatchError),
+    #[error("No `mapping` in config and no `.cell` or `.castep` to derive the species ranks from")]
+    MissingSpeciesMapping,
+    #[error("Invalid projector config: {0}")]
+    Projection(#[from] ProjectionError),
+    #[error("Can not reconstruct the k-point grid for the DOS method: {0}")]
+    KpointGrid(#[from] KpointGridError),
+    #[error("Can not recompute the Fermi level: {0}")]
+    FermiLevel(#[from] FermiLevelError),
+    #[error("Can not run the fast smearing: {0}")]
+    Histogram(#[from] HistogramError),
+    #[error("Seed files disagree on spin polarization: {0}")]
+    SpinLayout(#[from] SpinLayoutError),
+    #[error("Seed files failed validation")]
+    ValidationFailed,
+    #[error("{} already exists, pass `--force` to overwrite", .0.display())]
+    ConfigExists(PathBuf),
     #[error("Error when plotting pdos result: {0}")]
     Drawing(#[from] DrawingAreaErrorKind<std::io::Error>),
 }
 /// Execution
-fn main() -> Result<(), ExeError> {
+fn main() -> ExitCode {
     let args = ProgArgs::parse();
-    match args.commands {
+    match execute(args.commands) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn execute(commands: Commands) -> Result<(), ExeError> {
+    match commands {
         Commands::Example => write(
             Path::new("example.toml"),
             toml::to_string_pretty(&ProgramConfig::example()).map_err(ConfigError::Serialize)?,
         )
         .map_err(ExeError::IOError),
         Commands::Run { seed } => run(&seed),
+        Commands::Validate { seed } => validate(&seed),
+        Commands::Info { seed } => info(&seed),
+        Commands::Gap { seed } => gap(&seed),
+        Commands::Init { seed, force } => init(&seed, force),
+    }
+}
+
+fn init(seed: &str, force: bool) -> Result<(), ExeError> {
+    let seed_stem = Path::new(seed);
+    let config_file = seed_stem.with_extension("toml");
+    if config_file.exists() && !force {
+        return Err(ExeError::ConfigExists(config_file));
+    }
+    let orbital_states = load_pdos_weights_file(seed_stem)?
+        .header
+        .extract_orbital_states();
+    let mut mapping = seed_species_mapping(seed_stem)?.unwrap_or_default();
+    // Without `.cell` or `.castep` the symbols are unknown, leave placeholders to edit
+    let mut placeholders = orbital_states
+        .iter()
+        .map(|state| state.species_id)
+        .filter(|id| mapping.iter().all(|m| m.rank() != *id))
+        .collect::<Vec<u32>>();
+    placeholders.sort();
+    placeholders.dedup();
+    if !placeholders.is_empty() {
+        println!(
+            "No species symbol for rank {:?}, please edit the `species_<rank>` placeholders",
+            placeholders
+        );
+    }
+    mapping.extend(
+        placeholders
+            .into_iter()
+            .map(|id| Mapping::new(&format!("species_{id}"), id)),
+    );
+    write(
+        &config_file,
+        ProgramConfig::starter_toml(&mapping, &orbital_states),
+    )?;
+    println!("Config written to {}", config_file.display());
+    Ok(())
+}
+
+fn info(seed: &str) -> Result<(), ExeError> {
+    let seed_stem = Path::new(seed);
+    let bands_file = load_bands_file(seed_stem)?;
+    let pdos_weights_file = load_pdos_weights_file(seed_stem)?;
+    // The `mapping` in config is prioritized, as in `run`
+    let config_mapping = seed_stem
+        .with_extension("toml")
+        .exists()
+        .then(|| load_config(seed_stem))
+        .transpose()?
+        .and_then(|prog_config| prog_config.pdos_config.species_mapping);
+    let species_mapping = match config_mapping {
+        Some(mapping) => Some(mapping),
+        None => seed_species_mapping(seed_stem)?,
+    };
+    let summary = SeedSummary::new(&bands_file, &pdos_weights_file, species_mapping.as_deref());
+    println!("{summary}");
+    Ok(())
+}
+
+fn gap(seed: &str) -> Result<(), ExeError> {
+    let bands = load_bands_file(Path::new(seed))?.to_band_structure();
+    let report = |edges: &Option<BandEdges>| match edges {
+        Some(edges) => print!("{edges}"),
+        None => println!("No gap: bands cross the Fermi energy"),
+    };
+    match bands.band_edges()? {
+        SpinData::NonPolarized(edges) => report(&edges),
+        SpinData::SpinPolarized([up, down]) => {
+            println!("Spin up:");
+            report(&up);
+            println!("Spin down:");
+            report(&down);
+        }
+    }
+    Ok(())
+}
+
+fn validate(seed: &str) -> Result<(), ExeError> {
+    let seed_stem = Path::new(seed);
+    let bands_file = load_bands_file(seed_stem)?;
+    let pdos_weights_file = load_pdos_weights_file(seed_stem)?;
+    let mut report = validate_seed(&bands_file, &pdos_weights_file);
+    let config_file = seed_stem.with_extension("toml");
+    if config_file.exists() {
+        let prog_config = load_config(seed_stem)?;
+        let seed_mapping = match prog_config.pdos_config.species_mapping {
+            Some(_) => None,
+            None => seed_species_mapping(seed_stem)?,
+        };
+        let species_mapping = prog_config
+            .pdos_config
+            .species_mapping(seed_mapping.as_deref());
+        let orbital_states = pdos_weights_file.header.extract_orbital_states();
+        report.merge(validate_config(
+            &prog_config.pdos_config,
+            species_mapping.as_ref(),
+            &orbital_states,
+        ));
+    } else {
+        println!(
+            "No {} found, config selections are not checked",
+            config_file.display()
+        );
+    }
+    println!("{report}");
+    if report.passed() {
+        Ok(())
+    } else {
+        Err(ExeError::ValidationFailed)
     }
 }
 
 fn run(seed: &str) -> Result<(), ExeError> {
     let seed_stem = Path::new(&seed);
-    let (prog_config, pdos_weights, bands) = load_pdos_calc_files(seed_stem)?;
+    let (prog_config, pdos_weights, mut bands) = load_pdos_calc_files(seed_stem)?;
+    if let Some(solver) = prog_config.fermi_level {
+        let fermi_energy = solver.solve(&bands)?;
+        println!(
+            "Fermi energy recomputed from the electron count: {}, `.bands` header: {}",
+            format_spin_energies(&fermi_energy),
+            format_spin_energies(&bands.fermi_energy)
+        );
+        bands.fermi_energy = fermi_energy;
+    }
+    let reference = prog_config
+        .energy_grid
+        .reference
+        .resolve(&bands, &prog_config.fermi_level.unwrap_or_default())?;
+    if prog_config.energy_grid.reference != EnergyReference::PerSpin {
+        println!("Energies relative to {}", format_spin_energies(&reference));
+    }
+    // Where the Fermi energy of each spin sits on the energy grid
+    let fermi_energy = bands
+        .fermi_energy
+        .map_pair(&reference, |fermi, zero| (fermi - zero) * HATREE_TO_EV);
+    // Absolute energy of the zero of the grid of each spin
+    let zero = reference.map(|zero| zero * HATREE_TO_EV);
+    // The occupations of the spilling follow the Fermi energy, not the reference
+    let occupation_fermi_energy = bands.fermi_energy.clone();
+    // The PDOS is computed relative to `fermi_energy` of `bands`
+    bands.fermi_energy = reference;
     let (e_min, e_max) = determine_energy_range(&bands, &prog_config.energy_grid);
     let energy_grid = generate_grid(e_min, e_max, prog_config.energy_grid.points_per_ev);
-    let species_mapping = prog_config.pdos_config.species_mapping();
+    // The seed is only read when the config leaves out `mapping`
+    let seed_mapping = match prog_config.pdos_config.species_mapping {
+        Some(_) => None,
+        None => seed_species_mapping(seed_stem)?,
+    };
+    let species_mapping = prog_config
+        .pdos_config
+        .species_mapping(seed_mapping.as_deref())
+        .ok_or(ExeError::MissingSpeciesMapping)?;
     let before = Instant::now();
-    prog_config
+    // Check every projector before writing any output
+    let projections = prog_config
         .pdos_config
         .projectors
         .iter()
         .enumerate()
         .map(|(i, proj_conf)| {
-            (
-                proj_conf
-                    .name
-                    .clone()
-                    .unwrap_or(format!("setting_{}", i + 1)),
-                proj_conf.project_pdos_from_config(&species_mapping, &pdos_weights),
-            )
+            let named = ProjectorConfig {
+                name: Some(
+                    proj_conf
+                        .name
+                        .clone()
+                        .unwrap_or(format!("setting_{}", i + 1)),
+                ),
+                ..proj_conf.clone()
+            };
+            if named.m_resolved {
+                named.m_resolved_projectors(&species_mapping, &pdos_weights)
+            } else {
+                Ok(vec![named])
+            }
+        })
+        .collect::<Result<Vec<Vec<ProjectorConfig>>, ProjectionError>>()?
+        .into_iter()
+        .flatten()
+        .map(|proj_conf| {
+            proj_conf
+                .project_pdos_from_config(&species_mapping, &pdos_weights)
+                .map(|projected_weights| (proj_conf.name.unwrap_or_default(), projected_weights))
         })
-        .try_for_each(|(proj_name, projected_weights)| {
-            let result = calculate_pdos(
-                &bands,
-                &projected_weights,
+        .collect::<Result<Vec<_>, ProjectionError>>()?;
+    let method = prog_config.energy_grid.method;
+    let grid = match method {
+        DosMethod::Smearing | DosMethod::FastSmearing { .. } => None,
+        DosMethod::Adaptive(_) | DosMethod::Tetrahedron { .. } => {
+            Some(KpointGrid::from_band_structure(&bands)?)
+        }
+    };
+    let widths = match (method, &grid) {
+        (DosMethod::Adaptive(adaptive), Some(grid)) => Some(adaptive.widths(&bands, grid)),
+        _ => None,
+    };
+    // A projector without selections takes all orbitals
+    let all_orbitals = ProjectorConfig {
+        name: None,
+        label: None,
+        selections: None,
+        m_resolved: false,
+    }
+    .project_pdos_from_config(&species_mapping, &pdos_weights)?;
+    let total_weights = total_dos_weights(&bands);
+    let batch = projections
+        .iter()
+        .map(|(_, projected_weights)| projected_weights)
+        .chain([&all_orbitals, &total_weights])
+        .collect::<Vec<_>>();
+    // The broadening of the eigenvalues is shared by all projectors
+    let mut results = match (method, &widths, &grid) {
+        (DosMethod::Adaptive(_), Some(widths), _) => BroadeningFactors::adaptive(
+            &bands,
+            &energy_grid,
+            &prog_config.energy_grid.kernel,
+            widths,
+        )
+        .apply(&batch)?,
+        (DosMethod::FastSmearing { subdivisions }, _, _) => calculate_pdos_histogram(
+            &bands,
+            &batch,
+            &energy_grid,
+            &prog_config.energy_grid.kernel,
+            prog_config.energy_grid.smearing,
+            subdivisions,
+        )?,
+        (DosMethod::Tetrahedron { blochl_correction }, _, Some(grid)) => {
+            let mesh = TetrahedronMesh::new(grid);
+            batch
+                .iter()
+                .map(|projected_weights| {
+                    calculate_pdos_tetrahedron(
+                        &bands,
+                        projected_weights,
+                        &energy_grid,
+                        &mesh,
+                        blochl_correction,
+                    )
+                })
+                .collect::<Result<Vec<_>, SpinLayoutError>>()?
+        }
+        _ => BroadeningFactors::new(
+            &bands,
+            &energy_grid,
+            &prog_config.energy_grid.kernel,
+            prog_config.energy_grid.smearing,
+        )
+        .apply(&batch)?,
+    };
+    let total_dos = results.pop().expect("total DOS is in the batch");
+    let total_pdos = results.pop().expect("all orbitals are in the batch");
+    projections.into_iter().zip(results).try_for_each(
+        |((proj_name, projected_weights), result)| {
+            if let Some(moments) = prog_config.moments {
+                print_moments(
+                    &proj_name,
+                    &result,
+                    &energy_grid,
+                    &eigenvalue_moments(&bands, &projected_weights, moments.window())?,
+                    moments.window(),
+                )?;
+            }
+            if let Some(polarization) = SpinPolarization::from_pdos(&result) {
+                write(
+                    format!("{seed}_pdos_{proj_name}_spin_polarization.csv"),
+                    polarization.csv_output(&energy_grid),
+                )?;
+            }
+            let window = prog_config
+                .moments
+                .map(|moments| moments.window())
+                .unwrap_or((f64::NEG_INFINITY, f64::INFINITY));
+            if let Some(spin_moments) =
+                SpinMoments::from_pdos(&result, &energy_grid, &fermi_energy, &zero, window)
+            {
+                println!("Spin moments of {proj_name}:");
+                print!("{spin_moments}");
+            }
+            result_output(
+                result,
+                seed,
+                &proj_name,
+                &prog_config,
                 &energy_grid,
-                prog_config.energy_grid.smearing,
-            );
-            result_output(result, seed, &proj_name, &prog_config, &energy_grid)
-        })?;
+                &fermi_energy,
+            )
+        },
+    )?;
+    let sum_rule = check_sum_rule(
+        &total_pdos,
+        &energy_grid,
+        &fermi_energy,
+        &bands.electron_count,
+    );
+    println!(
+        "[{}] {}: {}",
+        sum_rule.status, sum_rule.name, sum_rule.message
+    );
+    total_dos_output(&total_dos, &total_pdos, seed, &energy_grid)?;
+    let spilling = Spilling::from_weights(&pdos_weights, &bands, &occupation_fermi_energy)?;
+    match &spilling {
+        SpinData::NonPolarized(spilling) => println!("Charge spilling: {spilling}"),
+        SpinData::SpinPolarized([up, down]) => {
+            println!("Charge spilling, spin up: {up}");
+            println!("Charge spilling, spin down: {down}");
+        }
+    }
+    write(format!("{seed}_spilling.csv"), spilling_csv(&spilling))?;
     println!(
         "PDOS calculations of {} finished in {:.2?}",
         seed,
@@ -95,26 +445,87 @@ fn run(seed: &str) -> Result<(), ExeError> {
     Ok(())
 }
 
+/// Energies in Hartree as eV, of each spin when spin polarized
+fn format_spin_energies(energies: &SpinData<f64>) -> String {
+    match energies {
+        SpinData::NonPolarized(energy) => format!("{:.4} eV", energy * HATREE_TO_EV),
+        SpinData::SpinPolarized([up, down]) => format!(
+            "{:.4} eV (up), {:.4} eV (down)",
+            up * HATREE_TO_EV,
+            down * HATREE_TO_EV
+        ),
+    }
+}
+
+/// Tables of the band moments of a projector, from the computed PDOS and
+/// from the eigenvalues without broadening
+fn print_moments(
+    proj_name: &str,
+    result: &SpinData<PDOSResult>,
+    energy_grid: &[f64],
+    eigenvalue_moments: &SpinData<ChannelMoments>,
+    window: (f64, f64),
+) -> Result<(), ExeError> {
+    let report = |spin: &str, pdos: &PDOSResult, eigenvalues: &ChannelMoments| {
+        println!("Band moments of {proj_name}{spin}, from PDOS:");
+        print!("{}", pdos_moments(pdos, energy_grid, window));
+        println!("Band moments of {proj_name}{spin}, from eigenvalues:");
+        print!("{eigenvalues}");
+    };
+    match result
+        .zip(eigenvalue_moments)
+        .ok_or(SpinLayoutError("PDOS", "eigenvalue moments"))?
+    {
+        SpinData::NonPolarized((pdos, eigenvalues)) => report("", pdos, eigenvalues),
+        SpinData::SpinPolarized([(pdos_up, up), (pdos_down, down)]) => {
+            report(" (spin up)", pdos_up, up);
+            report(" (spin down)", pdos_down, down);
+        }
+    }
+    Ok(())
+}
+
 fn load_pdos_calc_files(
     seed_stem: &Path,
 ) -> Result<(ProgramConfig, PDOSWeights, BandStructure), ExeError> {
-    let bands_file = seed_stem.with_extension("bands");
-    let pdos_weights_file = seed_stem.with_extension("pdos_bin");
-    let config_file = seed_stem.with_extension("toml");
+    let prog_config = load_config(seed_stem)?;
+    let mut pdos_weights = load_pdos_weights(seed_stem)?;
+    let mut bands = load_bands_file(seed_stem)?.to_band_structure();
+    // `.bands` from parallel runs is not ordered by k-point index
+    align_kpoints(&mut bands, &mut pdos_weights)?;
+    Ok((prog_config, pdos_weights, bands))
+}
 
-    let prog_config = read_to_string(config_file)
+fn load_config(seed_stem: &Path) -> Result<ProgramConfig, ExeError> {
+    let config_file = seed_stem.with_extension("toml");
+    read_to_string(config_file)
         .map_err(ExeError::IOError)
         .and_then(|content| {
             toml::from_str::<ProgramConfig>(&content)
                 .map_err(|e| ExeError::ConfigError(ConfigError::Deserialize(e)))
-        })?;
-    let pdos_weights = read(pdos_weights_file)
+        })
+}
+
+/// `.pdos_bin` is prioritized over `.pdos_weights`
+fn load_pdos_weights_file(seed_stem: &Path) -> Result<PDOSWeightsFile, ExeError> {
+    read(seed_stem.with_extension("pdos_bin"))
         .or_else(|_| read(seed_stem.with_extension("pdos_weights")).map_err(ExeError::IOError))
         .and_then(|content| {
-            parse_pdos_weight_file(&mut &content[..]).map_err(ExeError::PDOSWeightsParsing)
-        })?;
+            parse_pdos_weight_records(&mut &content[..]).map_err(ExeError::PDOSWeightsParsing)
+        })
+}
 
-    let bands = read_to_string(bands_file)
+/// Same as `load_pdos_weights_file`, memory-mapped and parsed in parallel
+/// straight into `PDOSWeights`
+fn load_pdos_weights(seed_stem: &Path) -> Result<PDOSWeights, ExeError> {
+    File::open(seed_stem.with_extension("pdos_bin"))
+        .or_else(|_| File::open(seed_stem.with_extension("pdos_weights")))
+        .map_err(ExeError::IOError)
+        .and_then(|file| parse_pdos_weight_mmap(&file).map_err(ExeError::PDOSWeightsParsing))
+}
+
+fn load_bands_file(seed_stem: &Path) -> Result<BandsFile, ExeError> {
+    read_to_string(seed_stem.with_extension("bands"))
         .map_err(ExeError::IOError)
         .and_then(|content| {
             let bands_parser = BandsParser::new(&content);
@@ -122,8 +533,38 @@ fn load_pdos_calc_files(
                 .parse_bands_file()
                 .map_err(ExeError::BandsParsing)
         })
-        .map(|bands_file| bands_file.to_band_structure())?;
-    Ok((prog_config, pdos_weights, bands))
+}
+
+/// The `.cell` is optional, only needed when `mapping` is left out of config.
+fn load_cell(seed_stem: &Path) -> Result<Option<CellFile>, ExeError> {
+    let cell_file = seed_stem.with_extension("cell");
+    if !cell_file.exists() {
+        return Ok(None);
+    }
+    let content = read_to_string(cell_file)?;
+    let cell = CellParser::new(&content).parse_cell_file()?;
+    Ok(Some(cell))
+}
+
+/// The `.castep` is optional, it provides the species table, Fermi energy
+/// and populations for cross-checking.
+fn load_castep_output(seed_stem: &Path) -> Result<Option<CastepOutput>, ExeError> {
+    let castep_file = seed_stem.with_extension("castep");
+    if !castep_file.exists() {
+        return Ok(None);
+    }
+    let content = read_to_string(castep_file)?;
+    let castep_output = CastepOutputParser::new(&content).parse_castep_output()?;
+    Ok(Some(castep_output))
+}
+
+/// Species ranks from the seed files, used when `mapping` is left out of config.
+/// `.cell` is prioritized over `.castep`.
+fn seed_species_mapping(seed_stem: &Path) -> Result<Option<Vec<Mapping>>, ExeError> {
+    if let Some(cell) = load_cell(seed_stem)? {
+        return Ok(Some(cell.species_mapping()));
+    }
+    Ok(load_castep_output(seed_stem)?.map(|castep_output| castep_output.species_mapping()))
 }
 
 fn generate_grid(e_min: f64, e_max: f64, points_per_ev: usize) -> Vec<f64> {
@@ -153,17 +594,50 @@ fn determine_energy_range(
     }
 }
 
+/// Total DOS from the eigenvalues next to the summed PDOS of all orbitals
+fn total_dos_output(
+    total: &SpinData<PDOSResult>,
+    projected: &SpinData<PDOSResult>,
+    seed: &str,
+    energy_grid: &[f64],
+) -> Result<(), ExeError> {
+    match total
+        .zip(projected)
+        .ok_or(SpinLayoutError("total DOS", "PDOS"))?
+    {
+        SpinData::NonPolarized((total, projected)) => write(
+            format!("{seed}_total_dos.csv"),
+            TotalDOS::new(total, projected).csv_output(energy_grid),
+        )?,
+        SpinData::SpinPolarized([(total_up, projected_up), (total_down, projected_down)]) => {
+            write(
+                format!("{seed}_total_dos_spin_up.csv"),
+                TotalDOS::new(total_up, projected_up).csv_output(energy_grid),
+            )?;
+            write(
+                format!("{seed}_total_dos_spin_down.csv"),
+                TotalDOS::new(total_down, projected_down).csv_output(energy_grid),
+            )?;
+        }
+    }
+    Ok(())
+}
+
 fn result_output(
     result: SpinData<PDOSResult>,
     seed: &str,
     proj_name: &str,
     prog_config: &ProgramConfig,
     energy_grid: &[f64],
+    fermi_energy: &SpinData<f64>,
 ) -> Result<(), ExeError> {
     let backup_name = format!("{}_pdos_{}_config_backup", seed, proj_name);
     let backup_stem = Path::new(&backup_name);
-    match result {
-        castep_dos_core::fundamental::SpinData::NonPolarized(no_spin) => {
+    match result
+        .zip(fermi_energy)
+        .ok_or(SpinLayoutError("PDOS", "fermi_energy"))?
+    {
+        SpinData::NonPolarized((no_spin, fermi)) => {
             let result_name = format!("{}_pdos_{}", seed, proj_name);
             let result_stem = Path::new(&result_name);
             let csv_path = &result_stem.with_extension("csv");
@@ -175,10 +649,10 @@ fn result_output(
                     .map_err(ConfigError::Serialize)
                     .map_err(ExeError::ConfigError)?,
             )?;
-            plot(energy_grid, &no_spin, &result_name)?;
+            plot(energy_grid, no_spin, *fermi, &result_name)?;
             Ok::<(), ExeError>(())
         }
-        castep_dos_core::fundamental::SpinData::SpinPolarized([up, down]) => {
+        SpinData::SpinPolarized([(up, fermi_up), (down, fermi_down)]) => {
             let up_name = format!("{}_pdos_{}_spin_up", seed, proj_name);
             let up_stem = Path::new(&up_name);
             let down_name = format!("{}_pdos_{}_spin_down", seed, proj_name);
@@ -194,8 +668,8 @@ fn result_output(
                     .map_err(ConfigError::Serialize)
                     .map_err(ExeError::ConfigError)?,
             )?;
-            plot(energy_grid, &up, &up_name)?;
-            plot(energy_grid, &down, &down_name)?;
+            plot(energy_grid, up, *fermi_up, &up_name)?;
+            plot(energy_grid, down, *fermi_down, &down_name)?;
             Ok::<(), ExeError>(())
         }
     }
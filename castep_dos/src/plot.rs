@@ -24,9 +24,12 @@ const BLACK: RGBColor = RGBColor(76, 79, 105);
 /// #7c7f93
 const OVERLAY: RGBColor = RGBColor(124, 127, 147);
 
+/// `fermi_energy` is where the Fermi energy sits on `energy_grid` (eV),
+/// marked by a vertical line
 pub fn plot(
     energy_grid: &[f64],
     pdos: &PDOSResult,
+    fermi_energy: f64,
     plotname: &str,
 ) -> Result<(), DrawingAreaErrorKind<std::io::Error>> {
     let plot_name = format!("{plotname}.svg");
@@ -148,7 +151,7 @@ pub fn plot(
         .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 60, y)], YELLOW.stroke_width(3)));
     chart
         .draw_series(LineSeries::new(
-            [(0.0, 0.0), (0.0, y_max)],
+            [(fermi_energy, 0.0), (fermi_energy, y_max)],
             OVERLAY.stroke_width(2),
         ))
         .unwrap();
@@ -4,11 +4,13 @@
 
 use derive_builder::Builder;
 mod parser;
+mod writer;
 
 pub use parser::{BandsParser, BandsParsingError};
+pub use writer::write_bands_file;
 
 use crate::fundamental::{
-    BandStructure, EigenvalueVec, KpointVec, KpointWeight, SpinData, SpinPolarized,
+    BandStructure, EigenvalueVec, KpointCoords, KpointVec, KpointWeight, SpinData, SpinPolarized,
 };
 
 //------------------------------------------
@@ -60,7 +62,7 @@ impl KPoint {
 pub struct BandsFile {
     /// Spin-polarized settings
     pub spin_polarized: SpinPolarized,
-    /// Lattice vectors in Angstroms (row-major)
+    /// Lattice vectors in Bohr (row-major)
     pub lattice_vectors: [[f64; 3]; 3],
     /// Fermi energy/energies in Hartree
     pub fermi_energy: FermiEnergy,
@@ -77,6 +79,11 @@ impl BandsFile {
     /// for PDOS calculation
     pub fn to_band_structure(self) -> BandStructure {
         let spin_polarized = self.spin_polarized;
+        let kpoints = self
+            .kpoints
+            .iter()
+            .map(|kpt| KpointCoords::new(kpt.index as u32, kpt.coords))
+            .collect::<KpointVec<KpointCoords>>();
         let kpoint_weights = self
             .kpoints
             .iter()
@@ -106,11 +113,18 @@ impl BandsFile {
             FermiEnergy::NonPolarized(f) => SpinData::NonPolarized(f),
             FermiEnergy::Polarized(up, down) => SpinData::SpinPolarized([up, down]),
         };
+        let electron_count = match self.electron_count {
+            ElectronCount::NonPolarized(count) => SpinData::NonPolarized(count),
+            ElectronCount::Polarized(up, down) => SpinData::SpinPolarized([up, down]),
+        };
         BandStructure {
             spin_polarized,
+            lattice_vectors: self.lattice_vectors,
+            kpoints,
             kpoint_weights,
             eigenvalues,
             fermi_energy,
+            electron_count,
         }
     }
 }
@@ -349,18 +349,26 @@ impl<'a> BandsParser<'a> {
 
 #[cfg(test)]
 mod test {
-    use std::fs::read_to_string;
+    use crate::{
+        bands::{ElectronCount, write_bands_file},
+        test_fixtures::sample_bands_file,
+    };
 
     use super::BandsParser;
 
-    // const BANDS_FILE: &str = "/home/tony/Downloads/cosxmos2_DOS/cosxmos2_DOS.bands";
-    const BANDS_FILE: &str =
-        "/home/tony/Downloads/Mg2SiO4_Dy_Bandstr_edft/Mg2SiO4_Dy_BandStr.bands";
     #[test]
     fn test_bands_parser() {
-        let bands_content = read_to_string(BANDS_FILE).unwrap();
+        let bands_content = write_bands_file(&sample_bands_file(2));
         let parser = BandsParser::new(&bands_content);
         let band_structure = parser.parse_bands_file().unwrap();
-        dbg!(band_structure);
+        assert_eq!(band_structure.kpoints.len(), 3);
+        assert_eq!(
+            band_structure.electron_count,
+            ElectronCount::Polarized(2.0, 2.0)
+        );
+        assert_eq!(
+            band_structure.eigenvalues.as_spin_polarized().unwrap()[1][2],
+            vec![-0.4609375, -0.2109375, 0.1015625, 0.3515625]
+        );
     }
 }
@@ -0,0 +1,137 @@
+use std::fmt::Write;
+
+use crate::bands::{BandsFile, Eigenvalues, ElectronCount, FermiEnergy};
+
+/// Format `BandsFile` in the text layout `CASTEP` writes `.bands`,
+/// with the same Fortran field widths:
+/// - header counts: `i5`, `i2`, `f11.3`, `i6`
+/// - Fermi energy and unit cell vectors: `f12.6`
+/// - k-point line: `i5` and `4f12.8`
+/// - eigenvalues: `f14.8`
+pub fn write_bands_file(bands_file: &BandsFile) -> String {
+    let mut output = String::new();
+    write_header(&mut output, bands_file)
+        .and_then(|_| write_band_per_kpoint(&mut output, bands_file))
+        .expect("Writing to `String` never fails");
+    output
+}
+
+fn write_header(output: &mut String, bands_file: &BandsFile) -> std::fmt::Result {
+    writeln!(output, "Number of k-points{:>5}", bands_file.kpoints.len())?;
+    match bands_file.electron_count {
+        ElectronCount::NonPolarized(count) => {
+            writeln!(output, "Number of spin components{:>2}", 1)?;
+            writeln!(output, "Number of electrons{:>11.3}     ", count)?;
+        }
+        ElectronCount::Polarized(up, down) => {
+            writeln!(output, "Number of spin components{:>2}", 2)?;
+            writeln!(output, "Number of electrons{:>11.3}{:>11.3}     ", up, down)?;
+        }
+    }
+    match &bands_file.eigenvalues {
+        Eigenvalues::NonPolarized(kpts) => writeln!(
+            output,
+            "Number of eigenvalues{:>6}",
+            kpts.first().map(|eigens| eigens.len()).unwrap_or(0)
+        )?,
+        Eigenvalues::SpinPolarized([up, down]) => writeln!(
+            output,
+            "Number of eigenvalues{:>6}{:>6}",
+            up.first().map(|eigens| eigens.len()).unwrap_or(0),
+            down.first().map(|eigens| eigens.len()).unwrap_or(0)
+        )?,
+    }
+    match bands_file.fermi_energy {
+        FermiEnergy::NonPolarized(fermi) => {
+            writeln!(output, "Fermi energy (in atomic units){:>12.6}", fermi)?
+        }
+        FermiEnergy::Polarized(up, down) => writeln!(
+            output,
+            "Fermi energies (in atomic units){:>12.6}{:>12.6}",
+            up, down
+        )?,
+    }
+    writeln!(output, "Unit cell vectors")?;
+    bands_file.lattice_vectors.iter().try_for_each(|vector| {
+        writeln!(
+            output,
+            "{:>12.6}{:>12.6}{:>12.6}",
+            vector[0], vector[1], vector[2]
+        )
+    })
+}
+
+fn write_band_per_kpoint(output: &mut String, bands_file: &BandsFile) -> std::fmt::Result {
+    bands_file
+        .kpoints
+        .iter()
+        .enumerate()
+        .try_for_each(|(i, kpoint)| {
+            let [kx, ky, kz] = kpoint.coords;
+            writeln!(
+                output,
+                "K-point{:>5}{:>12.8}{:>12.8}{:>12.8}{:>12.8}",
+                kpoint.index, kx, ky, kz, kpoint.weight
+            )?;
+            match &bands_file.eigenvalues {
+                Eigenvalues::NonPolarized(kpts) => write_spin_component(output, 1, &kpts[i]),
+                Eigenvalues::SpinPolarized([up, down]) => {
+                    write_spin_component(output, 1, &up[i])?;
+                    write_spin_component(output, 2, &down[i])
+                }
+            }
+        })
+}
+
+fn write_spin_component(output: &mut String, spin: usize, eigens: &[f64]) -> std::fmt::Result {
+    writeln!(output, "Spin component{:>2}", spin)?;
+    eigens
+        .iter()
+        .try_for_each(|eigen| writeln!(output, "{:>14.8}", eigen))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{bands::BandsParser, test_fixtures::sample_bands_file};
+
+    use super::write_bands_file;
+
+    const SI_BANDS: &str = r#"Number of k-points    2
+Number of spin components 1
+Number of electrons      8.000     
+Number of eigenvalues     4
+Fermi energy (in atomic units)    0.208250
+Unit cell vectors
+   -5.130000    5.130000    5.130000
+    5.130000   -5.130000    5.130000
+    5.130000    5.130000   -5.130000
+K-point    1  0.37500000 -0.37500000  0.37500000  0.75000000
+Spin component 1
+   -0.19022680
+    0.06291283
+    0.13738163
+    0.13738163
+K-point    2 -0.12500000  0.37500000  0.12500000  0.25000000
+Spin component 1
+   -0.15764117
+   -0.04050946
+    0.08669474
+    0.17017516
+"#;
+
+    #[test]
+    fn test_layout() {
+        let bands_file = BandsParser::new(SI_BANDS).parse_bands_file().unwrap();
+        assert_eq!(write_bands_file(&bands_file), SI_BANDS);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        [1, 2].into_iter().for_each(|num_spins| {
+            let bands_file = sample_bands_file(num_spins);
+            let content = write_bands_file(&bands_file);
+            let parsed = BandsParser::new(&content).parse_bands_file().unwrap();
+            assert_eq!(parsed, bands_file);
+        });
+    }
+}
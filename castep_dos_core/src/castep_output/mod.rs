@@ -0,0 +1,103 @@
+#![warn(missing_docs)]
+#![allow(dead_code)]
+//! Crate to parse the `.castep` text output for the species table,
+//! Fermi energy, Mulliken and orbital populations and total spin.
+
+mod parser;
+
+pub use parser::{CastepOutputParser, CastepOutputParsingError};
+
+use crate::{
+    bands::FermiEnergy,
+    fundamental::{AngularChannels, SpinData},
+    projectors::Mapping,
+};
+
+/// Species row of the "Cell Contents" section
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeciesEntry {
+    /// Species symbol
+    pub symbol: String,
+    /// Species id, same as `orbital_species` in `.pdos_weights`
+    pub rank: u32,
+    /// Number of ions of this species
+    pub ion_count: u32,
+}
+
+/// Mulliken population of an ion
+#[derive(Debug, Clone, PartialEq)]
+pub struct MullikenPopulation {
+    /// Species symbol
+    pub species: String,
+    /// Ion index within the species (start at 1)
+    pub ion: u32,
+    /// Populations of s, p, d, f for each spin channel
+    pub orbitals: SpinData<AngularChannels>,
+    /// Total population, summed over spins
+    pub total: f64,
+    /// Charge in e
+    pub charge: f64,
+    /// Spin in hbar/2, only available for spin-polarized runs
+    pub spin: Option<f64>,
+}
+
+/// Population of one sub-orbital of an ion, a row of "Orbital Populations"
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrbitalPopulation {
+    /// Species symbol
+    pub species: String,
+    /// Ion index within the species (start at 1)
+    pub ion: u32,
+    /// Sub-orbital as labelled by `CASTEP`, e.g. `Px` or `Dzz`, see
+    /// `OrbitalLabel::from_castep_label`
+    pub orbital: String,
+    /// Population columns of the row
+    pub populations: Vec<f64>,
+}
+
+/// Integrated spin density after the final SCF cycle, in hbar/2
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TotalSpin {
+    /// Integrated spin density
+    pub integrated: f64,
+    /// Integrated absolute spin density
+    pub integrated_abs: Option<f64>,
+}
+
+/// Parsed `.castep` with necessary data
+#[derive(Debug, Clone, PartialEq)]
+pub struct CastepOutput {
+    /// Species in rank order with ion counts
+    pub species: Vec<SpeciesEntry>,
+    /// Final Fermi energy/energies in eV
+    pub fermi_energy: Option<FermiEnergy>,
+    /// Mulliken populations per ion, in the order of output
+    pub mulliken: Option<Vec<MullikenPopulation>>,
+    /// Populations per sub-orbital of each ion, in the order of output
+    pub orbital_populations: Option<Vec<OrbitalPopulation>>,
+    /// Total spin of the final SCF cycle
+    pub total_spin: Option<TotalSpin>,
+}
+
+impl CastepOutput {
+    /// Species mapping for `PDOSConfig`
+    pub fn species_mapping(&self) -> Vec<Mapping> {
+        self.species
+            .iter()
+            .map(|entry| Mapping::new(&entry.symbol, entry.rank))
+            .collect()
+    }
+
+    /// Number of ions of the species
+    pub fn ion_count(&self, species: &str) -> Option<u32> {
+        self.species
+            .iter()
+            .find(|entry| entry.symbol == species)
+            .map(|entry| entry.ion_count)
+    }
+
+    /// Total number of ions in cell
+    pub fn total_ions(&self) -> u32 {
+        self.species.iter().map(|entry| entry.ion_count).sum()
+    }
+}
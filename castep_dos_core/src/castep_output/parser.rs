@@ -0,0 +1,420 @@
+use thiserror::Error;
+
+use crate::{
+    bands::FermiEnergy,
+    castep_output::{CastepOutput, MullikenPopulation, OrbitalPopulation, SpeciesEntry, TotalSpin},
+    fundamental::{AngularChannels, SpinData},
+};
+
+#[derive(Debug, Error)]
+/// Possible errors in parsing `.castep`
+pub enum CastepOutputParsingError {
+    #[error("Section `{0}` is not found in `.castep`")]
+    /// Required section is missing
+    MissingSection(&'static str),
+    #[error("Invalid line in section `{section}`: `{line}`")]
+    /// Line in section can not be parsed
+    InvalidLine {
+        /// Name of section
+        section: &'static str,
+        /// Content of the line
+        line: String,
+    },
+}
+
+const CELL_CONTENTS: &str = "Cell Contents";
+const MULLIKEN: &str = "Atomic Populations (Mulliken)";
+const ORBITAL_POPULATIONS: &str = "Orbital Populations";
+
+/// Parser of `.castep`, holds the slice of file content.
+/// A `.castep` may hold several runs appended one after another,
+/// the last occurrence of each section is taken.
+#[derive(Debug, Clone)]
+pub struct CastepOutputParser<'a> {
+    input: &'a str,
+}
+
+impl<'a> CastepOutputParser<'a> {
+    /// Constructor
+    pub fn new(input: &'a str) -> Self {
+        Self { input }
+    }
+
+    /// Lines after the last line containing `pattern`
+    fn lines_after_last(&self, pattern: &str) -> Option<impl Iterator<Item = &'a str>> {
+        let start = self.input.rfind(pattern)?;
+        Some(self.input[start..].lines().skip(1))
+    }
+
+    /// Species table from the ion coordinates in "Cell Contents":
+    /// `CASTEP` lists the ions grouped by species, in rank order.
+    fn parse_species(&self) -> Result<Vec<SpeciesEntry>, CastepOutputParsingError> {
+        let invalid_line = |line: &str| CastepOutputParsingError::InvalidLine {
+            section: CELL_CONTENTS,
+            line: line.to_string(),
+        };
+        let lines = self
+            .lines_after_last(CELL_CONTENTS)
+            .ok_or(CastepOutputParsingError::MissingSection(CELL_CONTENTS))?
+            .skip_while(|line| !line.contains("Element"))
+            // Skip the table heading to the separating line `x-----x`
+            .skip_while(|line| !line.contains("x---"))
+            .skip(1);
+        let mut species: Vec<SpeciesEntry> = Vec::new();
+        for line in lines {
+            let trimmed = line.trim().trim_matches('x');
+            if trimmed.trim().is_empty() {
+                break;
+            }
+            let tokens = trimmed.split_whitespace().collect::<Vec<&str>>();
+            let (symbol, ion) = match tokens.as_slice() {
+                [symbol, ion, _u, _v, _w, ..] => {
+                    (*symbol, ion.parse::<u32>().map_err(|_| invalid_line(line))?)
+                }
+                _ => return Err(invalid_line(line)),
+            };
+            match species.last_mut() {
+                Some(entry) if entry.symbol == symbol => entry.ion_count = entry.ion_count.max(ion),
+                _ => species.push(SpeciesEntry {
+                    symbol: symbol.to_string(),
+                    rank: species.len() as u32 + 1,
+                    ion_count: ion,
+                }),
+            }
+        }
+        if species.is_empty() {
+            Err(CastepOutputParsingError::MissingSection(CELL_CONTENTS))
+        } else {
+            Ok(species)
+        }
+    }
+
+    /// The last reported Fermi energy, e.g.
+    /// `Fermi energy for spin  up electrons  =  -1.234 eV`.
+    /// The value is the number right before `eV`.
+    /// `CASTEP` reports the spin-up value before the spin-down one, so an
+    /// up line or a non-spin line starts a new report and discards the
+    /// values of earlier runs.
+    fn parse_fermi_energy(&self) -> Option<FermiEnergy> {
+        let value_before_ev = |line: &str| -> Option<f64> {
+            let tokens = line.split_whitespace().collect::<Vec<&str>>();
+            let ev_pos = tokens.iter().position(|token| *token == "eV")?;
+            tokens.get(ev_pos.checked_sub(1)?)?.parse::<f64>().ok()
+        };
+        let mut non_polarized = None;
+        let mut up = None;
+        let mut down = None;
+        self.input
+            .lines()
+            .filter(|line| line.contains("Fermi energy"))
+            .for_each(|line| {
+                let Some(value) = value_before_ev(line) else {
+                    return;
+                };
+                let lowercase = line.to_lowercase();
+                if !lowercase.contains("spin") {
+                    non_polarized = Some(value);
+                    up = None;
+                    down = None;
+                } else if lowercase.contains("down") || lowercase.contains("dn") {
+                    down = Some(value);
+                } else {
+                    up = Some(value);
+                    down = None;
+                    non_polarized = None;
+                }
+            });
+        match (up, down, non_polarized) {
+            (Some(up), Some(down), _) => Some(FermiEnergy::Polarized(up, down)),
+            (_, _, Some(fermi)) => Some(FermiEnergy::NonPolarized(fermi)),
+            (Some(fermi), None, None) => Some(FermiEnergy::NonPolarized(fermi)),
+            _ => None,
+        }
+    }
+
+    /// The last Mulliken population table.
+    /// Spin-polarized tables carry an `up:` row and a `dn:` row for each ion.
+    fn parse_mulliken(&self) -> Result<Option<Vec<MullikenPopulation>>, CastepOutputParsingError> {
+        let invalid_line = |line: &str| CastepOutputParsingError::InvalidLine {
+            section: MULLIKEN,
+            line: line.to_string(),
+        };
+        let Some(mut lines) = self.lines_after_last(MULLIKEN) else {
+            return Ok(None);
+        };
+        let heading = lines
+            .find(|line| line.trim_start().starts_with("Species"))
+            .ok_or(CastepOutputParsingError::MissingSection(MULLIKEN))?;
+        // Orbital columns present in this table
+        let orbital_columns = heading
+            .split_whitespace()
+            .filter(|column| ["s", "p", "d", "f"].contains(column))
+            .collect::<Vec<&str>>();
+        let spin_polarized = heading.split_whitespace().any(|column| column == "Spin");
+        let to_channels = |values: &[f64]| {
+            let mut channels = AngularChannels::zero();
+            orbital_columns
+                .iter()
+                .zip(values)
+                .for_each(|(column, value)| match *column {
+                    "s" => channels.s = *value,
+                    "p" => channels.p = *value,
+                    "d" => channels.d = *value,
+                    _ => channels.f = *value,
+                });
+            channels
+        };
+        let parse_values = |line: &str, tokens: &[&str]| -> Result<Vec<f64>, _> {
+            tokens
+                .iter()
+                .map(|token| token.parse::<f64>().map_err(|_| invalid_line(line)))
+                .collect::<Result<Vec<f64>, CastepOutputParsingError>>()
+        };
+        let n_orb = orbital_columns.len();
+        let mut lines = lines.skip_while(|line| !line.contains("===")).skip(1);
+        let mut populations = Vec::new();
+        while let Some(line) = lines.next() {
+            if line.contains("===") || line.trim().is_empty() {
+                break;
+            }
+            let tokens = line.split_whitespace().collect::<Vec<&str>>();
+            let (species, ion, values) = match (spin_polarized, tokens.as_slice()) {
+                (true, [species, ion, "up:", values @ ..])
+                | (false, [species, ion, values @ ..]) => (
+                    species.to_string(),
+                    ion.parse::<u32>().map_err(|_| invalid_line(line))?,
+                    parse_values(line, values)?,
+                ),
+                _ => return Err(invalid_line(line)),
+            };
+            if values.len() < n_orb + 2 {
+                return Err(invalid_line(line));
+            }
+            let population = if spin_polarized {
+                let down_line = lines.next().ok_or_else(|| invalid_line(line))?;
+                let down_tokens = down_line.split_whitespace().collect::<Vec<&str>>();
+                let down_values = match down_tokens.as_slice() {
+                    ["dn:", values @ ..] if values.len() > n_orb => {
+                        parse_values(down_line, values)?
+                    }
+                    _ => return Err(invalid_line(down_line)),
+                };
+                MullikenPopulation {
+                    species,
+                    ion,
+                    orbitals: SpinData::SpinPolarized([
+                        to_channels(&values[..n_orb]),
+                        to_channels(&down_values[..n_orb]),
+                    ]),
+                    total: values[n_orb] + down_values[n_orb],
+                    charge: values[n_orb + 1],
+                    spin: values.get(n_orb + 2).copied(),
+                }
+            } else {
+                MullikenPopulation {
+                    species,
+                    ion,
+                    orbitals: SpinData::NonPolarized(to_channels(&values[..n_orb])),
+                    total: values[n_orb],
+                    charge: values[n_orb + 1],
+                    spin: None,
+                }
+            };
+            populations.push(population);
+        }
+        Ok(Some(populations))
+    }
+
+    /// The last table of sub-orbital populations, one row per sub-orbital
+    /// of each ion, e.g. `Ca  1  Dzz  0.190`, closed by a dashed line
+    fn parse_orbital_populations(
+        &self,
+    ) -> Result<Option<Vec<OrbitalPopulation>>, CastepOutputParsingError> {
+        let invalid_line = |line: &str| CastepOutputParsingError::InvalidLine {
+            section: ORBITAL_POPULATIONS,
+            line: line.to_string(),
+        };
+        let Some(lines) = self.lines_after_last(ORBITAL_POPULATIONS) else {
+            return Ok(None);
+        };
+        lines
+            // Skip the table heading to the dashed line under it
+            .skip_while(|line| !line.trim_start().starts_with("Ion"))
+            .skip_while(|line| !line.contains("---"))
+            .skip(1)
+            .take_while(|line| !line.contains("---") && !line.trim().is_empty())
+            .map(|line| {
+                let tokens = line.split_whitespace().collect::<Vec<&str>>();
+                match tokens.as_slice() {
+                    [species, ion, orbital, values @ ..] if !values.is_empty() => {
+                        Ok(OrbitalPopulation {
+                            species: species.to_string(),
+                            ion: ion.parse::<u32>().map_err(|_| invalid_line(line))?,
+                            orbital: orbital.to_string(),
+                            populations: values
+                                .iter()
+                                .map(|value| value.parse::<f64>().map_err(|_| invalid_line(line)))
+                                .collect::<Result<Vec<f64>, CastepOutputParsingError>>()?,
+                        })
+                    }
+                    _ => Err(invalid_line(line)),
+                }
+            })
+            .collect::<Result<Vec<OrbitalPopulation>, CastepOutputParsingError>>()
+            .map(Some)
+    }
+
+    /// `Integrated Spin Density     =    2.00000 hbar/2`
+    fn parse_total_spin(&self) -> Option<TotalSpin> {
+        let last_value = |pattern: &str| -> Option<f64> {
+            self.input
+                .lines()
+                .filter(|line| line.contains(pattern))
+                .filter_map(|line| {
+                    line.split('=')
+                        .nth(1)?
+                        .split_whitespace()
+                        .next()?
+                        .parse::<f64>()
+                        .ok()
+                })
+                .next_back()
+        };
+        last_value("Integrated Spin Density").map(|integrated| TotalSpin {
+            integrated,
+            integrated_abs: last_value("Integrated |Spin Density|"),
+        })
+    }
+
+    /// Main usage
+    pub fn parse_castep_output(self) -> Result<CastepOutput, CastepOutputParsingError> {
+        Ok(CastepOutput {
+            species: self.parse_species()?,
+            fermi_energy: self.parse_fermi_energy(),
+            mulliken: self.parse_mulliken()?,
+            orbital_populations: self.parse_orbital_populations()?,
+            total_spin: self.parse_total_spin(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CastepOutputParser;
+    use crate::{
+        bands::FermiEnergy,
+        fundamental::{AngularChannels, SpinData},
+    };
+
+    const CAO_CASTEP: &str = r#"
+                           -------------------------------
+                                      Cell Contents
+                           -------------------------------
+
+                         Total number of ions in cell =    2
+                      Total number of species in cell =    2
+                        Max number of any one species =    1
+
+            xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx
+            x  Element    Atom        Fractional coordinates of atoms  x
+            x            Number           u          v          w      x
+            x----------------------------------------------------------x
+            x  O            1         0.000000   0.000000   0.000000   x
+            x  Ca           1         0.500000   0.500000   0.500000   x
+            xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx
+
+ Fermi energy for spin  up electrons  =   4.2101 eV
+
+     Atomic Populations (Mulliken)
+     -----------------------------
+Species          Ion     s       p       d       f      Total   Charge (e)
+==============================================================================
+  O              1     1.86    5.06    0.00    0.00     6.92    -0.92
+  Ca             1     2.13    6.00    0.95    0.00     9.08     0.92
+==============================================================================
+"#;
+
+    const FE_CASTEP: &str = r#"
+                           -------------------------------
+                                      Cell Contents
+                           -------------------------------
+            xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx
+            x  Element    Atom        Fractional coordinates of atoms  x
+            x            Number           u          v          w      x
+            x----------------------------------------------------------x
+            x  Fe           1         0.000000   0.000000   0.000000   x
+            x  Fe           2         0.500000   0.500000   0.500000   x
+            xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx
+Integrated Spin Density     =    4.44000     hbar/2
+Integrated |Spin Density|   =    4.80000     hbar/2
+Fermi energy for spin  up electrons  =   6.5000 eV
+Fermi energy for spin down electrons =   6.4000 eV
+
+     Atomic Populations (Mulliken)
+     -----------------------------
+Species          Ion Spin      s       p       d       f      Total   Charge(e)   Spin(hbar/2)
+===============================================================================================
+  Fe              1   up:     0.500   0.500   4.600   0.000   5.600    -0.000      2.22
+                      dn:     0.500   0.500   2.400   0.000   3.400
+  Fe              2   up:     0.500   0.500   4.600   0.000   5.600    -0.000      2.22
+                      dn:     0.500   0.500   2.400   0.000   3.400
+===============================================================================================
+"#;
+
+    #[test]
+    fn test_castep_output() {
+        let output = CastepOutputParser::new(CAO_CASTEP)
+            .parse_castep_output()
+            .unwrap();
+        assert_eq!(output.species.len(), 2);
+        assert_eq!(output.species[0].symbol, "O");
+        assert_eq!(output.species[1].rank, 2);
+        assert_eq!(output.total_ions(), 2);
+        // No spin in a non-polarized run, even if the line says "spin up"
+        assert_eq!(output.fermi_energy, Some(FermiEnergy::NonPolarized(4.2101)));
+        assert!(output.total_spin.is_none());
+        let mulliken = output.mulliken.unwrap();
+        assert_eq!(
+            mulliken[1].orbitals,
+            SpinData::NonPolarized(AngularChannels::new(2.13, 6.0, 0.95, 0.0))
+        );
+        assert_eq!(mulliken[1].charge, 0.92);
+        assert!(output.orbital_populations.is_none());
+    }
+
+    #[test]
+    fn test_spin_castep_output() {
+        let output = CastepOutputParser::new(FE_CASTEP)
+            .parse_castep_output()
+            .unwrap();
+        assert_eq!(output.ion_count("Fe"), Some(2));
+        assert_eq!(output.fermi_energy, Some(FermiEnergy::Polarized(6.5, 6.4)));
+        assert_eq!(output.total_spin.unwrap().integrated, 4.44);
+        let mulliken = output.mulliken.unwrap();
+        assert_eq!(mulliken.len(), 2);
+        assert_eq!(mulliken[1].ion, 2);
+        assert_eq!(mulliken[0].total, 9.0);
+        assert_eq!(mulliken[0].spin, Some(2.22));
+    }
+
+    #[test]
+    fn test_fermi_energy_of_last_run() {
+        // A spin-polarized run followed by a non-polarized restart
+        for later_run in [
+            "Fermi energy for spin  up electrons  =   4.2101 eV",
+            "Fermi energy  =   4.2101 eV",
+        ] {
+            let content = format!("{FE_CASTEP}\n{later_run}\n");
+            let output = CastepOutputParser::new(&content)
+                .parse_castep_output()
+                .unwrap();
+            assert_eq!(output.fermi_energy, Some(FermiEnergy::NonPolarized(4.2101)));
+        }
+        // And the other way round
+        let content = format!("{CAO_CASTEP}\n{FE_CASTEP}");
+        let output = CastepOutputParser::new(&content)
+            .parse_castep_output()
+            .unwrap();
+        assert_eq!(output.fermi_energy, Some(FermiEnergy::Polarized(6.5, 6.4)));
+    }
+}
@@ -0,0 +1,84 @@
+#![warn(missing_docs)]
+#![allow(dead_code)]
+//! Crate to parse `.cell` for the lattice and the species ranks of ions.
+
+use derive_builder::Builder;
+mod elements;
+mod parser;
+
+pub use parser::{CellParser, CellParsingError};
+
+use crate::projectors::Mapping;
+
+/// Position of an ion as given in `.cell`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IonPosition {
+    /// From `%BLOCK POSITIONS_FRAC`, fractional coordinates
+    Fractional([f64; 3]),
+    /// From `%BLOCK POSITIONS_ABS`, cartesian coordinates in Angstroms
+    Cartesian([f64; 3]),
+}
+
+/// Ion entry of the positions block
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ion {
+    /// Species label, e.g. `Mo` or `Fe:1`
+    pub species: String,
+    /// Position of the ion
+    pub position: IonPosition,
+}
+
+impl Ion {
+    /// Constructor
+    pub fn new(species: String, position: IonPosition) -> Self {
+        Self { species, position }
+    }
+}
+
+/// Unit cell description from `.cell`
+#[derive(Debug, Clone, PartialEq, Builder)]
+#[builder()]
+pub struct CellFile {
+    /// Lattice vectors in Angstroms (row-major)
+    pub lattice_vectors: [[f64; 3]; 3],
+    /// Ions in the order of the positions block
+    pub ions: Vec<Ion>,
+}
+
+impl CellFile {
+    /// Species labels with their ranks, in the same way `CASTEP` assigns
+    /// `orbital_species` in `.pdos_weights`:
+    /// species are ranked by atomic number, starting from 1. Species sharing
+    /// the same element (e.g. `Fe:1` and `Fe:2`) keep their order of first appearance.
+    pub fn species_ranks(&self) -> Vec<(&str, u32)> {
+        let mut species: Vec<&str> = Vec::new();
+        self.ions.iter().for_each(|ion| {
+            if !species.contains(&ion.species.as_str()) {
+                species.push(&ion.species);
+            }
+        });
+        // stable sort, ties stay in the order of appearance
+        species.sort_by_key(|label| elements::atomic_number(label).unwrap_or(u32::MAX));
+        species
+            .into_iter()
+            .enumerate()
+            .map(|(i, label)| (label, i as u32 + 1))
+            .collect()
+    }
+
+    /// Species mapping for `PDOSConfig`
+    pub fn species_mapping(&self) -> Vec<Mapping> {
+        self.species_ranks()
+            .into_iter()
+            .map(|(species, rank)| Mapping::new(species, rank))
+            .collect()
+    }
+
+    /// Number of ions of the species
+    pub fn ion_count(&self, species: &str) -> usize {
+        self.ions
+            .iter()
+            .filter(|ion| ion.species == species)
+            .count()
+    }
+}
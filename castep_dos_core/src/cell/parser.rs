@@ -0,0 +1,300 @@
+use thiserror::Error;
+use winnow::{
+    ModalResult, Parser,
+    ascii::{float, space0, space1},
+    combinator::{preceded, repeat, terminated},
+    error::StrContext,
+    token::take_till,
+};
+
+use crate::{
+    cell::{CellFile, CellFileBuilder, CellFileBuilderError, Ion, IonPosition},
+    fundamental::BOHR_TO_ANGSTROM,
+};
+
+#[derive(Debug, Error)]
+/// Possible errors in parsing `.cell`
+pub enum CellParsingError {
+    #[error("Neither `{0}` nor `{1}` is found in `.cell`")]
+    /// Both alternative blocks are missing
+    MissingBlock(&'static str, &'static str),
+    #[error("Invalid line in block `{block}`: `{line}`")]
+    /// Line in block can not be parsed
+    InvalidLine {
+        /// Name of block
+        block: String,
+        /// Content of the line
+        line: String,
+    },
+    #[error("Unknown length unit `{0}`")]
+    /// Unsupported unit line at the start of a block
+    UnknownUnit(String),
+    #[error("Block `{0}` is not closed by `%ENDBLOCK`")]
+    /// Missing `%ENDBLOCK`
+    UnclosedBlock(String),
+    #[error("Builder error: {0}")]
+    /// Error from builder
+    BuilderError(#[from] CellFileBuilderError),
+}
+
+/// A `%BLOCK` in `.cell`, lines are stripped of comments
+#[derive(Debug, Clone)]
+struct Block<'a> {
+    name: String,
+    lines: Vec<&'a str>,
+}
+
+/// Parser of `.cell`, holds the slice of file content.
+#[derive(Debug, Clone)]
+pub struct CellParser<'a> {
+    input: &'a str,
+}
+
+impl<'a> CellParser<'a> {
+    /// Constructor
+    pub fn new(input: &'a str) -> Self {
+        Self { input }
+    }
+
+    /// Collect all `%BLOCK ... %ENDBLOCK` sections, keywords are case-insensitive
+    fn blocks(&self) -> Result<Vec<Block<'a>>, CellParsingError> {
+        let mut blocks = Vec::new();
+        let mut current: Option<Block<'a>> = None;
+        for line in self
+            .input
+            .lines()
+            .map(strip_comment)
+            .filter(|l| !l.is_empty())
+        {
+            let mut words = line.split_whitespace();
+            let keyword = words.next().unwrap_or_default().to_lowercase();
+            match (keyword.as_str(), current.as_mut()) {
+                ("%block", None) => {
+                    current = Some(Block {
+                        name: words.next().unwrap_or_default().to_lowercase(),
+                        lines: Vec::new(),
+                    })
+                }
+                ("%endblock", Some(_)) => blocks.extend(current.take()),
+                (_, Some(block)) => block.lines.push(line),
+                // Keywords outside blocks are not needed
+                (_, None) => (),
+            }
+        }
+        match current {
+            Some(block) => Err(CellParsingError::UnclosedBlock(block.name)),
+            None => Ok(blocks),
+        }
+    }
+
+    /// Main usage
+    pub fn parse_cell_file(self) -> Result<CellFile, CellParsingError> {
+        let blocks = self.blocks()?;
+        let find = |name: &str| blocks.iter().find(|block| block.name == name);
+        let lattice_vectors = match (find("lattice_cart"), find("lattice_abc")) {
+            (Some(block), _) => parse_lattice_cart(block)?,
+            (None, Some(block)) => parse_lattice_abc(block)?,
+            (None, None) => {
+                return Err(CellParsingError::MissingBlock(
+                    "LATTICE_CART",
+                    "LATTICE_ABC",
+                ));
+            }
+        };
+        let ions = match (find("positions_frac"), find("positions_abs")) {
+            (Some(block), _) => parse_positions(block, IonPosition::Fractional, 1.0)?,
+            (None, Some(block)) => {
+                let (factor, lines) = block_unit(block)?;
+                parse_positions(
+                    &Block {
+                        name: block.name.clone(),
+                        lines: lines.to_vec(),
+                    },
+                    IonPosition::Cartesian,
+                    factor,
+                )?
+            }
+            (None, None) => {
+                return Err(CellParsingError::MissingBlock(
+                    "POSITIONS_FRAC",
+                    "POSITIONS_ABS",
+                ));
+            }
+        };
+        Ok(CellFileBuilder::default()
+            .lattice_vectors(lattice_vectors)
+            .ions(ions)
+            .build()?)
+    }
+}
+
+/// `CASTEP` accepts `!`, `#` and `;` for comments
+fn strip_comment(line: &str) -> &str {
+    line.split(['!', '#', ';'])
+        .next()
+        .unwrap_or_default()
+        .trim()
+}
+
+/// Blocks with cartesian values may start with a line of length unit.
+/// Returns the factor to convert to Angstroms and the remaining lines.
+fn block_unit<'b, 'a>(block: &'b Block<'a>) -> Result<(f64, &'b [&'a str]), CellParsingError> {
+    let Some(first) = block.lines.first() else {
+        return Ok((1.0, &block.lines));
+    };
+    if first.split_whitespace().count() != 1 {
+        return Ok((1.0, &block.lines));
+    }
+    let factor = match first.to_lowercase().as_str() {
+        "ang" => 1.0,
+        "bohr" | "a0" => BOHR_TO_ANGSTROM,
+        "nm" => 10.0,
+        "m" => 1.0e10,
+        "cm" => 1.0e8,
+        unit => return Err(CellParsingError::UnknownUnit(unit.to_string())),
+    };
+    Ok((factor, &block.lines[1..]))
+}
+
+fn three_floats(input: &mut &str) -> ModalResult<[f64; 3]> {
+    repeat(3, preceded(space0, float::<_, f64, _>))
+        .verify_map(|v: Vec<f64>| v.try_into().ok())
+        .context(StrContext::Label("three floats"))
+        .parse_next(input)
+}
+
+fn invalid_line(block: &Block, line: &str) -> CellParsingError {
+    CellParsingError::InvalidLine {
+        block: block.name.to_uppercase(),
+        line: line.to_string(),
+    }
+}
+
+fn parse_lattice_cart(block: &Block) -> Result<[[f64; 3]; 3], CellParsingError> {
+    let (factor, lines) = block_unit(block)?;
+    let vectors = lines
+        .iter()
+        .map(|line| {
+            terminated(three_floats, space0)
+                .parse(line)
+                .map(|v| v.map(|x| x * factor))
+                .map_err(|_| invalid_line(block, line))
+        })
+        .collect::<Result<Vec<[f64; 3]>, CellParsingError>>()?;
+    vectors
+        .try_into()
+        .map_err(|_| invalid_line(block, &lines.join("\n")))
+}
+
+/// `a`, `b`, `c` in the first line and `alpha`, `beta`, `gamma` in degrees
+/// in the second line. The `a` vector lies along `x` and `b` in the `xy` plane.
+fn parse_lattice_abc(block: &Block) -> Result<[[f64; 3]; 3], CellParsingError> {
+    let (factor, lines) = block_unit(block)?;
+    let [lengths, angles] = lines
+        .iter()
+        .map(|line| {
+            terminated(three_floats, space0)
+                .parse(line)
+                .map_err(|_| invalid_line(block, line))
+        })
+        .collect::<Result<Vec<[f64; 3]>, CellParsingError>>()?
+        .try_into()
+        .map_err(|_| invalid_line(block, &lines.join("\n")))?;
+    let [a, b, c] = lengths.map(|x| x * factor);
+    let [alpha, beta, gamma] = angles.map(f64::to_radians);
+    let cx = c * beta.cos();
+    let cy = c * (alpha.cos() - beta.cos() * gamma.cos()) / gamma.sin();
+    let cz = (c * c - cx * cx - cy * cy).sqrt();
+    Ok([
+        [a, 0.0, 0.0],
+        [b * gamma.cos(), b * gamma.sin(), 0.0],
+        [cx, cy, cz],
+    ])
+}
+
+/// Each line: species label followed by three coordinates.
+/// Trailing per-ion keywords such as `SPIN=1.0` are ignored.
+fn parse_positions(
+    block: &Block,
+    position: fn([f64; 3]) -> IonPosition,
+    factor: f64,
+) -> Result<Vec<Ion>, CellParsingError> {
+    block
+        .lines
+        .iter()
+        .map(|line| {
+            (
+                take_till(1.., |c: char| c.is_whitespace()),
+                preceded(space1, three_floats),
+            )
+                .map(|(species, coords): (&str, [f64; 3])| {
+                    Ion::new(species.to_string(), position(coords.map(|x| x * factor)))
+                })
+                .parse_next(&mut &line[..])
+                .map_err(|_| invalid_line(block, line))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::CellParser;
+    use crate::{cell::IonPosition, fundamental::BOHR_TO_ANGSTROM};
+
+    const MOS2_CELL: &str = r#"%BLOCK LATTICE_CART
+   3.160000000000000   0.000000000000000   0.000000000000000
+  -1.580000000000000   2.736640276691348   0.000000000000000
+   0.000000000000000   0.000000000000000  12.290000000000001
+%ENDBLOCK LATTICE_CART
+
+%BLOCK POSITIONS_FRAC
+   Mo   0.333333333333333   0.666666666666667   0.250000000000000
+   Mo   0.666666666666667   0.333333333333333   0.750000000000000
+   S    0.333333333333333   0.666666666666667   0.621000000000000
+   S    0.666666666666667   0.333333333333333   0.121000000000000 SPIN=0.0
+   S    0.666666666666667   0.333333333333333   0.379000000000000
+   S    0.333333333333333   0.666666666666667   0.879000000000000
+%ENDBLOCK POSITIONS_FRAC
+
+! comment
+kpoints_mp_grid 6 6 1
+"#;
+
+    const LABELLED_CELL: &str = r#"%block lattice_abc
+ang
+ 2.87 2.87 2.87
+ 90 90 90
+%endblock lattice_abc
+%block positions_abs
+bohr
+Fe:2 0.0 0.0 0.0
+O    1.0 1.0 1.0 # comment
+Fe:1 2.0 2.0 2.0
+%endblock positions_abs
+"#;
+
+    #[test]
+    fn test_cell_parser() {
+        let cell = CellParser::new(MOS2_CELL).parse_cell_file().unwrap();
+        assert_eq!(cell.ions.len(), 6);
+        assert_eq!(cell.ion_count("S"), 4);
+        assert_eq!(cell.lattice_vectors[2][2], 12.290000000000001);
+        // S (Z=16) ranks before Mo (Z=42)
+        assert_eq!(cell.species_ranks(), vec![("S", 1), ("Mo", 2)]);
+    }
+
+    #[test]
+    fn test_labelled_species() {
+        let cell = CellParser::new(LABELLED_CELL).parse_cell_file().unwrap();
+        assert_eq!(
+            cell.species_ranks(),
+            vec![("O", 1), ("Fe:2", 2), ("Fe:1", 3)]
+        );
+        assert!((cell.lattice_vectors[1][1] - 2.87).abs() < 1e-12);
+        assert!(cell.lattice_vectors[1][0].abs() < 1e-12);
+        let IonPosition::Cartesian(coords) = cell.ions[2].position else {
+            panic!("Expected cartesian position")
+        };
+        assert!((coords[0] - 2.0 * BOHR_TO_ANGSTROM).abs() < 1e-12);
+    }
+}
@@ -50,6 +50,17 @@ impl From<AngularMomentum> for u32 {
     }
 }
 
+impl std::fmt::Display for AngularMomentum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AngularMomentum::S => write!(f, "s"),
+            AngularMomentum::P => write!(f, "p"),
+            AngularMomentum::D => write!(f, "d"),
+            AngularMomentum::F => write!(f, "f"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Default, PartialEq)]
 /// Struct to collect orbital weights for each angular momentum
 /// channels
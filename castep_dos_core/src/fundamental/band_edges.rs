@@ -0,0 +1,214 @@
+use std::fmt::Display;
+
+use super::{
+    BandStructure, EigenvalueVec, HATREE_TO_EV, KpointCoords, KpointVec, SpinData, SpinLayoutError,
+};
+
+/// Electron counts within this distance of an integer fill whole bands
+const OCCUPATION_TOLERANCE: f64 = 1e-6;
+/// Gaps closer than this in eV are the same, for degenerate band extrema
+const DIRECT_GAP_TOLERANCE: f64 = 1e-6;
+
+/// Extremum of a band
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BandEdge {
+    /// Energy in eV, not shifted by the Fermi energy
+    pub energy: f64,
+    /// Band index, starts from 1
+    pub band: usize,
+    /// K-point where the extremum is
+    pub kpoint: KpointCoords,
+}
+
+/// Valence band maximum, conduction band minimum and gaps of one spin channel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BandEdges {
+    /// Valence band maximum
+    pub vbm: BandEdge,
+    /// Conduction band minimum
+    pub cbm: BandEdge,
+    /// Smallest gap in eV between the two bands at the same k-point
+    pub direct_gap: f64,
+    /// K-point of the smallest direct gap
+    pub direct_gap_kpoint: KpointCoords,
+}
+
+impl BandEdges {
+    /// Fundamental gap in eV
+    pub fn gap(&self) -> f64 {
+        self.cbm.energy - self.vbm.energy
+    }
+
+    /// The fundamental gap is direct. Degenerate extrema at several
+    /// k-points count as direct when one pair of them is.
+    pub fn is_direct(&self) -> bool {
+        self.direct_gap - self.gap() < DIRECT_GAP_TOLERANCE
+    }
+}
+
+impl BandStructure {
+    /// Band edges of each spin channel, `None` for metallic channels.
+    /// The occupied bands are counted from the electron count; when it does
+    /// not fill whole bands, the bands below the Fermi energy at every
+    /// k-point are taken, and a band crossing the Fermi energy makes the
+    /// channel metallic.
+    /// Fails when the Fermi energy or electron count is not in the spin
+    /// layout of the eigenvalues.
+    pub fn band_edges(&self) -> Result<SpinData<Option<BandEdges>>, SpinLayoutError> {
+        let edges = |kpts: &KpointVec<EigenvalueVec<f64>>, fermi: f64, filled: f64| {
+            occupied_bands(kpts, fermi, filled)
+                .and_then(|occupied| find_edges(kpts, &self.kpoints, occupied))
+        };
+        let channels = self
+            .eigenvalues
+            .zip(&self.fermi_energy)
+            .ok_or(SpinLayoutError("eigenvalues", "fermi_energy"))?;
+        let channels = channels
+            .zip(&self.electron_count)
+            .ok_or(SpinLayoutError("eigenvalues", "electron_count"))?;
+        Ok(match channels {
+            SpinData::NonPolarized(((kpts, fermi), count)) => {
+                SpinData::NonPolarized(edges(kpts, **fermi, count / 2.0))
+            }
+            SpinData::SpinPolarized(spins) => SpinData::SpinPolarized(
+                spins.map(|((kpts, fermi), count)| edges(kpts, **fermi, *count)),
+            ),
+        })
+    }
+}
+
+/// Number of fully occupied bands
+fn occupied_bands(kpts: &KpointVec<EigenvalueVec<f64>>, fermi: f64, filled: f64) -> Option<usize> {
+    if (filled - filled.round()).abs() < OCCUPATION_TOLERANCE {
+        return Some(filled.round() as usize);
+    }
+    let mut below = kpts
+        .iter()
+        .map(|eigens| eigens.iter().filter(|&&e| e <= fermi).count());
+    let first = below.next()?;
+    below.all(|count| count == first).then_some(first)
+}
+
+fn find_edges(
+    kpts: &KpointVec<EigenvalueVec<f64>>,
+    kpoints: &KpointVec<KpointCoords>,
+    occupied: usize,
+) -> Option<BandEdges> {
+    let nbands = kpts.iter().map(|eigens| eigens.len()).min()?;
+    if occupied == 0 || occupied >= nbands {
+        return None;
+    }
+    let (valence, conduction) = (occupied - 1, occupied);
+    let (vbm_k, vbm) = kpts
+        .iter()
+        .map(|eigens| eigens[valence])
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+    let (cbm_k, cbm) = kpts
+        .iter()
+        .map(|eigens| eigens[conduction])
+        .enumerate()
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))?;
+    // Overlapping bands: semimetal
+    if cbm <= vbm {
+        return None;
+    }
+    let (direct_k, direct_gap) = kpts
+        .iter()
+        .map(|eigens| eigens[conduction] - eigens[valence])
+        .enumerate()
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))?;
+    Some(BandEdges {
+        vbm: BandEdge {
+            energy: vbm * HATREE_TO_EV,
+            band: valence + 1,
+            kpoint: kpoints[vbm_k],
+        },
+        cbm: BandEdge {
+            energy: cbm * HATREE_TO_EV,
+            band: conduction + 1,
+            kpoint: kpoints[cbm_k],
+        },
+        direct_gap: direct_gap * HATREE_TO_EV,
+        direct_gap_kpoint: kpoints[direct_k],
+    })
+}
+
+impl Display for BandEdge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [k1, k2, k3] = self.kpoint.coords;
+        write!(
+            f,
+            "{:.4} eV, band {} at k-point {} ({k1:.6}, {k2:.6}, {k3:.6})",
+            self.energy, self.band, self.kpoint.index
+        )
+    }
+}
+
+impl Display for BandEdges {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = if self.is_direct() {
+            "direct"
+        } else {
+            "indirect"
+        };
+        let [k1, k2, k3] = self.direct_gap_kpoint.coords;
+        writeln!(f, "VBM: {}", self.vbm)?;
+        writeln!(f, "CBM: {}", self.cbm)?;
+        writeln!(f, "Gap: {:.4} eV ({kind})", self.gap())?;
+        writeln!(
+            f,
+            "Smallest direct gap: {:.4} eV at k-point {} ({k1:.6}, {k2:.6}, {k3:.6})",
+            self.direct_gap, self.direct_gap_kpoint.index
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        bands::{ElectronCount, FermiEnergy},
+        fundamental::{HATREE_TO_EV, SpinData, SpinLayoutError},
+        test_fixtures::sample_bands_file,
+    };
+
+    #[test]
+    fn test_band_edges() {
+        // Bands -0.5, -0.25, 0.0625, 0.3125 Ha, raised by 2^-6 Ha per k-point
+        let Ok(SpinData::NonPolarized(Some(edges))) =
+            sample_bands_file(1).to_band_structure().band_edges()
+        else {
+            panic!("insulator expected");
+        };
+        assert_eq!((edges.vbm.band, edges.vbm.kpoint.index), (2, 3));
+        assert_eq!((edges.cbm.band, edges.cbm.kpoint.index), (3, 1));
+        assert!((edges.gap() - 0.28125 * HATREE_TO_EV).abs() < 1e-9);
+        assert!((edges.direct_gap - 0.3125 * HATREE_TO_EV).abs() < 1e-9);
+        assert!(!edges.is_direct());
+        assert!(edges.to_string().contains("(indirect)"));
+
+        let Ok(SpinData::SpinPolarized([Some(up), Some(down)])) =
+            sample_bands_file(2).to_band_structure().band_edges()
+        else {
+            panic!("insulator expected in both spins");
+        };
+        assert!((down.vbm.energy - up.vbm.energy - 0.0078125 * HATREE_TO_EV).abs() < 1e-9);
+
+        // 2.5 filled bands: the third band crosses the Fermi energy
+        let mut metal = sample_bands_file(1);
+        metal.electron_count = ElectronCount::NonPolarized(5.0);
+        metal.fermi_energy = FermiEnergy::NonPolarized(0.07);
+        assert_eq!(
+            metal.to_band_structure().band_edges(),
+            Ok(SpinData::NonPolarized(None))
+        );
+
+        // One electron count for two spin channels
+        let mut malformed = sample_bands_file(2);
+        malformed.electron_count = ElectronCount::NonPolarized(4.0);
+        assert_eq!(
+            malformed.to_band_structure().band_edges(),
+            Err(SpinLayoutError("eigenvalues", "electron_count"))
+        );
+    }
+}
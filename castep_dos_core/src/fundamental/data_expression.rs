@@ -1,4 +1,4 @@
-use super::AngularMomentum;
+use super::{AngularMomentum, OrbitalLabel};
 
 /// ---------------------------------------
 /// Represent every `T` for a spin is data belongs to a k-point
@@ -16,11 +16,29 @@ pub struct OrbitalWeight(pub f64);
 
 /// Wrapper newtype of k-point weight (`f64`)
 /// This is the only thing we need to calculate dos,
-/// throw away the k-point index and coordinate
-/// information from `.bands`
+/// the k-point index and coordinate are kept in
+/// `KpointCoords` to match `.bands` and `.pdos_weights`
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub struct KpointWeight(pub f64);
 
+/// Global index and fractional coordinates of a k-point.
+/// `CASTEP` runs in parallel write k-points out of order,
+/// the index is the key to pair eigenvalues and weights.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KpointCoords {
+    /// Global index in k-point mesh, starts from 1
+    pub index: u32,
+    /// Fractional coordinates in reciprocal space
+    pub coords: [f64; 3],
+}
+
+impl KpointCoords {
+    /// Constructor
+    pub fn new(index: u32, coords: [f64; 3]) -> Self {
+        Self { index, coords }
+    }
+}
+
 /// Unified spin polarization handling
 /// Whether the spin is polarized or not, the data we have
 /// always carry a dimension of spin. Since k-point is always
@@ -43,15 +61,29 @@ pub struct OrbitalState {
     pub ion_id: u32,
     /// Angular momentum
     pub angular_momentum: AngularMomentum,
+    /// Column of the orbital within the 2l+1 columns of its
+    /// (species, ion, l), see `OrbitalLabel::from_m_index`
+    pub m_index: u32,
 }
 
 impl OrbitalState {
-    /// Constructor
+    /// Constructor, of the first column of the (species, ion, l)
     pub fn new(species_id: u32, ion_id: u32, angular_momentum: AngularMomentum) -> Self {
         Self {
             species_id,
             ion_id,
             angular_momentum,
+            m_index: 0,
         }
     }
+
+    /// Set the column within the (species, ion, l)
+    pub fn with_m_index(self, m_index: u32) -> Self {
+        Self { m_index, ..self }
+    }
+
+    /// The sub-orbital of this column
+    pub fn orbital_label(&self) -> Option<OrbitalLabel> {
+        OrbitalLabel::from_m_index(self.angular_momentum, self.m_index)
+    }
 }
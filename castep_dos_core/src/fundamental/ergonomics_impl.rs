@@ -225,6 +225,20 @@ impl<T> SpinData<T> {
         }
     }
 
+    /// Pair the data of each spin with the data of the same spin in `rhs`.
+    /// Returns `None` when only one of them is spin polarized.
+    pub fn zip<'a, U>(&'a self, rhs: &'a SpinData<U>) -> Option<SpinData<(&'a T, &'a U)>> {
+        match (self, rhs) {
+            (SpinData::NonPolarized(data), SpinData::NonPolarized(rhs_data)) => {
+                Some(SpinData::NonPolarized((data, rhs_data)))
+            }
+            (SpinData::SpinPolarized([up, down]), SpinData::SpinPolarized([rhs_up, rhs_down])) => {
+                Some(SpinData::SpinPolarized([(up, rhs_up), (down, rhs_down)]))
+            }
+            _ => None,
+        }
+    }
+
     /// Map data on two `SpinData` with same spin polarization settings with a function
     /// act on the two different data types and produce one new `SpinData`
     pub fn map_pair<U, V, F: Fn(&T, &U) -> V>(&self, rhs: &SpinData<U>, f: F) -> SpinData<V> {
@@ -0,0 +1,231 @@
+use std::collections::HashSet;
+
+use thiserror::Error;
+
+use super::{BandStructure, KpointCoords, KpointVec, PDOSWeights, SpinData};
+
+/// Tolerance of fractional coordinates, `.bands` only keeps 8 decimals
+const COORDS_TOLERANCE: f64 = 1e-6;
+
+#[derive(Debug, Error, PartialEq)]
+/// Inconsistent k-points between `.bands` and `.pdos_weights`
+pub enum KpointMismatchError {
+    /// Different number of k-points
+    #[error("`.bands` has {bands} k-points but `.pdos_weights` has {pdos_weights}")]
+    Count {
+        /// Number of k-points in `.bands`
+        bands: usize,
+        /// Number of k-points in `.pdos_weights`
+        pdos_weights: usize,
+    },
+    /// The same global index appears more than once in one file
+    #[error("K-point index {index} appears more than once in `{file}`")]
+    DuplicateIndex {
+        /// File extension
+        file: &'static str,
+        /// Duplicated index
+        index: u32,
+    },
+    /// After sorting, the indices of the two files differ
+    #[error("K-point index {bands} in `.bands` does not match {pdos_weights} in `.pdos_weights`")]
+    Index {
+        /// Index in `.bands`
+        bands: u32,
+        /// Index in `.pdos_weights`
+        pdos_weights: u32,
+    },
+    /// Same index but different coordinates
+    #[error(
+        "K-point {index} is at {bands:?} in `.bands` but at {pdos_weights:?} in `.pdos_weights`"
+    )]
+    Coords {
+        /// Global k-point index
+        index: u32,
+        /// Coordinates in `.bands`
+        bands: [f64; 3],
+        /// Coordinates in `.pdos_weights`
+        pdos_weights: [f64; 3],
+    },
+}
+
+impl BandStructure {
+    /// Sort k-points, weights and eigenvalues by global k-point index
+    pub fn sort_kpoints(&mut self) {
+        let order = sorting_order(&self.kpoints);
+        reorder(&mut self.kpoints, &order);
+        reorder(&mut self.kpoint_weights, &order);
+        match &mut self.eigenvalues {
+            SpinData::NonPolarized(kpts) => reorder(kpts, &order),
+            SpinData::SpinPolarized([up, down]) => {
+                reorder(up, &order);
+                reorder(down, &order);
+            }
+        }
+    }
+}
+
+impl PDOSWeights {
+    /// Sort k-points and orbital weights by global k-point index
+    pub fn sort_kpoints(&mut self) {
+        let order = sorting_order(&self.kpoints);
+        reorder(&mut self.kpoints, &order);
+        self.orbital_weights.reorder_kpoints(&order);
+    }
+}
+
+/// Sort both data sets by global k-point index and check that each
+/// pair of k-points has the same index and coordinates, so
+/// `calculate_pdos` can zip them by position.
+pub fn align_kpoints(
+    band_structure: &mut BandStructure,
+    pdos_weights: &mut PDOSWeights,
+) -> Result<(), KpointMismatchError> {
+    check_unique(&band_structure.kpoints, ".bands")?;
+    check_unique(&pdos_weights.kpoints, ".pdos_weights")?;
+    if band_structure.kpoints.len() != pdos_weights.kpoints.len() {
+        return Err(KpointMismatchError::Count {
+            bands: band_structure.kpoints.len(),
+            pdos_weights: pdos_weights.kpoints.len(),
+        });
+    }
+    band_structure.sort_kpoints();
+    pdos_weights.sort_kpoints();
+    band_structure
+        .kpoints
+        .iter()
+        .zip(pdos_weights.kpoints.iter())
+        .try_for_each(|(bands_kpt, weights_kpt)| {
+            if bands_kpt.index != weights_kpt.index {
+                return Err(KpointMismatchError::Index {
+                    bands: bands_kpt.index,
+                    pdos_weights: weights_kpt.index,
+                });
+            }
+            let same_coords = bands_kpt
+                .coords
+                .iter()
+                .zip(weights_kpt.coords.iter())
+                .all(|(a, b)| (a - b).abs() < COORDS_TOLERANCE);
+            if !same_coords {
+                return Err(KpointMismatchError::Coords {
+                    index: bands_kpt.index,
+                    bands: bands_kpt.coords,
+                    pdos_weights: weights_kpt.coords,
+                });
+            }
+            Ok(())
+        })
+}
+
+fn check_unique(
+    kpoints: &KpointVec<KpointCoords>,
+    file: &'static str,
+) -> Result<(), KpointMismatchError> {
+    let mut seen = HashSet::new();
+    kpoints.iter().try_for_each(|kpt| {
+        if seen.insert(kpt.index) {
+            Ok(())
+        } else {
+            Err(KpointMismatchError::DuplicateIndex {
+                file,
+                index: kpt.index,
+            })
+        }
+    })
+}
+
+/// Positions of the k-points in ascending order of index
+fn sorting_order(kpoints: &KpointVec<KpointCoords>) -> Vec<usize> {
+    let mut order = (0..kpoints.len()).collect::<Vec<usize>>();
+    order.sort_by_key(|&i| kpoints[i].index);
+    order
+}
+
+/// Move the data of k-points to the new order without cloning
+fn reorder<T>(data: &mut KpointVec<T>, order: &[usize]) {
+    let mut slots = std::mem::take(&mut data.0)
+        .into_iter()
+        .map(Some)
+        .collect::<Vec<Option<T>>>();
+    data.0 = order
+        .iter()
+        .map(|&i| slots[i].take().expect("`order` is a permutation"))
+        .collect();
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        bands::Eigenvalues,
+        fundamental::{KpointMismatchError, align_kpoints},
+        test_fixtures::{sample_bands_file, sample_pdos_weights_file},
+    };
+
+    #[test]
+    fn test_align_kpoints() {
+        [1, 2].into_iter().for_each(|num_spins| {
+            let expected_bands = sample_bands_file(num_spins).to_band_structure();
+            let expected_weights = sample_pdos_weights_file(num_spins).to_pdos_weights();
+            // Shuffle the k-points as a parallel run writes them
+            let mut shuffled_bands = sample_bands_file(num_spins);
+            shuffled_bands.kpoints.rotate_left(1);
+            match &mut shuffled_bands.eigenvalues {
+                Eigenvalues::NonPolarized(kpts) => kpts.rotate_left(1),
+                Eigenvalues::SpinPolarized([up, down]) => {
+                    up.rotate_left(1);
+                    down.rotate_left(1);
+                }
+            }
+            let mut shuffled_weights = sample_pdos_weights_file(num_spins);
+            shuffled_weights.kpoints.reverse();
+            let mut bands = shuffled_bands.to_band_structure();
+            let mut weights = shuffled_weights.to_pdos_weights();
+            assert_ne!(bands, expected_bands);
+            align_kpoints(&mut bands, &mut weights).unwrap();
+            assert_eq!(bands, expected_bands);
+            assert_eq!(weights, expected_weights);
+        });
+    }
+
+    #[test]
+    fn test_kpoint_mismatch() {
+        let mut bands_file = sample_bands_file(1);
+        bands_file.kpoints[1].coords = [0.0, 0.25, 0.0];
+        let result = align_kpoints(
+            &mut bands_file.to_band_structure(),
+            &mut sample_pdos_weights_file(1).to_pdos_weights(),
+        );
+        assert!(matches!(
+            result,
+            Err(KpointMismatchError::Coords { index: 2, .. })
+        ));
+
+        let mut weights_file = sample_pdos_weights_file(1);
+        weights_file.kpoints[2].index = 1;
+        let result = align_kpoints(
+            &mut sample_bands_file(1).to_band_structure(),
+            &mut weights_file.to_pdos_weights(),
+        );
+        assert_eq!(
+            result,
+            Err(KpointMismatchError::DuplicateIndex {
+                file: ".pdos_weights",
+                index: 1
+            })
+        );
+
+        let mut weights_file = sample_pdos_weights_file(1);
+        weights_file.kpoints.pop();
+        let result = align_kpoints(
+            &mut sample_bands_file(1).to_band_structure(),
+            &mut weights_file.to_pdos_weights(),
+        );
+        assert_eq!(
+            result,
+            Err(KpointMismatchError::Count {
+                bands: 3,
+                pdos_weights: 2
+            })
+        );
+    }
+}
@@ -1,5 +1,7 @@
 /// Implementation details of `AngularMomentum`
 mod angular_momentum;
+/// Valence and conduction band edges and gaps
+mod band_edges;
 /// Data structs and enums related to band structure
 /// Create wrapper for `Vec<T>`
 /// So we can express the following nested data array:
@@ -11,20 +13,39 @@ mod data_expression;
 /// Traits implementations for custom data structs and enums
 mod ergonomics_impl;
 
+/// Sorting and matching k-points of `.bands` and `.pdos_weights`
+mod kpoints;
+
 mod pdos_file;
+/// Real spherical harmonic sub-orbitals and the column order of each l
+mod real_harmonics;
 /// Spin related structs and enums
 mod spins;
+/// Contiguous array of the orbital weights of every state
+mod weights_array;
 
-const HATREE_TO_EV: f64 = 27.211396641308;
+/// Hartree to eV, the energies in `.bands` are in Hartree
+pub const HATREE_TO_EV: f64 = 27.211396641308;
+/// Bohr to Angstrom, the lengths in `.bands` are in Bohr
+pub const BOHR_TO_ANGSTROM: f64 = 0.529177210903;
 
 pub use angular_momentum::{AngularChannels, AngularMomentum, AngularMomentumConvertError};
-pub use pdos_file::{Header, HeaderBuilder, HeaderBuilderError};
+pub use band_edges::{BandEdge, BandEdges};
+pub use kpoints::{KpointMismatchError, align_kpoints};
+pub use pdos_file::{Header, HeaderBuilder, HeaderBuilderError, PDOSBinHeader, PDOSWeightsFile};
 pub use pdos_file::{WeightsPerEigen, WeightsPerKPoint, WeightsPerSpin};
+pub use real_harmonics::OrbitalLabel;
 
 pub use data_expression::{
-    EigenvalueVec, KpointVec, KpointWeight, OrbitalState, OrbitalWeight, OrbitalWeightVec, SpinData,
+    EigenvalueVec, KpointCoords, KpointVec, KpointWeight, OrbitalState, OrbitalWeight,
+    OrbitalWeightVec, SpinData,
+};
+pub use weights_array::OrbitalWeights;
+
+pub use spins::{
+    NumSpins, NumSpinsConvertError, SpinIndex, SpinIndexConvertError, SpinLayoutError,
+    SpinPolarized,
 };
-pub use spins::{NumSpins, NumSpinsConvertError, SpinIndex, SpinIndexConvertError, SpinPolarized};
 
 #[derive(Debug, Clone, PartialEq)]
 /// Parsed `.pdos_weights` with necessary data
@@ -33,10 +54,12 @@ pub struct PDOSWeights {
     pub spin_polarized: SpinPolarized,
     /// Orbital metadatas
     pub orbital_states: Vec<OrbitalState>,
+    /// Index and coordinates of the k-points, in the same order of `orbital_weights`
+    pub kpoints: KpointVec<KpointCoords>,
     /// The orbital weights, organized in a 4D array:
     /// [spin][k-point][eigenvalue][orbital weight]
-    /// dim: 2   nkpt     n_eigen    n_orbs
-    pub orbital_weights: SpinData<KpointVec<EigenvalueVec<OrbitalWeightVec>>>,
+    /// dim: 1|2 nkpt     max_bands  n_orbs
+    pub orbital_weights: OrbitalWeights,
 }
 
 impl PDOSWeights {
@@ -44,11 +67,13 @@ impl PDOSWeights {
     pub fn new(
         spin_polarized: SpinPolarized,
         orbital_states: Vec<OrbitalState>,
-        orbital_weights: SpinData<KpointVec<EigenvalueVec<OrbitalWeightVec>>>,
+        kpoints: KpointVec<KpointCoords>,
+        orbital_weights: OrbitalWeights,
     ) -> Self {
         Self {
             spin_polarized,
             orbital_states,
+            kpoints,
             orbital_weights,
         }
     }
@@ -61,6 +86,12 @@ pub struct BandStructure {
     pub spin_polarized: SpinPolarized,
     /// Fermi energy
     pub fermi_energy: SpinData<f64>,
+    /// Number of electrons, of each spin when spin polarized
+    pub electron_count: SpinData<f64>,
+    /// Lattice vectors in Bohr (row-major), to build the reciprocal lattice
+    pub lattice_vectors: [[f64; 3]; 3],
+    /// Index and coordinates of the k-points
+    pub kpoints: KpointVec<KpointCoords>,
     /// K-point weights in the same order of the k-points
     pub kpoint_weights: KpointVec<KpointWeight>,
     /// Eigenvalues, organized in a 3D array
@@ -73,36 +104,45 @@ impl BandStructure {
     pub fn new(
         spin_polarized: SpinPolarized,
         fermi_energy: SpinData<f64>,
+        electron_count: SpinData<f64>,
+        lattice_vectors: [[f64; 3]; 3],
+        kpoints: KpointVec<KpointCoords>,
         kpoint_weights: KpointVec<KpointWeight>,
         eigenvalues: SpinData<KpointVec<EigenvalueVec<f64>>>,
     ) -> Self {
         Self {
             spin_polarized,
             fermi_energy,
+            electron_count,
+            lattice_vectors,
+            kpoints,
             kpoint_weights,
             eigenvalues,
         }
     }
 
-    /// Returns min and max eigenvalue energy in eV
+    /// Returns min and max eigenvalue energy in eV, relative to the Fermi
+    /// energy of each spin and padded by 10 `smearing` (eV) on both sides.
     /// For spin polarized bands, the min and max of two spins are compared
     /// and returns the smaller and larger one, respectively
     pub fn energy_range(&self, smearing: f64) -> (f64, f64) {
-        let spins_range = self.eigenvalues.map(|kpts| {
-            kpts.iter()
-                .map(|kpt_eigenvalues| {
-                    // CASTEP has already sorted the eigenvalues in ascending order
-                    let min = (kpt_eigenvalues.first().unwrap() - 10.0 * smearing) * HATREE_TO_EV;
-                    let max = (kpt_eigenvalues.last().unwrap() + 10.0 * smearing) * HATREE_TO_EV;
-                    (min, max)
-                })
-                .reduce(|(mut acc_min, mut acc_max), (curr_min, curr_max)| {
-                    acc_min = acc_min.min(curr_min);
-                    acc_max = acc_max.max(curr_max);
-                    (acc_min, acc_max)
-                })
-                .unwrap()
-        });
+        let spins_range = self
+            .eigenvalues
+            .map_pair(&self.fermi_energy, |kpts, fermi| {
+                kpts.iter()
+                    .map(|kpt_eigenvalues| {
+                        // CASTEP has already sorted the eigenvalues in ascending order
+                        let min = (kpt_eigenvalues.first().unwrap() - fermi) * HATREE_TO_EV;
+                        let max = (kpt_eigenvalues.last().unwrap() - fermi) * HATREE_TO_EV;
+                        (min - 10.0 * smearing, max + 10.0 * smearing)
+                    })
+                    .reduce(|(mut acc_min, mut acc_max), (curr_min, curr_max)| {
+                        acc_min = acc_min.min(curr_min);
+                        acc_max = acc_max.max(curr_max);
+                        (acc_min, acc_max)
+                    })
+                    .unwrap()
+            });
         match spins_range {
             SpinData::NonPolarized(e_range) => e_range,
             SpinData::SpinPolarized([(up_min, up_max), (down_min, down_max)]) => {
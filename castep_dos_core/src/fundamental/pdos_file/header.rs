@@ -1,8 +1,7 @@
 use crate::fundamental::{AngularMomentum, NumSpins, OrbitalState, SpinPolarized};
 use derive_builder::Builder;
-use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
 
-#[derive(Debug, Builder, Clone)]
+#[derive(Debug, Builder, Clone, PartialEq)]
 #[builder()]
 /// The header sections of the `.pdos_weight` file
 pub struct Header {
@@ -41,13 +40,31 @@ impl Header {
     /// for projector filtering, which requires comparing the species,
     /// ion id, and angular momentum together.
     /// Hence we group them into array of struct `OrbitalState`
+    /// The 2l+1 columns of each (species, ion, l) are consecutive, and
+    /// numbered by `OrbitalState::m_index` in order. Two shells of the same
+    /// l (e.g. semicore and valence s) follow each other with the count
+    /// starting over.
     pub fn extract_orbital_states(&self) -> Vec<OrbitalState> {
+        let mut previous: Option<OrbitalState> = None;
         self.orbital_species
-            .par_iter()
-            .zip(self.orbital_ion.par_iter())
-            .zip(self.orbital_am.par_iter())
+            .iter()
+            .zip(self.orbital_ion.iter())
+            .zip(self.orbital_am.iter())
             .map(|((species_id, ion_id), angular_momentum)| {
-                OrbitalState::new(*species_id, *ion_id, *angular_momentum)
+                let state = OrbitalState::new(*species_id, *ion_id, *angular_momentum);
+                let shell_size = 2 * u32::from(*angular_momentum) + 1;
+                let m_index = match previous {
+                    Some(prev)
+                        if (prev.species_id, prev.ion_id, prev.angular_momentum)
+                            == (state.species_id, state.ion_id, state.angular_momentum) =>
+                    {
+                        (prev.m_index + 1) % shell_size
+                    }
+                    _ => 0,
+                };
+                let state = state.with_m_index(m_index);
+                previous = Some(state);
+                state
             })
             .collect()
     }
@@ -1,4 +1,6 @@
 mod header;
 mod parsing_intermediates;
+mod weights_file;
 pub use header::{Header, HeaderBuilder, HeaderBuilderError};
 pub use parsing_intermediates::{WeightsPerEigen, WeightsPerKPoint, WeightsPerSpin};
+pub use weights_file::{PDOSBinHeader, PDOSWeightsFile};
@@ -1,6 +1,8 @@
-use crate::fundamental::SpinIndex;
+use ndarray::ArrayView1;
 
-#[derive(Debug, Clone)]
+use crate::fundamental::{Header, OrbitalWeights, SpinIndex};
+
+#[derive(Debug, Clone, PartialEq)]
 /// Data written for each k-point
 pub struct WeightsPerKPoint {
     /// global k-point index
@@ -20,9 +22,33 @@ impl WeightsPerKPoint {
             spins,
         }
     }
+
+    /// Weights of this k-point alone, with the bands and orbitals of
+    /// `header`, e.g. to project them by `OrbitalWeights::project` while
+    /// streaming with `PDOSWeightsReader`
+    pub fn orbital_weights(&self, header: &Header) -> OrbitalWeights {
+        let mut orbital_weights = OrbitalWeights::zeros(
+            self.spins.len(),
+            1,
+            header.max_bands as usize,
+            header.num_orbitals as usize,
+        );
+        self.spins
+            .iter()
+            .enumerate()
+            .for_each(|(spin, weights_per_spin)| {
+                let bands = &weights_per_spin.bands;
+                let mut view = orbital_weights.kpoint_mut(spin, 0, bands.len());
+                bands.iter().enumerate().for_each(|(band, weights)| {
+                    view.row_mut(band)
+                        .assign(&ArrayView1::from(weights.weights.as_slice()))
+                });
+            });
+        orbital_weights
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 /// Grouped data written by `CASTEP` for every
 /// spin components
 pub struct WeightsPerSpin {
@@ -45,7 +71,7 @@ impl WeightsPerSpin {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 /// PDOS weights for the band at each eigenvalues
 pub struct WeightsPerEigen {
     /// PDOS weight, flattened according to the projectors
@@ -0,0 +1,140 @@
+use ndarray::ArrayView1;
+
+use crate::fundamental::{
+    Header, KpointCoords, KpointVec, NumSpins, OrbitalWeights, PDOSWeights, SpinIndex,
+    WeightsPerEigen, WeightsPerKPoint, WeightsPerSpin,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+/// The two leading records of `.pdos_bin`, absent in `.pdos_weights`
+pub struct PDOSBinHeader {
+    /// `CASTEP` file format version
+    pub version: f64,
+    /// Raw bytes of the header record, a fixed-length string
+    /// with `CASTEP` version and generated date.
+    pub header: Vec<u8>,
+}
+
+impl PDOSBinHeader {
+    /// Constructor
+    pub fn new(version: f64, header: Vec<u8>) -> Self {
+        Self { version, header }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Every record of a `.pdos_weights` or `.pdos_bin`, kept as it is written
+/// by `CASTEP` so the file can be written back byte-identically.
+pub struct PDOSWeightsFile {
+    /// `Some` for `.pdos_bin`, `None` for `.pdos_weights`
+    pub bin_header: Option<PDOSBinHeader>,
+    /// Header section
+    pub header: Header,
+    /// Data of each k-point, in the order of the file
+    pub kpoints: Vec<WeightsPerKPoint>,
+}
+
+impl PDOSWeightsFile {
+    /// Constructor
+    pub fn new(
+        bin_header: Option<PDOSBinHeader>,
+        header: Header,
+        kpoints: Vec<WeightsPerKPoint>,
+    ) -> Self {
+        Self {
+            bin_header,
+            header,
+            kpoints,
+        }
+    }
+
+    /// Reorganize the records into `PDOSWeights` for PDOS calculation
+    pub fn to_pdos_weights(&self) -> PDOSWeights {
+        let spin_polarized = self.header.spin_polarized();
+        let orbital_states = self.header.extract_orbital_states();
+        let kpoints = self
+            .kpoints
+            .iter()
+            .map(|kpt| KpointCoords::new(kpt.index, kpt.kpoint))
+            .collect::<KpointVec<KpointCoords>>();
+        let mut orbital_weights = OrbitalWeights::zeros(
+            self.header.num_spins.spin_count(),
+            self.kpoints.len(),
+            self.header.max_bands as usize,
+            self.header.num_orbitals as usize,
+        );
+        self.kpoints
+            .iter()
+            .enumerate()
+            .for_each(|(k, weights_per_kpoint)| {
+                weights_per_kpoint
+                    .spins
+                    .iter()
+                    .enumerate()
+                    .for_each(|(spin, weights_per_spin)| {
+                        let bands = &weights_per_spin.bands;
+                        let mut view = orbital_weights.kpoint_mut(spin, k, bands.len());
+                        bands.iter().enumerate().for_each(|(band, weights)| {
+                            view.row_mut(band)
+                                .assign(&ArrayView1::from(weights.weights.as_slice()))
+                        });
+                    })
+            });
+        PDOSWeights::new(spin_polarized, orbital_states, kpoints, orbital_weights)
+    }
+
+    /// Build the records from `PDOSWeights`, e.g. after filtering or merging weights.
+    /// The k-points are written in the order of `pdos_weights.kpoints`.
+    /// The number of occupied bands of each k-point is the number of eigenvalues
+    /// it holds, `max_bands` is the largest of them.
+    pub fn from_pdos_weights(
+        pdos_weights: &PDOSWeights,
+        bin_header: Option<PDOSBinHeader>,
+    ) -> Self {
+        let orbital_weights = &pdos_weights.orbital_weights;
+        let to_weights_per_spin = |spin: usize, k: usize| -> WeightsPerSpin {
+            let bands = orbital_weights
+                .kpoint(spin, k)
+                .rows()
+                .into_iter()
+                .map(|weights| WeightsPerEigen::new(weights.to_vec()))
+                .collect::<Vec<WeightsPerEigen>>();
+            let index = if spin == 0 {
+                SpinIndex::One
+            } else {
+                SpinIndex::Two
+            };
+            WeightsPerSpin::new(index, bands.len() as u32, bands)
+        };
+        let kpoints = pdos_weights
+            .kpoints
+            .iter()
+            .enumerate()
+            .map(|(k, kpt)| {
+                let spins = (0..orbital_weights.num_spins())
+                    .map(|spin| to_weights_per_spin(spin, k))
+                    .collect();
+                WeightsPerKPoint::new(kpt.index, kpt.coords, spins)
+            })
+            .collect::<Vec<WeightsPerKPoint>>();
+        let max_bands = kpoints
+            .iter()
+            .flat_map(|kpt| kpt.spins.iter().map(|spin| spin.nbands_occ))
+            .max()
+            .unwrap_or(0);
+        let orbital_states = &pdos_weights.orbital_states;
+        let header = Header {
+            total_kpoints: kpoints.len() as u32,
+            num_spins: match orbital_weights.num_spins() {
+                1 => NumSpins::One,
+                _ => NumSpins::Two,
+            },
+            num_orbitals: orbital_states.len() as u32,
+            max_bands,
+            orbital_species: orbital_states.iter().map(|s| s.species_id).collect(),
+            orbital_ion: orbital_states.iter().map(|s| s.ion_id).collect(),
+            orbital_am: orbital_states.iter().map(|s| s.angular_momentum).collect(),
+        };
+        Self::new(bin_header, header, kpoints)
+    }
+}
@@ -0,0 +1,300 @@
+use serde::{Deserialize, Serialize};
+
+use super::AngularMomentum;
+
+/// Sub-orbitals in the order of the 2l+1 weight columns of each
+/// (species, ion, l) in `.pdos_weights`. `CASTEP` projects on the same
+/// atomic basis as its orbital populations, and lists them in this order
+/// in "Orbital Populations" of `.castep`, see `from_castep_label`.
+const P_ORDER: [OrbitalLabel; 3] = [OrbitalLabel::Px, OrbitalLabel::Py, OrbitalLabel::Pz];
+const D_ORDER: [OrbitalLabel; 5] = [
+    OrbitalLabel::Dz2,
+    OrbitalLabel::Dyz,
+    OrbitalLabel::Dxz,
+    OrbitalLabel::Dx2y2,
+    OrbitalLabel::Dxy,
+];
+const F_ORDER: [OrbitalLabel; 7] = [
+    OrbitalLabel::Fx3,
+    OrbitalLabel::Fy3,
+    OrbitalLabel::Fz3,
+    OrbitalLabel::Fxyz,
+    OrbitalLabel::Fzx2y2,
+    OrbitalLabel::Fyz2x2,
+    OrbitalLabel::Fxy2z2,
+];
+
+/// Real spherical harmonic sub-orbital, or a group of them, to select
+/// m-resolved projections in config, e.g. `orbitals = ["t2g"]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum OrbitalLabel {
+    /// s
+    #[serde(rename = "s")]
+    S,
+    /// All three p
+    #[serde(rename = "p")]
+    P,
+    /// p_x
+    #[serde(rename = "px")]
+    Px,
+    /// p_y
+    #[serde(rename = "py")]
+    Py,
+    /// p_z
+    #[serde(rename = "pz")]
+    Pz,
+    /// All five d
+    #[serde(rename = "d")]
+    D,
+    /// d_xy
+    #[serde(rename = "dxy")]
+    Dxy,
+    /// d_yz
+    #[serde(rename = "dyz")]
+    Dyz,
+    /// d_xz
+    #[serde(rename = "dxz")]
+    Dxz,
+    /// d_z²
+    #[serde(rename = "dz2")]
+    Dz2,
+    /// d_x²-y²
+    #[serde(rename = "dx2-y2")]
+    Dx2y2,
+    /// d_xy, d_yz and d_xz, lowered in an octahedral field
+    #[serde(rename = "t2g")]
+    T2g,
+    /// d_z² and d_x²-y², raised in an octahedral field
+    #[serde(rename = "eg")]
+    Eg,
+    /// All seven f
+    #[serde(rename = "f")]
+    F,
+    /// f_x³
+    #[serde(rename = "fx3")]
+    Fx3,
+    /// f_y³
+    #[serde(rename = "fy3")]
+    Fy3,
+    /// f_z³
+    #[serde(rename = "fz3")]
+    Fz3,
+    /// f_xyz
+    #[serde(rename = "fxyz")]
+    Fxyz,
+    /// f_z(x²-y²)
+    #[serde(rename = "fz(x2-y2)")]
+    Fzx2y2,
+    /// f_y(z²-x²)
+    #[serde(rename = "fy(z2-x2)")]
+    Fyz2x2,
+    /// f_x(y²-z²)
+    #[serde(rename = "fx(y2-z2)")]
+    Fxy2z2,
+}
+
+impl OrbitalLabel {
+    /// Sub-orbital of the `m_index`-th weight column of an (species, ion, l)
+    /// group, `None` beyond the 2l+1 columns
+    pub fn from_m_index(angular_momentum: AngularMomentum, m_index: u32) -> Option<Self> {
+        let m_index = m_index as usize;
+        match angular_momentum {
+            AngularMomentum::S => (m_index == 0).then_some(OrbitalLabel::S),
+            AngularMomentum::P => P_ORDER.get(m_index).copied(),
+            AngularMomentum::D => D_ORDER.get(m_index).copied(),
+            AngularMomentum::F => F_ORDER.get(m_index).copied(),
+        }
+    }
+
+    /// Sub-orbital of a row of "Orbital Populations" in `.castep`, e.g.
+    /// `Dzz` or `Fz(xx-yy)`
+    pub fn from_castep_label(label: &str) -> Option<Self> {
+        match label {
+            "S" => Some(OrbitalLabel::S),
+            "Px" => Some(OrbitalLabel::Px),
+            "Py" => Some(OrbitalLabel::Py),
+            "Pz" => Some(OrbitalLabel::Pz),
+            "Dzz" => Some(OrbitalLabel::Dz2),
+            "Dzy" => Some(OrbitalLabel::Dyz),
+            "Dzx" => Some(OrbitalLabel::Dxz),
+            "Dxx-yy" => Some(OrbitalLabel::Dx2y2),
+            "Dxy" => Some(OrbitalLabel::Dxy),
+            "Fxxx" => Some(OrbitalLabel::Fx3),
+            "Fyyy" => Some(OrbitalLabel::Fy3),
+            "Fzzz" => Some(OrbitalLabel::Fz3),
+            "Fxyz" => Some(OrbitalLabel::Fxyz),
+            "Fz(xx-yy)" => Some(OrbitalLabel::Fzx2y2),
+            "Fy(zz-xx)" => Some(OrbitalLabel::Fyz2x2),
+            "Fx(yy-zz)" => Some(OrbitalLabel::Fxy2z2),
+            _ => None,
+        }
+    }
+
+    /// The single sub-orbitals of this label
+    pub fn sub_orbitals(&self) -> Vec<OrbitalLabel> {
+        match self {
+            OrbitalLabel::P => P_ORDER.to_vec(),
+            OrbitalLabel::D => D_ORDER.to_vec(),
+            OrbitalLabel::F => F_ORDER.to_vec(),
+            OrbitalLabel::T2g => vec![OrbitalLabel::Dxy, OrbitalLabel::Dyz, OrbitalLabel::Dxz],
+            OrbitalLabel::Eg => vec![OrbitalLabel::Dz2, OrbitalLabel::Dx2y2],
+            single => vec![*single],
+        }
+    }
+
+    /// The label includes the sub-orbital of the `m_index`-th column
+    pub fn contains(&self, angular_momentum: AngularMomentum, m_index: u32) -> bool {
+        OrbitalLabel::from_m_index(angular_momentum, m_index)
+            .is_some_and(|sub_orbital| self.sub_orbitals().contains(&sub_orbital))
+    }
+}
+
+impl std::fmt::Display for OrbitalLabel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Same as in config
+        let label = match self {
+            OrbitalLabel::S => "s",
+            OrbitalLabel::P => "p",
+            OrbitalLabel::Px => "px",
+            OrbitalLabel::Py => "py",
+            OrbitalLabel::Pz => "pz",
+            OrbitalLabel::D => "d",
+            OrbitalLabel::Dxy => "dxy",
+            OrbitalLabel::Dyz => "dyz",
+            OrbitalLabel::Dxz => "dxz",
+            OrbitalLabel::Dz2 => "dz2",
+            OrbitalLabel::Dx2y2 => "dx2-y2",
+            OrbitalLabel::T2g => "t2g",
+            OrbitalLabel::Eg => "eg",
+            OrbitalLabel::F => "f",
+            OrbitalLabel::Fx3 => "fx3",
+            OrbitalLabel::Fy3 => "fy3",
+            OrbitalLabel::Fz3 => "fz3",
+            OrbitalLabel::Fxyz => "fxyz",
+            OrbitalLabel::Fzx2y2 => "fz(x2-y2)",
+            OrbitalLabel::Fyz2x2 => "fy(z2-x2)",
+            OrbitalLabel::Fxy2z2 => "fx(y2-z2)",
+        };
+        write!(f, "{label}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use serde::Deserialize;
+
+    use crate::{castep_output::CastepOutputParser, fundamental::AngularMomentum};
+
+    use super::OrbitalLabel;
+
+    /// Orbital populations of `.castep` with an f shell on Dy
+    const DY_O_CASTEP: &str = r#"
+                           -------------------------------
+                                      Cell Contents
+                           -------------------------------
+            xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx
+            x  Element    Atom        Fractional coordinates of atoms  x
+            x            Number           u          v          w      x
+            x----------------------------------------------------------x
+            x  O            1         0.000000   0.000000   0.000000   x
+            x  Dy           1         0.500000   0.500000   0.500000   x
+            xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx
+
+     Orbital Populations
+     Ion    Atom   Orbital             Charge
+     -------------------------------------------
+     O       1      S                  1.862
+     O       1      Px                 1.581
+     O       1      Py                 1.581
+     O       1      Pz                 1.581
+     Dy      1      S                  2.104
+     Dy      1      Px                 1.998
+     Dy      1      Py                 1.998
+     Dy      1      Pz                 1.998
+     Dy      1      Dzz                0.288
+     Dy      1      Dzy                0.233
+     Dy      1      Dzx                0.233
+     Dy      1      Dxx-yy             0.288
+     Dy      1      Dxy                0.233
+     Dy      1      Fxxx               1.366
+     Dy      1      Fyyy               1.366
+     Dy      1      Fzzz               1.366
+     Dy      1      Fxyz               1.364
+     Dy      1      Fz(xx-yy)          1.365
+     Dy      1      Fy(zz-xx)          1.365
+     Dy      1      Fx(yy-zz)          1.365
+     -------------------------------------------
+                    Total:            30.000
+     -------------------------------------------
+"#;
+
+    #[test]
+    fn test_orbital_labels() {
+        #[derive(Deserialize)]
+        struct Config {
+            orbitals: Vec<OrbitalLabel>,
+        }
+        let config =
+            toml::from_str::<Config>(r#"orbitals = ["t2g", "dx2-y2", "fz(x2-y2)", "p"]"#).unwrap();
+        assert_eq!(
+            config.orbitals,
+            vec![
+                OrbitalLabel::T2g,
+                OrbitalLabel::Dx2y2,
+                OrbitalLabel::Fzx2y2,
+                OrbitalLabel::P
+            ]
+        );
+        config.orbitals.iter().for_each(|label| {
+            let text = format!("orbitals = [\"{label}\"]");
+            assert_eq!(toml::from_str::<Config>(&text).unwrap().orbitals[0], *label);
+        });
+        // Each column of a shell is in exactly one of t2g and eg
+        (0..5).for_each(|m| {
+            let t2g = OrbitalLabel::T2g.contains(AngularMomentum::D, m);
+            let eg = OrbitalLabel::Eg.contains(AngularMomentum::D, m);
+            assert!(t2g != eg);
+        });
+        assert!(OrbitalLabel::P.contains(AngularMomentum::P, 2));
+        assert!(!OrbitalLabel::P.contains(AngularMomentum::D, 0));
+        assert!(!OrbitalLabel::Dxy.contains(AngularMomentum::D, 5));
+        assert_eq!(OrbitalLabel::from_m_index(AngularMomentum::S, 1), None);
+    }
+
+    #[test]
+    fn test_castep_column_order() {
+        let populations = CastepOutputParser::new(DY_O_CASTEP)
+            .parse_castep_output()
+            .unwrap()
+            .orbital_populations
+            .unwrap();
+        assert_eq!(populations.len(), 20);
+        // The rows of an ion follow its weight columns: the m-th row of
+        // each l is the m-th column
+        let mut m_indices = HashMap::new();
+        populations.iter().for_each(|row| {
+            let am = match &row.orbital[..1] {
+                "S" => AngularMomentum::S,
+                "P" => AngularMomentum::P,
+                "D" => AngularMomentum::D,
+                _ => AngularMomentum::F,
+            };
+            let m = m_indices.entry((&row.species, row.ion, am)).or_insert(0);
+            let label = OrbitalLabel::from_castep_label(&row.orbital);
+            assert!(label.is_some(), "{}", row.orbital);
+            assert_eq!(OrbitalLabel::from_m_index(am, *m), label);
+            *m += 1;
+        });
+        // Every column of each shell is listed
+        m_indices.iter().for_each(|((_, _, am), &count)| {
+            assert_eq!(OrbitalLabel::from_m_index(*am, count), None);
+        });
+        // t2g and eg of the d shell
+        let t2g = (0..5)
+            .filter(|&m| OrbitalLabel::T2g.contains(AngularMomentum::D, m))
+            .collect::<Vec<u32>>();
+        assert_eq!(t2g, vec![1, 2, 4]);
+    }
+}
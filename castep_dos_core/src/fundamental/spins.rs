@@ -25,6 +25,12 @@ impl TryFrom<u32> for SpinIndex {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("`{0}` and `{1}` have different spin layouts")]
+/// Two `SpinData` combined per spin are not both spin polarized or both
+/// non-polarized, e.g. from malformed or mismatched seed files
+pub struct SpinLayoutError(pub &'static str, pub &'static str);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 /// Bool enum to mark if the data is spin-polarized
 pub enum SpinPolarized {
@@ -1,68 +1,69 @@
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use ndarray::ArrayView1;
 
 use super::{
-    AngularMomentum, EigenvalueVec, KpointVec, OrbitalState, OrbitalWeight, OrbitalWeightVec,
-    PDOSWeights, SpinData, SpinIndex, SpinPolarized,
+    AngularMomentum, EigenvalueVec, HATREE_TO_EV, KpointCoords, KpointVec, OrbitalState,
+    OrbitalWeights, PDOSWeights, SpinData, SpinPolarized,
 };
 /// Gather weights at each eigenvalue's orbital weight array
-fn sum_weights_per_eigenvalue(indices: &[usize], weights: &OrbitalWeightVec) -> f64 {
-    indices.par_iter().map(|&idx| weights[idx].value()).sum()
+fn sum_weights_per_eigenvalue(indices: &[usize], weights: ArrayView1<f64>) -> f64 {
+    indices.iter().map(|&idx| weights[idx]).sum()
 }
 
 #[test]
 fn defined_types() {
-    // Construction - matches your exact specification
+    // Construction from the nested layout
     let pdos_weights = PDOSWeights {
         spin_polarized: SpinPolarized::True,
         orbital_states: vec![OrbitalState::new(1, 1, AngularMomentum::S)],
-        orbital_weights: SpinData::SpinPolarized([
-            KpointVec::new(vec![EigenvalueVec::new(vec![
-                OrbitalWeightVec::new(vec![OrbitalWeight::new(0.1), OrbitalWeight::new(0.2)]),
-                OrbitalWeightVec::new(vec![OrbitalWeight::new(0.3), OrbitalWeight::new(0.4)]),
-            ])]),
-            KpointVec::new(vec![EigenvalueVec::new(vec![
-                OrbitalWeightVec::new(vec![OrbitalWeight::new(0.5), OrbitalWeight::new(0.6)]),
-                OrbitalWeightVec::new(vec![OrbitalWeight::new(0.7), OrbitalWeight::new(0.8)]),
-            ])]),
-        ]),
+        kpoints: KpointVec::new(vec![KpointCoords::new(1, [0.0; 3])]),
+        orbital_weights: OrbitalWeights::from_nested(
+            &SpinData::SpinPolarized([
+                KpointVec::new(vec![EigenvalueVec::new(vec![
+                    vec![0.1, 0.2],
+                    vec![0.3, 0.4],
+                ])]),
+                KpointVec::new(vec![EigenvalueVec::new(vec![
+                    vec![0.5, 0.6],
+                    vec![0.7, 0.8],
+                ])]),
+            ]),
+            2,
+        ),
     };
 
-    // Access patterns - direct and intuitive
-    let first_kpoint = &pdos_weights.orbital_weights.get(SpinIndex::One).unwrap()[0];
-    let first_eigenvalue = &first_kpoint[0];
-    let _first_orbital = first_eigenvalue[0].value();
+    // Access patterns - [spin, k-point, band, orbital]
+    let first_kpoint = pdos_weights.orbital_weights.kpoint(0, 0);
+    let first_eigenvalue = first_kpoint.row(0);
+    assert_eq!(first_eigenvalue[0], 0.1);
+    assert_eq!(pdos_weights.orbital_weights.array()[[1, 0, 1, 1]], 0.8);
 
-    // Iteration - using Deref to Vec
-    for kpoint_data in pdos_weights
-        .orbital_weights
-        .get(SpinIndex::One)
-        .unwrap()
-        .iter()
-    {
-        for eigenvalue_data in kpoint_data.iter() {
-            for orbital_weight in eigenvalue_data.iter() {
-                println!("Weight: {}", orbital_weight.value());
-            }
+    // Iteration over the bands of a k-point
+    for eigenvalue_data in pdos_weights.orbital_weights.kpoint(0, 0).rows() {
+        for orbital_weight in eigenvalue_data.iter() {
+            println!("Weight: {}", orbital_weight);
         }
     }
-    // Iteration - using Deref to Vec
-    pdos_weights
-        .orbital_weights
-        .get(SpinIndex::One)
-        .map(|spin_channel| {
-            spin_channel.iter().for_each(|kpt_data| {
-                kpt_data.iter().for_each(|eigen_data| {
-                    eigen_data
-                        .iter()
-                        .for_each(|weight| println!("Weight: {}", weight.value()));
-                });
-            });
-        })
-        .unwrap();
 
     let indices = vec![0_usize, 1_usize];
     // Functional transformation
-    let _squared_weights = pdos_weights
+    let summed_weights = pdos_weights
         .orbital_weights
         .map_on_data_of_eigenvalue(|w| sum_weights_per_eigenvalue(&indices, w));
+    let SpinData::SpinPolarized([_, down]) = summed_weights else {
+        panic!("spin polarized expected");
+    };
+    assert!((down[0][1] - 1.5).abs() < 1e-12);
+}
+
+#[test]
+fn energy_range() {
+    // Bands from -0.5 Ha, up to 0.3125 + 2 * 2^-6 Ha and 2^-7 Ha more in spin down
+    let mut band_structure = crate::test_fixtures::sample_bands_file(2).to_band_structure();
+    let (min, max) = band_structure.energy_range(0.1);
+    assert!((min - (-0.5 * HATREE_TO_EV - 1.0)).abs() < 1e-9);
+    assert!((max - (0.3515625 * HATREE_TO_EV + 1.0)).abs() < 1e-9);
+    // Relative to the Fermi energy of each spin
+    band_structure.fermi_energy = SpinData::SpinPolarized([0.0, 0.0078125]);
+    let (_, max) = band_structure.energy_range(0.1);
+    assert!((max - (0.34375 * HATREE_TO_EV + 1.0)).abs() < 1e-9);
 }
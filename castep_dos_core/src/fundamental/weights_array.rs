@@ -0,0 +1,256 @@
+use ndarray::{Array2, Array4, ArrayView1, ArrayView2, ArrayViewMut2, Axis, s};
+
+use super::{AngularChannels, EigenvalueVec, KpointVec, SpinData};
+
+#[derive(Debug, Clone, PartialEq)]
+/// Orbital weights of every state in one contiguous array
+/// [spin, k-point, band, orbital].
+/// `.pdos_weights` records the occupied bands of each spin and k-point,
+/// up to `max_bands` of the header. The bands beyond are padded with zero
+/// weights and left out of the views of the states.
+pub struct OrbitalWeights {
+    /// dim: n_spins, n_kpts, max_bands, n_orbs
+    weights: Array4<f64>,
+    /// Bands recorded at each [spin, k-point]
+    band_counts: Array2<usize>,
+}
+
+impl OrbitalWeights {
+    /// Constructor. `band_counts` has the [spin, k-point] shape of
+    /// `weights`, and none is above its band axis.
+    pub fn new(weights: Array4<f64>, band_counts: Array2<usize>) -> Self {
+        let (num_spins, num_kpoints, max_bands, _) = weights.dim();
+        assert_eq!(band_counts.dim(), (num_spins, num_kpoints));
+        assert!(band_counts.iter().all(|&count| count <= max_bands));
+        Self {
+            weights,
+            band_counts,
+        }
+    }
+
+    /// Zero weights, with `max_bands` bands at each spin and k-point until
+    /// set by `kpoint_mut`
+    pub fn zeros(
+        num_spins: usize,
+        num_kpoints: usize,
+        max_bands: usize,
+        num_orbitals: usize,
+    ) -> Self {
+        Self {
+            weights: Array4::zeros((num_spins, num_kpoints, max_bands, num_orbitals)),
+            band_counts: Array2::from_elem((num_spins, num_kpoints), max_bands),
+        }
+    }
+
+    /// From the weights of each state in the nested layout of
+    /// `BandStructure::eigenvalues`, each holding `num_orbitals` values
+    pub fn from_nested(
+        weights: &SpinData<KpointVec<EigenvalueVec<Vec<f64>>>>,
+        num_orbitals: usize,
+    ) -> Self {
+        let spins = match weights {
+            SpinData::NonPolarized(kpts) => vec![kpts],
+            SpinData::SpinPolarized([up, down]) => vec![up, down],
+        };
+        let num_kpoints = spins[0].len();
+        let max_bands = spins
+            .iter()
+            .flat_map(|kpts| kpts.iter().map(|bands| bands.len()))
+            .max()
+            .unwrap_or(0);
+        let mut orbital_weights = Self::zeros(spins.len(), num_kpoints, max_bands, num_orbitals);
+        spins.iter().enumerate().for_each(|(spin, kpts)| {
+            kpts.iter().enumerate().for_each(|(k, bands)| {
+                let mut view = orbital_weights.kpoint_mut(spin, k, bands.len());
+                bands.iter().enumerate().for_each(|(band, weights)| {
+                    view.row_mut(band)
+                        .assign(&ArrayView1::from(weights.as_slice()))
+                });
+            })
+        });
+        orbital_weights
+    }
+
+    /// The whole array, padding included
+    pub fn array(&self) -> &Array4<f64> {
+        &self.weights
+    }
+
+    /// Number of spins, 1 or 2
+    pub fn num_spins(&self) -> usize {
+        self.weights.dim().0
+    }
+
+    /// Number of k-points
+    pub fn num_kpoints(&self) -> usize {
+        self.weights.dim().1
+    }
+
+    /// Length of the band axis
+    pub fn max_bands(&self) -> usize {
+        self.weights.dim().2
+    }
+
+    /// Number of orbitals
+    pub fn num_orbitals(&self) -> usize {
+        self.weights.dim().3
+    }
+
+    /// Bands recorded at a spin and k-point
+    pub fn band_count(&self, spin: usize, kpoint: usize) -> usize {
+        self.band_counts[[spin, kpoint]]
+    }
+
+    /// Weights of the bands recorded at a spin and k-point, [band, orbital]
+    pub fn kpoint(&self, spin: usize, kpoint: usize) -> ArrayView2<'_, f64> {
+        let count = self.band_count(spin, kpoint);
+        self.weights.slice(s![spin, kpoint, ..count, ..])
+    }
+
+    /// Record `band_count` bands at a spin and k-point and return their
+    /// weights to fill, [band, orbital]
+    pub fn kpoint_mut(
+        &mut self,
+        spin: usize,
+        kpoint: usize,
+        band_count: usize,
+    ) -> ArrayViewMut2<'_, f64> {
+        assert!(band_count <= self.max_bands());
+        self.band_counts[[spin, kpoint]] = band_count;
+        self.weights.slice_mut(s![spin, kpoint, ..band_count, ..])
+    }
+
+    /// Map the orbital weights of each state, in the nested layout of
+    /// `BandStructure::eigenvalues`
+    pub fn map_on_data_of_eigenvalue<U, F>(&self, f: F) -> SpinData<KpointVec<EigenvalueVec<U>>>
+    where
+        F: Fn(ArrayView1<f64>) -> U,
+    {
+        let spin = |spin: usize| -> KpointVec<EigenvalueVec<U>> {
+            (0..self.num_kpoints())
+                .map(|k| self.kpoint(spin, k).rows().into_iter().map(&f).collect())
+                .collect()
+        };
+        match self.num_spins() {
+            1 => SpinData::NonPolarized(spin(0)),
+            _ => SpinData::SpinPolarized([spin(0), spin(1)]),
+        }
+    }
+
+    /// Sum the orbital weights of every state into s, p, d and f channels
+    /// with `selection` [orbital, channel], as one matrix product.
+    /// `selection` holds the share of each orbital in each channel, 1 or 0
+    /// to select it or not.
+    pub fn project(
+        &self,
+        selection: &Array2<f64>,
+    ) -> SpinData<KpointVec<EigenvalueVec<AngularChannels>>> {
+        assert_eq!(selection.dim(), (self.num_orbitals(), 4));
+        let (num_spins, num_kpoints, max_bands, num_orbitals) = self.weights.dim();
+        let states = self
+            .weights
+            .as_standard_layout()
+            .into_shape_with_order((num_spins * num_kpoints * max_bands, num_orbitals))
+            .expect("the array is contiguous");
+        let channels = states
+            .dot(selection)
+            .into_shape_with_order((num_spins, num_kpoints, max_bands, 4))
+            .expect("the product is contiguous");
+        let spin = |spin: usize| -> KpointVec<EigenvalueVec<AngularChannels>> {
+            (0..num_kpoints)
+                .map(|k| {
+                    channels
+                        .slice(s![spin, k, ..self.band_count(spin, k), ..])
+                        .rows()
+                        .into_iter()
+                        .map(|c| AngularChannels::new(c[0], c[1], c[2], c[3]))
+                        .collect()
+                })
+                .collect()
+        };
+        match num_spins {
+            1 => SpinData::NonPolarized(spin(0)),
+            _ => SpinData::SpinPolarized([spin(0), spin(1)]),
+        }
+    }
+
+    /// Move the k-points to a new order, `order[i]` is the current position
+    /// of the i-th k-point
+    pub fn reorder_kpoints(&mut self, order: &[usize]) {
+        // `select` may return another memory order, keep the states
+        // contiguous for `project`
+        self.weights = self
+            .weights
+            .select(Axis(1), order)
+            .as_standard_layout()
+            .into_owned();
+        self.band_counts = self.band_counts.select(Axis(1), order);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::Array2;
+
+    use crate::fundamental::{AngularChannels, EigenvalueVec, KpointVec, SpinData};
+
+    use super::OrbitalWeights;
+
+    #[test]
+    fn test_orbital_weights() {
+        // Two k-points, the second one records a single band
+        let nested = SpinData::SpinPolarized([
+            KpointVec::new(vec![
+                EigenvalueVec::new(vec![vec![0.1, 0.2, 0.3], vec![0.4, 0.5, 0.6]]),
+                EigenvalueVec::new(vec![vec![0.7, 0.8, 0.9]]),
+            ]),
+            KpointVec::new(vec![
+                EigenvalueVec::new(vec![vec![1.0, 1.1, 1.2], vec![1.3, 1.4, 1.5]]),
+                EigenvalueVec::new(vec![vec![1.6, 1.7, 1.8], vec![1.9, 2.0, 2.1]]),
+            ]),
+        ]);
+        let mut weights = OrbitalWeights::from_nested(&nested, 3);
+        assert_eq!(weights.array().dim(), (2, 2, 2, 3));
+        assert_eq!(weights.band_count(0, 1), 1);
+        assert_eq!(weights.array()[[0, 1, 1, 2]], 0.0);
+        assert_eq!(
+            weights.map_on_data_of_eigenvalue(|w| w.to_vec()),
+            nested.map_on_data_of_eigenvalue(|w| w.clone())
+        );
+        // Orbital 0 in s, orbitals 1 and 2 in p, half of orbital 2 in d
+        let mut selection = Array2::zeros((3, 4));
+        selection[[0, 0]] = 1.0;
+        selection[[1, 1]] = 1.0;
+        selection[[2, 1]] = 1.0;
+        selection[[2, 2]] = 0.5;
+        let projected = weights.project(&selection);
+        let projected_len = projected.map(|kpts| kpts.len());
+        let expected = nested.map_on_data_of_eigenvalue(|w| {
+            AngularChannels::new(w[0], w[1] + w[2], 0.5 * w[2], 0.0)
+        });
+        let flatten = |data: &SpinData<KpointVec<EigenvalueVec<AngularChannels>>>| {
+            let mut values = Vec::new();
+            data.for_each(|kpts| {
+                kpts.iter().flat_map(|bands| bands.iter()).for_each(|c| {
+                    values.extend([c.s, c.p, c.d, c.f]);
+                })
+            });
+            values
+        };
+        let (projected, expected) = (flatten(&projected), flatten(&expected));
+        // Seven states recorded, four channels each
+        assert_eq!(projected.len(), 7 * 4);
+        projected
+            .iter()
+            .zip(expected)
+            .for_each(|(a, b)| assert!((a - b).abs() < 1e-12));
+        weights.reorder_kpoints(&[1, 0]);
+        assert!(weights.array().is_standard_layout());
+        assert_eq!(
+            weights.project(&selection).map(|kpts| kpts.len()),
+            projected_len
+        );
+        assert_eq!(weights.band_count(0, 0), 1);
+        assert_eq!(weights.kpoint(1, 1).row(0).to_vec(), vec![1.0, 1.1, 1.2]);
+    }
+}
@@ -108,6 +108,23 @@ where
         .collect::<Result<Vec<T>, HelperError>>()
 }
 
+/// Same as `parse_vec`, into `output` of the record length
+pub(crate) fn parse_vec_into<T, const N: usize>(
+    input: &mut &[u8],
+    output: &mut [T],
+) -> Result<(), HelperError>
+where
+    T: FromBeBytes<N>,
+{
+    let data = parse_record(input, N * output.len())?;
+    data.chunks_exact(N)
+        .zip(output.iter_mut())
+        .try_for_each(|(chunk, value)| {
+            *value = T::from_be_bytes(chunk.try_into().map_err(HelperError::BytesIntoArray)?);
+            Ok(())
+        })
+}
+
 pub(crate) trait FromBeBytes<const N: usize>: Sized {
     fn from_be_bytes(bytes: [u8; N]) -> Self;
 }
@@ -123,3 +140,47 @@ impl FromBeBytes<8> for f64 {
         Self::from_be_bytes(bytes)
     }
 }
+
+/// Helper: write big-endian record with leading and ending size markers.
+pub(crate) fn write_record(output: &mut Vec<u8>, data: &[u8]) {
+    let size = (data.len() as u32).to_be_bytes();
+    output.extend_from_slice(&size);
+    output.extend_from_slice(data);
+    output.extend_from_slice(&size);
+}
+
+/// Helper functions to write `u32` or `f64` as a record
+pub(crate) fn write_scalar<T, const N: usize>(output: &mut Vec<u8>, value: T)
+where
+    T: ToBeBytes<N>,
+{
+    write_record(output, &value.to_be_bytes());
+}
+
+/// Helper functions to write array of `u32` or `f64` as one record
+pub(crate) fn write_vec<T, const N: usize>(output: &mut Vec<u8>, values: &[T])
+where
+    T: ToBeBytes<N> + Copy,
+{
+    let data = values
+        .iter()
+        .flat_map(|value| value.to_be_bytes())
+        .collect::<Vec<u8>>();
+    write_record(output, &data);
+}
+
+pub(crate) trait ToBeBytes<const N: usize>: Sized {
+    fn to_be_bytes(self) -> [u8; N];
+}
+
+impl ToBeBytes<4> for u32 {
+    fn to_be_bytes(self) -> [u8; 4] {
+        Self::to_be_bytes(self)
+    }
+}
+
+impl ToBeBytes<8> for f64 {
+    fn to_be_bytes(self) -> [u8; 8] {
+        Self::to_be_bytes(self)
+    }
+}
@@ -10,10 +10,43 @@ pub mod helper;
 /// Parsing logics and function routines
 pub mod pdos_weights_parser;
 
+/// Reading `.pdos_weights` and `.pdos_bin` one k-point at a time
+pub mod pdos_weights_reader;
+
+/// Memory-mapped parsing of `.pdos_bin` with the k-points decoded in parallel
+pub mod pdos_weights_parallel;
+
+/// Serializing `PDOSWeightsFile` back to `.pdos_weights` and `.pdos_bin`
+pub mod pdos_weights_writer;
+
 pub mod bands;
 
+/// Parsing `.cell` for lattice and species ranks
+pub mod cell;
+
+/// Parsing `.castep` for species, Fermi energy and populations
+pub mod castep_output;
+
 /// Projector preprocess
 pub mod projectors;
 
 /// calculation of PDOS
 pub mod pdos_compute;
+
+/// Band centre, width and higher moments of the projected DOS
+pub mod moments;
+
+/// Total DOS from the eigenvalues and charge spilling of the projections
+pub mod spilling;
+
+/// Spin polarization, magnetic moments and exchange splitting
+pub mod spin_analysis;
+
+/// Consistency checks across the seed files
+pub mod validation;
+
+/// Overview of the content of the seed files
+pub mod summary;
+
+#[cfg(test)]
+mod test_fixtures;
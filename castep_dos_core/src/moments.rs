@@ -0,0 +1,265 @@
+use std::fmt::Display;
+
+use crate::{
+    fundamental::{
+        AngularChannels, BandStructure, EigenvalueVec, HATREE_TO_EV, KpointVec, SpinData,
+        SpinLayoutError,
+    },
+    pdos_compute::PDOSResult,
+};
+
+/// Moments of the distribution of states of one channel in an energy window,
+/// e.g. the d-band centre of Hammer and Nørskov
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BandMoments {
+    /// Number of states in the window
+    pub states: f64,
+    /// Mean energy in eV
+    pub centre: f64,
+    /// Standard deviation in eV, the square root of the second central moment
+    pub width: f64,
+    /// Third standardized moment
+    pub skewness: f64,
+    /// Fourth standardized moment, 3 for a Gaussian (not the excess kurtosis)
+    pub kurtosis: f64,
+}
+
+impl BandMoments {
+    /// Moments of `(energy, weight)` samples. `None` without weight.
+    pub fn from_weighted_samples(samples: &[(f64, f64)]) -> Option<Self> {
+        let states: f64 = samples.iter().map(|(_, w)| w).sum();
+        if states.abs() < f64::EPSILON {
+            return None;
+        }
+        let centre = samples.iter().map(|(e, w)| e * w).sum::<f64>() / states;
+        let central = |order: i32| -> f64 {
+            samples
+                .iter()
+                .map(|(e, w)| (e - centre).powi(order) * w)
+                .sum::<f64>()
+                / states
+        };
+        let variance = central(2);
+        let width = variance.sqrt();
+        let (skewness, kurtosis) = if variance > 0.0 {
+            (central(3) / width.powi(3), central(4) / variance.powi(2))
+        } else {
+            (0.0, 0.0)
+        };
+        Some(Self {
+            states,
+            centre,
+            width,
+            skewness,
+            kurtosis,
+        })
+    }
+}
+
+/// Moments of the s, p, d and f channels, `None` for empty channels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelMoments {
+    /// Channel s
+    pub s: Option<BandMoments>,
+    /// Channel p
+    pub p: Option<BandMoments>,
+    /// Channel d
+    pub d: Option<BandMoments>,
+    /// Channel f
+    pub f: Option<BandMoments>,
+}
+
+/// Moments of the broadened PDOS within `window` (min, max) in eV,
+/// with the energy grid points as samples.
+pub fn pdos_moments(pdos: &PDOSResult, energy_grid: &[f64], window: (f64, f64)) -> ChannelMoments {
+    let (min, max) = window;
+    // Trapezoidal weights of the grid points
+    let steps = (0..energy_grid.len())
+        .map(|i| {
+            let lower = energy_grid[i.saturating_sub(1)];
+            let upper = energy_grid[(i + 1).min(energy_grid.len() - 1)];
+            0.5 * (upper - lower)
+        })
+        .collect::<Vec<f64>>();
+    let channel = |dos: &[f64]| {
+        let samples = energy_grid
+            .iter()
+            .zip(dos.iter())
+            .zip(steps.iter())
+            .filter(|((e, _), _)| (min..=max).contains(*e))
+            .map(|((&e, &dos), &step)| (e, dos * step))
+            .collect::<Vec<(f64, f64)>>();
+        BandMoments::from_weighted_samples(&samples)
+    };
+    ChannelMoments {
+        s: channel(&pdos.s),
+        p: channel(&pdos.p),
+        d: channel(&pdos.d),
+        f: channel(&pdos.f),
+    }
+}
+
+/// Moments of the projected weights at the eigenvalues within `window`
+/// (min, max) in eV relative to `band_structure.fermi_energy`, the axis of
+/// the PDOS, without broadening.
+/// Each state counts with its k-point weight, and twice without spin
+/// polarization, as in the PDOS.
+/// Fails when the Fermi energy or `projected_weights` is not in the spin
+/// layout of the eigenvalues.
+pub fn eigenvalue_moments(
+    band_structure: &BandStructure,
+    projected_weights: &SpinData<KpointVec<EigenvalueVec<AngularChannels>>>,
+    window: (f64, f64),
+) -> Result<SpinData<ChannelMoments>, SpinLayoutError> {
+    let (min, max) = window;
+    let spin_coeff = match band_structure.eigenvalues {
+        SpinData::NonPolarized(_) => 2.0,
+        SpinData::SpinPolarized(_) => 1.0,
+    };
+    let channels = band_structure
+        .eigenvalues
+        .zip(&band_structure.fermi_energy)
+        .ok_or(SpinLayoutError("eigenvalues", "fermi_energy"))?;
+    let channels = channels
+        .zip(projected_weights)
+        .ok_or(SpinLayoutError("eigenvalues", "projected weights"))?;
+    let moments = |kpts: &KpointVec<EigenvalueVec<f64>>,
+                   fermi: f64,
+                   weights: &KpointVec<EigenvalueVec<AngularChannels>>| {
+        let samples = kpts
+            .iter()
+            .zip(weights.iter())
+            .zip(band_structure.kpoint_weights.iter())
+            .flat_map(|((eigens, weights_k), kw)| {
+                eigens
+                    .iter()
+                    .zip(weights_k.iter())
+                    .map(|(e, channels)| ((e - fermi) * HATREE_TO_EV, channels))
+                    .filter(|(e, _)| (min..=max).contains(e))
+                    .map(move |(e, channels)| (e, kw.value() * spin_coeff, *channels))
+            })
+            .collect::<Vec<(f64, f64, AngularChannels)>>();
+        let channel = |pick: fn(&AngularChannels) -> f64| {
+            let channel_samples = samples
+                .iter()
+                .map(|(e, w, channels)| (*e, w * pick(channels)))
+                .collect::<Vec<(f64, f64)>>();
+            BandMoments::from_weighted_samples(&channel_samples)
+        };
+        ChannelMoments {
+            s: channel(|c| c.s),
+            p: channel(|c| c.p),
+            d: channel(|c| c.d),
+            f: channel(|c| c.f),
+        }
+    };
+    Ok(match channels {
+        SpinData::NonPolarized(((kpts, fermi), weights)) => {
+            SpinData::NonPolarized(moments(kpts, **fermi, weights))
+        }
+        SpinData::SpinPolarized(spins) => SpinData::SpinPolarized(
+            spins.map(|((kpts, fermi), weights)| moments(kpts, **fermi, weights)),
+        ),
+    })
+}
+
+impl Display for ChannelMoments {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{:<8}{:>12}{:>12}{:>12}{:>12}{:>12}",
+            "channel", "states", "centre", "width", "skewness", "kurtosis"
+        )?;
+        [
+            ("s", &self.s),
+            ("p", &self.p),
+            ("d", &self.d),
+            ("f", &self.f),
+        ]
+        .iter()
+        .try_for_each(|(name, moments)| match moments {
+            Some(m) => writeln!(
+                f,
+                "{:<8}{:>12.4}{:>12.4}{:>12.4}{:>12.4}{:>12.4}",
+                name, m.states, m.centre, m.width, m.skewness, m.kurtosis
+            ),
+            None => writeln!(f, "{:<8}{:>12}", name, "-"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        fundamental::{AngularChannels, EigenvalueVec, KpointVec, SpinData, SpinLayoutError},
+        pdos_compute::{BroadeningKernel, calculate_pdos},
+        test_fixtures::sample_grid_band_structure,
+    };
+
+    use super::{BandMoments, eigenvalue_moments, pdos_moments};
+
+    #[test]
+    fn test_weighted_moments() {
+        let symmetric = BandMoments::from_weighted_samples(&[(-1.0, 1.0), (1.0, 1.0)]).unwrap();
+        assert_eq!(symmetric.states, 2.0);
+        assert_eq!(symmetric.centre, 0.0);
+        assert_eq!(symmetric.width, 1.0);
+        assert_eq!(symmetric.skewness, 0.0);
+        assert_eq!(symmetric.kurtosis, 1.0);
+        // A tail to higher energies
+        let skewed =
+            BandMoments::from_weighted_samples(&[(0.0, 3.0), (1.0, 1.0), (5.0, 0.5)]).unwrap();
+        assert!(skewed.skewness > 0.0);
+        assert!(BandMoments::from_weighted_samples(&[(1.0, 0.0)]).is_none());
+    }
+
+    #[test]
+    fn test_pdos_matches_eigenvalues() {
+        let band_structure = sample_grid_band_structure(8, true);
+        // d weight on the lower band, p weight on both
+        let channels: KpointVec<EigenvalueVec<AngularChannels>> = band_structure
+            .kpoints
+            .iter()
+            .map(|_| {
+                EigenvalueVec(vec![
+                    AngularChannels::new(0.0, 0.5, 1.0, 0.0),
+                    AngularChannels::new(0.0, 0.5, 0.0, 0.0),
+                ])
+            })
+            .collect();
+        let projected_weights = SpinData::SpinPolarized([channels.clone(), channels]);
+        let energy_grid = (0..=3000)
+            .map(|i| -10.0 + 0.01 * i as f64)
+            .collect::<Vec<f64>>();
+        let window = (-10.0, 20.0);
+        let pdos = calculate_pdos(
+            &band_structure,
+            &projected_weights,
+            &energy_grid,
+            &BroadeningKernel::Gaussian,
+            0.05,
+        )
+        .unwrap();
+        let exact = eigenvalue_moments(&band_structure, &projected_weights, window).unwrap();
+        pdos.map_pair(&exact, |pdos, exact| {
+            let broadened = pdos_moments(pdos, &energy_grid, window);
+            assert!(broadened.s.is_none() && exact.s.is_none());
+            let (b, e) = (broadened.d.unwrap(), exact.d.unwrap());
+            assert!((b.states - 1.0).abs() < 1e-6, "{b:?}");
+            assert!((e.states - 1.0).abs() < 1e-12, "{e:?}");
+            assert!((b.centre - e.centre).abs() < 1e-6);
+            // Broadening adds σ² to the variance
+            assert!((b.width.powi(2) - e.width.powi(2) - 0.05_f64.powi(2)).abs() < 1e-4);
+            let (b, e) = (broadened.p.unwrap(), exact.p.unwrap());
+            assert!((b.centre - e.centre).abs() < 1e-6);
+        });
+        // Non-polarized weights on spin polarized bands
+        let SpinData::SpinPolarized([channels, _]) = projected_weights else {
+            panic!("spin polarized expected");
+        };
+        assert_eq!(
+            eigenvalue_moments(&band_structure, &SpinData::NonPolarized(channels), window),
+            Err(SpinLayoutError("eigenvalues", "projected weights"))
+        );
+    }
+}
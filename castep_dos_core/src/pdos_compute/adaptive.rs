@@ -0,0 +1,204 @@
+use serde::{Deserialize, Serialize};
+
+use crate::fundamental::{BandStructure, EigenvalueVec, KpointVec, SpinData};
+
+use super::{KpointGrid, fermi_shifted_eigenvalues};
+
+/// Adaptive broadening (Yates, Wang, Vanderbilt and Souza, 2007), as in
+/// OptaDOS: each state is broadened with the width `scale * |∇ε| * Δk`,
+/// where `Δk` is the spacing of the Monkhorst-Pack grid. Steep bands are
+/// smeared over the energy range they sweep between grid points, flat bands
+/// keep sharp peaks.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AdaptiveBroadening {
+    /// Factor of the band gradient
+    pub scale: f64,
+    /// Smallest width in eV, keeps flat bands and band extrema finite
+    pub min_width: f64,
+    /// Largest width in eV
+    pub max_width: f64,
+}
+
+impl Default for AdaptiveBroadening {
+    fn default() -> Self {
+        Self {
+            scale: 0.4,
+            min_width: 0.01,
+            max_width: 1.0,
+        }
+    }
+}
+
+impl AdaptiveBroadening {
+    /// Broadening width in eV of every state, in the layout of
+    /// `BandStructure::eigenvalues`.
+    /// Band gradients are central differences between the neighbouring
+    /// grid points along each axis. Axes with one or two grid points, e.g.
+    /// the vacuum direction of a slab, contribute no gradient and are left
+    /// out of the grid spacing.
+    pub fn widths(
+        &self,
+        band_structure: &BandStructure,
+        grid: &KpointGrid,
+    ) -> SpinData<KpointVec<EigenvalueVec<f64>>> {
+        let lattice_vectors = band_structure.lattice_vectors;
+        let spacing = grid_spacing(grid);
+        fermi_shifted_eigenvalues(band_structure).map(|eigens| {
+            grid.coordinates
+                .iter()
+                .enumerate()
+                .map(|(k, m)| {
+                    let m = m.map(|x| x as i64);
+                    let neighbours = [0, 1, 2].map(|axis| {
+                        let mut forward = m;
+                        let mut backward = m;
+                        forward[axis] += 1;
+                        backward[axis] -= 1;
+                        (grid.kpoint(forward), grid.kpoint(backward))
+                    });
+                    (0..eigens[k].len())
+                        .map(|band| {
+                            // ∂ε/∂f_i in fractional coordinates f_i = m_i / N_i
+                            let derivatives = [0, 1, 2].map(|axis| {
+                                let (forward, backward) = neighbours[axis];
+                                grid.size[axis] as f64
+                                    * (eigens[forward][band] - eigens[backward][band])
+                                    / 2.0
+                            });
+                            // ∇ε = Σ_i ∂ε/∂f_i a_i / 2π, the 2π cancels with
+                            // the one left out of the grid spacing
+                            let gradient = [0, 1, 2].map(|x| {
+                                (0..3)
+                                    .map(|i| derivatives[i] * lattice_vectors[i][x])
+                                    .sum::<f64>()
+                            });
+                            let norm = gradient.iter().map(|g| g * g).sum::<f64>().sqrt();
+                            (self.scale * norm * spacing)
+                                .min(self.max_width)
+                                .max(self.min_width)
+                        })
+                        .collect()
+                })
+                .collect()
+        })
+    }
+}
+
+/// Mean length of the grid steps along the axes with more than two
+/// points, without the 2π factor
+fn grid_spacing(grid: &KpointGrid) -> f64 {
+    let steps = (0..3)
+        .filter(|&axis| grid.size[axis] > 2)
+        .map(|axis| {
+            let b = grid.reciprocal_vectors[axis];
+            (b[0] * b[0] + b[1] * b[1] + b[2] * b[2]).sqrt() / grid.size[axis] as f64
+        })
+        .collect::<Vec<f64>>();
+    if steps.is_empty() {
+        0.0
+    } else {
+        steps.iter().sum::<f64>() / steps.len() as f64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f64::consts::PI;
+
+    use crate::{
+        fundamental::{AngularChannels, EigenvalueVec, HATREE_TO_EV, KpointVec, SpinData},
+        pdos_compute::{BroadeningKernel, KpointGrid, calculate_pdos, calculate_pdos_adaptive},
+        test_fixtures::sample_grid_band_structure,
+    };
+
+    use super::AdaptiveBroadening;
+
+    #[test]
+    fn test_widths() {
+        let n = 8;
+        let band_structure = sample_grid_band_structure(n, true);
+        let grid = KpointGrid::from_band_structure(&band_structure).unwrap();
+        let adaptive = AdaptiveBroadening {
+            scale: 0.4,
+            min_width: 0.0,
+            max_width: f64::INFINITY,
+        };
+        let widths = adaptive.widths(&band_structure, &grid);
+        // ε = -0.1 (cos 2πk1 + cos 2πk2): the central difference of the
+        // cosine is sin 2πk sin(2π/N), grid spacing times lattice constant is 1
+        widths.for_each(|widths| {
+            band_structure
+                .kpoints
+                .iter()
+                .zip(widths.iter())
+                .for_each(|(kpt, widths_k)| {
+                    let [k1, k2, _] = kpt.coords;
+                    let sines =
+                        ((2.0 * PI * k1).sin().powi(2) + (2.0 * PI * k2).sin().powi(2)).sqrt();
+                    let expected = 0.4 * 0.1 * HATREE_TO_EV * (2.0 * PI / n as f64).sin() * sines;
+                    // Both bands have the same dispersion
+                    widths_k
+                        .iter()
+                        .for_each(|w| assert!((w - expected).abs() < 1e-9, "{w} {expected}"));
+                });
+        });
+        let clamped = AdaptiveBroadening {
+            scale: 0.4,
+            min_width: 0.5,
+            max_width: 0.6,
+        }
+        .widths(&band_structure, &grid);
+        clamped.for_each(|widths| {
+            widths
+                .iter()
+                .flat_map(|widths_k| widths_k.iter())
+                .for_each(|&w| assert!((0.5..=0.6).contains(&w)));
+        });
+    }
+
+    #[test]
+    fn test_fixed_width_matches_smearing() {
+        let band_structure = sample_grid_band_structure(4, false);
+        let grid = KpointGrid::from_band_structure(&band_structure).unwrap();
+        let channels: KpointVec<EigenvalueVec<AngularChannels>> = band_structure
+            .kpoints
+            .iter()
+            .map(|_| EigenvalueVec(vec![AngularChannels::new(1.0, 0.5, 0.0, 0.0); 2]))
+            .collect();
+        let projected_weights = SpinData::NonPolarized(channels);
+        let energy_grid = (0..200)
+            .map(|i| -8.0 + 0.1 * i as f64)
+            .collect::<Vec<f64>>();
+        let widths = AdaptiveBroadening {
+            scale: 0.4,
+            min_width: 0.2,
+            max_width: 0.2,
+        }
+        .widths(&band_structure, &grid);
+        let kernel = BroadeningKernel::Gaussian;
+        let adaptive = calculate_pdos_adaptive(
+            &band_structure,
+            &projected_weights,
+            &energy_grid,
+            &kernel,
+            &widths,
+        )
+        .unwrap();
+        let fixed = calculate_pdos(
+            &band_structure,
+            &projected_weights,
+            &energy_grid,
+            &kernel,
+            0.2,
+        )
+        .unwrap();
+        adaptive.map_pair(&fixed, |adaptive, fixed| {
+            adaptive
+                .s
+                .iter()
+                .zip(fixed.s.iter())
+                .for_each(|(a, f)| assert!((a - f).abs() < 1e-12));
+        });
+    }
+}
@@ -0,0 +1,317 @@
+use ndarray::{Array1, Array2, s};
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+
+use crate::fundamental::{
+    AngularChannels, BandStructure, EigenvalueVec, KpointVec, KpointWeight, SpinData,
+    SpinLayoutError,
+};
+
+use super::{BroadeningKernel, PDOSResult, fermi_shifted_eigenvalues, merge_spins, split_spins};
+
+/// Broadening of one state on the energy grid
+#[derive(Debug, Clone, PartialEq)]
+struct StateFactors {
+    /// Grid index of the first value
+    start: usize,
+    /// Kernel values times the k-point weight and the spin degeneracy
+    values: Array1<f64>,
+}
+
+/// Broadening of every eigenvalue on the energy grid, applied to the
+/// weights of any number of projectors in a single pass. The factors do
+/// not depend on the projector, so computing them again for each one is
+/// wasted work on large cells.
+///
+/// The factors of a k-point are built when the pass reaches it, shared by
+/// all projectors and dropped afterwards. Each state keeps the grid points
+/// within `BroadeningKernel::cutoff` of its eigenvalue, and the whole grid
+/// for kernels with long tails, so the memory is bounded by the states of
+/// one k-point on the whole grid for each thread.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BroadeningFactors {
+    /// Energy grid in eV
+    energy_grid: Vec<f64>,
+    /// Shape of the broadening
+    kernel: BroadeningKernel,
+    /// K-point weights of the bands
+    kpoint_weights: KpointVec<KpointWeight>,
+    /// Eigenvalue relative to the Fermi energy and broadening width of each
+    /// state in eV, in the layout of `BandStructure::eigenvalues`
+    states: SpinData<KpointVec<EigenvalueVec<(f64, f64)>>>,
+}
+
+impl BroadeningFactors {
+    /// Each eigenvalue is broadened by `kernel` with width `smearing` in
+    /// eV, see `calculate_pdos`
+    pub fn new(
+        band_structure: &BandStructure,
+        energy_grid: &[f64],
+        kernel: &BroadeningKernel,
+        smearing: f64,
+    ) -> Self {
+        let widths = band_structure.eigenvalues.map(|kpts| {
+            kpts.iter()
+                .map(|eigens| eigens.iter().map(|_| smearing).collect())
+                .collect()
+        });
+        Self::adaptive(band_structure, energy_grid, kernel, &widths)
+    }
+
+    /// Same as `new`, with a broadening width in eV for each state, see
+    /// `calculate_pdos_adaptive`
+    pub fn adaptive(
+        band_structure: &BandStructure,
+        energy_grid: &[f64],
+        kernel: &BroadeningKernel,
+        widths: &SpinData<KpointVec<EigenvalueVec<f64>>>,
+    ) -> Self {
+        let states =
+            fermi_shifted_eigenvalues(band_structure).map_pair(widths, |eigens, widths| {
+                eigens
+                    .iter()
+                    .zip(widths.iter())
+                    .map(|(eigen_k, widths_k)| {
+                        eigen_k
+                            .iter()
+                            .copied()
+                            .zip(widths_k.iter().copied())
+                            .collect()
+                    })
+                    .collect()
+            });
+        Self {
+            energy_grid: energy_grid.to_vec(),
+            kernel: *kernel,
+            kpoint_weights: band_structure.kpoint_weights.clone(),
+            states,
+        }
+    }
+
+    /// PDOS of each projector, in the order of `projected_weights`, from a
+    /// single pass over the states. Fails when a projector is not in the
+    /// spin layout of the bands.
+    pub fn apply(
+        &self,
+        projected_weights: &[&SpinData<KpointVec<EigenvalueVec<AngularChannels>>>],
+    ) -> Result<Vec<SpinData<PDOSResult>>, SpinLayoutError> {
+        let weights = split_spins(&self.states, projected_weights)?;
+        let spin_coeff = match self.states {
+            SpinData::NonPolarized(_) => 2.0,
+            SpinData::SpinPolarized(_) => 1.0,
+        };
+        Ok(merge_spins(
+            self.states.map_pair(&weights, |states, weights| {
+                self.apply_spin(states, weights, spin_coeff)
+            }),
+        ))
+    }
+
+    /// PDOS of one spin for each projector
+    fn apply_spin(
+        &self,
+        states: &KpointVec<EigenvalueVec<(f64, f64)>>,
+        projected_weights: &[&KpointVec<EigenvalueVec<AngularChannels>>],
+        spin_coeff: f64,
+    ) -> Vec<PDOSResult> {
+        let grid_len = self.energy_grid.len();
+        let empty = || vec![Array2::<f64>::zeros((4, grid_len)); projected_weights.len()];
+        let results = states
+            .par_iter()
+            .zip(self.kpoint_weights.par_iter())
+            .enumerate()
+            .fold(empty, |mut results, (k, (states_k, kw))| {
+                let factors_k = state_factors(
+                    states_k,
+                    kw.value() * spin_coeff,
+                    &self.energy_grid,
+                    &self.kernel,
+                );
+                factors_k.iter().enumerate().for_each(|(band, state)| {
+                    let end = state.start + state.values.len();
+                    projected_weights.iter().zip(results.iter_mut()).for_each(
+                        |(weights, result)| {
+                            let am_weights = &weights[k][band];
+                            [am_weights.s, am_weights.p, am_weights.d, am_weights.f]
+                                .into_iter()
+                                .enumerate()
+                                .filter(|(_, weight)| *weight != 0.0)
+                                .for_each(|(channel, weight)| {
+                                    result
+                                        .slice_mut(s![channel, state.start..end])
+                                        .scaled_add(weight, &state.values);
+                                });
+                        },
+                    );
+                });
+                results
+            })
+            .reduce(empty, |mut acc, results| {
+                acc.iter_mut()
+                    .zip(results)
+                    .for_each(|(acc, result)| *acc += &result);
+                acc
+            });
+        results
+            .into_iter()
+            .map(|result| PDOSResult {
+                s: result.row(0).to_vec(),
+                p: result.row(1).to_vec(),
+                d: result.row(2).to_vec(),
+                f: result.row(3).to_vec(),
+            })
+            .collect()
+    }
+}
+
+/// Factors of the states of one k-point, each scaled by `coeff`.
+/// `energy_grid` is sorted.
+fn state_factors(
+    states_k: &EigenvalueVec<(f64, f64)>,
+    coeff: f64,
+    energy_grid: &[f64],
+    kernel: &BroadeningKernel,
+) -> Vec<StateFactors> {
+    states_k
+        .iter()
+        .map(|&(eigen, width)| {
+            let (start, end) = match kernel.cutoff(width) {
+                Some(cutoff) => (
+                    energy_grid.partition_point(|&e| e < eigen - cutoff),
+                    energy_grid.partition_point(|&e| e <= eigen + cutoff),
+                ),
+                None => (0, energy_grid.len()),
+            };
+            StateFactors {
+                start,
+                values: energy_grid[start..end]
+                    .iter()
+                    .map(|e| coeff * kernel.value(e - eigen, width))
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        fundamental::{AngularChannels, BandStructure, HATREE_TO_EV, SpinData, SpinLayoutError},
+        pdos_compute::{BroadeningKernel, PDOSResult},
+        test_fixtures::sample_grid_band_structure,
+    };
+
+    use super::BroadeningFactors;
+
+    /// Every channel of every spin, one after another
+    fn flatten(result: &SpinData<PDOSResult>) -> Vec<f64> {
+        let mut values = Vec::new();
+        result.for_each(|pdos| {
+            [&pdos.s, &pdos.p, &pdos.d, &pdos.f]
+                .into_iter()
+                .for_each(|channel| values.extend(channel))
+        });
+        values
+    }
+
+    /// The same weights on every state, broadened on the whole grid
+    fn full_grid_pdos(
+        band_structure: &BandStructure,
+        channels: AngularChannels,
+        energy_grid: &[f64],
+        kernel: &BroadeningKernel,
+        smearing: f64,
+    ) -> SpinData<PDOSResult> {
+        let spin_coeff = match band_structure.eigenvalues {
+            SpinData::NonPolarized(_) => 2.0,
+            SpinData::SpinPolarized(_) => 1.0,
+        };
+        band_structure.eigenvalues.map(|kpts| {
+            let dos = energy_grid
+                .iter()
+                .map(|e| {
+                    kpts.iter()
+                        .zip(band_structure.kpoint_weights.iter())
+                        .flat_map(|(eigens, kw)| {
+                            eigens.iter().map(move |eigen| {
+                                kw.value()
+                                    * spin_coeff
+                                    * kernel.value(e - eigen * HATREE_TO_EV, smearing)
+                            })
+                        })
+                        .sum::<f64>()
+                })
+                .collect::<Vec<f64>>();
+            let scaled = |weight: f64| dos.iter().map(|v| v * weight).collect();
+            PDOSResult {
+                s: scaled(channels.s),
+                p: scaled(channels.p),
+                d: scaled(channels.d),
+                f: scaled(channels.f),
+            }
+        })
+    }
+
+    #[test]
+    fn test_batched_broadening() {
+        // The bands span -0.2 to 0.5 Ha, Fermi energy at 0
+        let energy_grid = (0..=4000)
+            .map(|i| -20.0 + 0.01 * i as f64)
+            .collect::<Vec<f64>>();
+        let channels = [
+            AngularChannels::new(0.5, 0.0, 0.0, 0.0),
+            AngularChannels::new(0.1, 0.2, 0.3, 0.4),
+        ];
+        [false, true].into_iter().for_each(|spin_polarized| {
+            let band_structure = sample_grid_band_structure(8, spin_polarized);
+            let weights = channels.map(|channels| {
+                band_structure.eigenvalues.map(|kpts| {
+                    kpts.iter()
+                        .map(|eigens| eigens.iter().map(|_| channels).collect())
+                        .collect()
+                })
+            });
+            [
+                (BroadeningKernel::Gaussian, 0.05),
+                (BroadeningKernel::FermiDirac, 0.02),
+                (BroadeningKernel::Lorentzian, 0.1),
+            ]
+            .into_iter()
+            .for_each(|(kernel, smearing)| {
+                let results =
+                    BroadeningFactors::new(&band_structure, &energy_grid, &kernel, smearing)
+                        .apply(&[&weights[0], &weights[1]])
+                        .unwrap();
+                assert_eq!(results.len(), 2);
+                // Same as one full-grid kernel per state, up to the cutoff
+                results.iter().zip(channels).for_each(|(result, channels)| {
+                    let expected =
+                        full_grid_pdos(&band_structure, channels, &energy_grid, &kernel, smearing);
+                    let (actual, expected) = (flatten(result), flatten(&expected));
+                    assert_eq!(actual.len(), expected.len());
+                    actual.iter().zip(expected).for_each(|(a, e)| {
+                        assert!((a - e).abs() < 1e-9, "{kernel:?}: {a} {e}");
+                    });
+                });
+            });
+        });
+        // Non-polarized weights on spin polarized bands
+        let band_structure = sample_grid_band_structure(8, true);
+        let weights = sample_grid_band_structure(8, false)
+            .eigenvalues
+            .map(|kpts| {
+                kpts.iter()
+                    .map(|eigens| eigens.iter().map(|_| channels[0]).collect())
+                    .collect()
+            });
+        assert_eq!(
+            BroadeningFactors::new(
+                &band_structure,
+                &energy_grid,
+                &BroadeningKernel::Gaussian,
+                0.05
+            )
+            .apply(&[&weights]),
+            Err(SpinLayoutError("eigenvalues", "projected weights"))
+        );
+    }
+}
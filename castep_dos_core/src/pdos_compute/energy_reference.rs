@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+
+use crate::fundamental::{BandStructure, SpinData};
+
+use super::{FermiLevelError, FermiLevelSolver, HATREE_TO_EV};
+
+/// Zero of the energy axis of each spin. `.bands` of spin polarized runs
+/// declares a Fermi energy for each spin, and shifting each spin by its own
+/// one plots up and down against different zeros.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EnergyReference {
+    /// The Fermi energy of each spin
+    #[default]
+    PerSpin,
+    /// The higher Fermi energy of the two spins
+    Highest,
+    /// A chemical potential of both spins recomputed from the total
+    /// electron count, see `FermiLevelSolver::solve_common`
+    Common,
+    /// No shift, the absolute eigenvalues of `CASTEP`
+    Absolute,
+    /// A given energy
+    Value {
+        /// Absolute energy in eV
+        energy: f64,
+    },
+}
+
+impl EnergyReference {
+    /// Reference energy of each spin in Hartree, the layout follows
+    /// `BandStructure::fermi_energy`. `solver` is only used by `Common`.
+    pub fn resolve(
+        &self,
+        band_structure: &BandStructure,
+        solver: &FermiLevelSolver,
+    ) -> Result<SpinData<f64>, FermiLevelError> {
+        let fermi_energy = &band_structure.fermi_energy;
+        let uniform = |value: f64| fermi_energy.map(|_| value);
+        match self {
+            EnergyReference::PerSpin => Ok(fermi_energy.clone()),
+            EnergyReference::Highest => Ok(uniform(match fermi_energy {
+                SpinData::NonPolarized(fermi) => *fermi,
+                SpinData::SpinPolarized([up, down]) => up.max(*down),
+            })),
+            EnergyReference::Common => solver.solve_common(band_structure).map(uniform),
+            EnergyReference::Absolute => Ok(uniform(0.0)),
+            EnergyReference::Value { energy } => Ok(uniform(energy / HATREE_TO_EV)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        fundamental::{HATREE_TO_EV, SpinData},
+        pdos_compute::FermiLevelSolver,
+        test_fixtures::sample_grid_band_structure,
+    };
+
+    use super::EnergyReference;
+
+    #[test]
+    fn test_resolve_reference() {
+        let mut band_structure = sample_grid_band_structure(8, true);
+        band_structure.fermi_energy = SpinData::SpinPolarized([0.01, 0.02]);
+        let solver = FermiLevelSolver::default();
+        let resolve = |reference: EnergyReference| reference.resolve(&band_structure, &solver);
+        assert_eq!(
+            resolve(EnergyReference::PerSpin),
+            Ok(SpinData::SpinPolarized([0.01, 0.02]))
+        );
+        assert_eq!(
+            resolve(EnergyReference::Highest),
+            Ok(SpinData::SpinPolarized([0.02, 0.02]))
+        );
+        assert_eq!(
+            resolve(EnergyReference::Absolute),
+            Ok(SpinData::SpinPolarized([0.0, 0.0]))
+        );
+        let SpinData::SpinPolarized([up, down]) =
+            resolve(EnergyReference::Value { energy: 2.0 }).unwrap()
+        else {
+            panic!("spin polarized expected");
+        };
+        assert_eq!(up, down);
+        assert!((up * HATREE_TO_EV - 2.0).abs() < 1e-12);
+        assert_eq!(
+            resolve(EnergyReference::Common),
+            solver
+                .solve_common(&band_structure)
+                .map(|mu| SpinData::SpinPolarized([mu, mu]))
+        );
+    }
+}
@@ -0,0 +1,297 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::fundamental::{
+    BandStructure, EigenvalueVec, KpointVec, KpointWeight, SpinData, SpinLayoutError,
+};
+
+use super::{BroadeningKernel, HATREE_TO_EV};
+
+/// Bisection stops when the bracket is narrower than this, in Hartree
+const ENERGY_TOLERANCE: f64 = 1e-12;
+/// Upper bound of bisection steps and bracket expansions
+const MAX_ITERATIONS: usize = 200;
+
+#[derive(Debug, Error, PartialEq)]
+/// The chemical potential can not be solved
+pub enum FermiLevelError {
+    /// The occupation function needs a finite width
+    #[error("The smearing width of the occupation must be positive, got {0} eV")]
+    NonPositiveWidth(f64),
+    /// More electrons than states, or a negative count
+    #[error("{count} electrons do not fit in the {capacity} states of the bands")]
+    ElectronCount {
+        /// Declared number of electrons
+        count: f64,
+        /// Number of states of the bands
+        capacity: f64,
+    },
+    /// No energy has the declared number of electrons below it
+    #[error("No chemical potential gives {count} electrons")]
+    NoSolution {
+        /// Declared number of electrons
+        count: f64,
+    },
+    /// The electron count is not in the spin layout of the eigenvalues
+    #[error("{0}")]
+    SpinLayout(#[from] SpinLayoutError),
+}
+
+/// Finds the chemical potential where the occupied states hold the
+/// electron count of `.bands`, to replace the Fermi energy of the header.
+/// The header value comes from the SCF k-point set, and is often off
+/// for non-SCF DOS runs on denser k-point sets.
+///
+/// `[fermi_level]` in config, the occupation function in
+/// `[fermi_level.occupation]` takes the same `type` as the broadening kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct FermiLevelSolver {
+    /// Occupation function, the integral of this kernel
+    pub occupation: BroadeningKernel,
+    /// Width of the occupation function in eV, kT for `FermiDirac`
+    pub smearing: f64,
+}
+
+impl Default for FermiLevelSolver {
+    fn default() -> Self {
+        Self {
+            occupation: BroadeningKernel::Gaussian,
+            smearing: 0.1,
+        }
+    }
+}
+
+impl FermiLevelSolver {
+    /// Constructor
+    pub fn new(occupation: BroadeningKernel, smearing: f64) -> Self {
+        Self {
+            occupation,
+            smearing,
+        }
+    }
+
+    /// Chemical potential in Hartree. Spin polarized channels are solved
+    /// separately with their own electron count, as `.bands` declares
+    /// one of each. Inside a gap much wider than the smearing, any energy
+    /// holds the count and the result is only one of them.
+    pub fn solve(&self, band_structure: &BandStructure) -> Result<SpinData<f64>, FermiLevelError> {
+        if self.smearing <= 0.0 {
+            return Err(FermiLevelError::NonPositiveWidth(self.smearing));
+        }
+        let weights = &band_structure.kpoint_weights;
+        let channels = band_structure
+            .eigenvalues
+            .zip(&band_structure.electron_count)
+            .ok_or(SpinLayoutError("eigenvalues", "electron_count"))?;
+        match channels {
+            SpinData::NonPolarized((kpts, count)) => self
+                .solve_channels(&[kpts], weights, 2.0, *count)
+                .map(SpinData::NonPolarized),
+            SpinData::SpinPolarized([(up, count_up), (down, count_down)]) => {
+                Ok(SpinData::SpinPolarized([
+                    self.solve_channels(&[up], weights, 1.0, *count_up)?,
+                    self.solve_channels(&[down], weights, 1.0, *count_down)?,
+                ]))
+            }
+        }
+    }
+
+    /// One chemical potential in Hartree for both spins, holding the total
+    /// electron count. Same as `solve` without spin polarization.
+    pub fn solve_common(&self, band_structure: &BandStructure) -> Result<f64, FermiLevelError> {
+        if self.smearing <= 0.0 {
+            return Err(FermiLevelError::NonPositiveWidth(self.smearing));
+        }
+        let weights = &band_structure.kpoint_weights;
+        let channels = band_structure
+            .eigenvalues
+            .zip(&band_structure.electron_count)
+            .ok_or(SpinLayoutError("eigenvalues", "electron_count"))?;
+        match channels {
+            SpinData::NonPolarized((kpts, count)) => {
+                self.solve_channels(&[kpts], weights, 2.0, *count)
+            }
+            SpinData::SpinPolarized([(up, count_up), (down, count_down)]) => {
+                self.solve_channels(&[up, down], weights, 1.0, count_up + count_down)
+            }
+        }
+    }
+
+    /// Electrons below `mu` (Hartree) in one channel, each state holds
+    /// `spin_coeff` electrons
+    pub fn electrons_below(
+        &self,
+        kpts: &KpointVec<EigenvalueVec<f64>>,
+        kpoint_weights: &KpointVec<KpointWeight>,
+        spin_coeff: f64,
+        mu: f64,
+    ) -> f64 {
+        kpts.iter()
+            .zip(kpoint_weights.iter())
+            .map(|(eigens, kw)| {
+                kw.value()
+                    * eigens
+                        .iter()
+                        .map(|e| {
+                            self.occupation
+                                .occupation((e - mu) * HATREE_TO_EV, self.smearing)
+                        })
+                        .sum::<f64>()
+            })
+            .sum::<f64>()
+            * spin_coeff
+    }
+
+    /// Chemical potential of `channels` sharing it
+    fn solve_channels(
+        &self,
+        channels: &[&KpointVec<EigenvalueVec<f64>>],
+        kpoint_weights: &KpointVec<KpointWeight>,
+        spin_coeff: f64,
+        count: f64,
+    ) -> Result<f64, FermiLevelError> {
+        let capacity = channels
+            .iter()
+            .flat_map(|kpts| kpts.iter().zip(kpoint_weights.iter()))
+            .map(|(eigens, kw)| kw.value() * eigens.len() as f64)
+            .sum::<f64>()
+            * spin_coeff;
+        if !(0.0..=capacity).contains(&count) {
+            return Err(FermiLevelError::ElectronCount { count, capacity });
+        }
+        let excess = |mu: f64| {
+            channels
+                .iter()
+                .map(|kpts| self.electrons_below(kpts, kpoint_weights, spin_coeff, mu))
+                .sum::<f64>()
+                - count
+        };
+        let (min, max) = channels
+            .iter()
+            .flat_map(|kpts| kpts.iter())
+            .flat_map(|eigens| eigens.iter())
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &e| {
+                (min.min(e), max.max(e))
+            });
+        // Widen the bracket until the count is inside
+        let mut step = self.smearing / HATREE_TO_EV;
+        let (mut lower, mut upper) = (min - step, max + step);
+        let mut expansions = 0;
+        while excess(lower) > 0.0 || excess(upper) < 0.0 {
+            if expansions == MAX_ITERATIONS {
+                return Err(FermiLevelError::NoSolution { count });
+            }
+            step *= 2.0;
+            (lower, upper) = (min - step, max + step);
+            expansions += 1;
+        }
+        let mut iterations = 0;
+        while upper - lower > ENERGY_TOLERANCE && iterations < MAX_ITERATIONS {
+            let mid = 0.5 * (lower + upper);
+            if excess(mid) < 0.0 {
+                lower = mid;
+            } else {
+                upper = mid;
+            }
+            iterations += 1;
+        }
+        Ok(0.5 * (lower + upper))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        fundamental::{SpinData, SpinLayoutError},
+        pdos_compute::BroadeningKernel,
+        test_fixtures::sample_grid_band_structure,
+    };
+
+    use super::{FermiLevelError, FermiLevelSolver};
+
+    #[test]
+    fn test_solve_fermi_level() {
+        // Half filled band symmetric about 0 Ha
+        let mut band_structure = sample_grid_band_structure(8, false);
+        // The header value is not used
+        band_structure.fermi_energy = SpinData::NonPolarized(0.05);
+        [
+            BroadeningKernel::Gaussian,
+            BroadeningKernel::FermiDirac,
+            BroadeningKernel::MethfesselPaxton { order: 1 },
+        ]
+        .into_iter()
+        .for_each(|occupation| {
+            let SpinData::NonPolarized(mu) = FermiLevelSolver::new(occupation, 0.1)
+                .solve(&band_structure)
+                .unwrap()
+            else {
+                panic!("non polarized expected");
+            };
+            assert!(mu.abs() < 1e-9, "{occupation:?}: {mu}");
+        });
+        let solver = FermiLevelSolver::default();
+        // The two bands overlap between 0.1 and 0.2 Ha
+        band_structure.electron_count = SpinData::NonPolarized(2.0);
+        let SpinData::NonPolarized(mu) = solver.solve(&band_structure).unwrap() else {
+            panic!("non polarized expected");
+        };
+        assert!(mu > 0.1 && mu < 0.2, "{mu}");
+        band_structure.electron_count = SpinData::NonPolarized(4.5);
+        assert_eq!(
+            solver.solve(&band_structure),
+            Err(FermiLevelError::ElectronCount {
+                count: 4.5,
+                capacity: 4.0
+            })
+        );
+        assert_eq!(
+            FermiLevelSolver::new(BroadeningKernel::Gaussian, 0.0).solve(&band_structure),
+            Err(FermiLevelError::NonPositiveWidth(0.0))
+        );
+    }
+
+    #[test]
+    fn test_solve_spin_polarized() {
+        let band_structure = sample_grid_band_structure(8, true);
+        let solver = FermiLevelSolver::default();
+        let SpinData::SpinPolarized(mu) = solver.solve(&band_structure).unwrap() else {
+            panic!("spin polarized expected");
+        };
+        let (SpinData::SpinPolarized(kpts), SpinData::SpinPolarized(counts)) =
+            (&band_structure.eigenvalues, &band_structure.electron_count)
+        else {
+            panic!("spin polarized expected");
+        };
+        (0..2).for_each(|spin| {
+            let electrons =
+                solver.electrons_below(&kpts[spin], &band_structure.kpoint_weights, 1.0, mu[spin]);
+            assert!((electrons - counts[spin]).abs() < 1e-9, "{electrons}");
+        });
+        // The common one holds the electrons of both spins, between the two
+        let common = solver.solve_common(&band_structure).unwrap();
+        let electrons = (0..2)
+            .map(|spin| {
+                solver.electrons_below(&kpts[spin], &band_structure.kpoint_weights, 1.0, common)
+            })
+            .sum::<f64>();
+        assert!(
+            (electrons - counts[0] - counts[1]).abs() < 1e-9,
+            "{electrons}"
+        );
+        assert!(common > mu[0].min(mu[1]) - 1e-9 && common < mu[0].max(mu[1]) + 1e-9);
+        // One electron count for two spin channels
+        let mut malformed = band_structure;
+        malformed.electron_count = SpinData::NonPolarized(4.0);
+        let mismatch = SpinLayoutError("eigenvalues", "electron_count");
+        assert_eq!(
+            solver.solve(&malformed),
+            Err(FermiLevelError::SpinLayout(mismatch))
+        );
+        assert_eq!(
+            solver.solve_common(&malformed),
+            Err(FermiLevelError::SpinLayout(mismatch))
+        );
+    }
+}
@@ -0,0 +1,359 @@
+use std::sync::Arc;
+
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+use thiserror::Error;
+
+use crate::fundamental::{
+    AngularChannels, BandStructure, EigenvalueVec, KpointVec, KpointWeight, SpinData,
+    SpinLayoutError,
+};
+
+use super::{BroadeningKernel, PDOSResult, fermi_shifted_eigenvalues, merge_spins, split_spins};
+
+/// Relative deviation of a grid step from the mean one still taken as
+/// uniform
+const UNIFORM_TOLERANCE: f64 = 1e-6;
+
+#[derive(Debug, Error, PartialEq)]
+/// The histogram method can not run on the input
+pub enum HistogramError {
+    /// The convolution needs evenly spaced grid points
+    #[error("The fast smearing needs a uniform, increasing energy grid of at least two points")]
+    NonUniformGrid,
+    /// The fine grid needs at least one bin per grid step
+    #[error("The number of subdivisions of a grid step must be positive")]
+    ZeroSubdivisions,
+    /// A projector is not in the spin layout of the eigenvalues
+    #[error("{0}")]
+    SpinLayout(#[from] SpinLayoutError),
+}
+
+/// Fast path of `calculate_pdos` for uniform energy grids, for all
+/// projectors together. The weighted eigenvalues are first binned on a
+/// grid `subdivisions` times finer than `energy_grid`, shared linearly
+/// between the two nearest bins, then the bins are convolved once with
+/// the kernel by FFT. The cost is O(N_states + N_fine log N_fine) instead
+/// of O(N_states × N_grid).
+///
+/// The binning moves each state by less than a fine step h. For a
+/// Gaussian of width σ the DOS differs from `calculate_pdos` by at most
+/// (h/σ)²/8 of its peak height, 8e-5 with the default 100 points per
+/// eV, 0.1 eV smearing and 4 subdivisions. The sharper Lorentzian and
+/// Methfessel-Paxton shapes of the same width differ by a few times
+/// that. The fine step must stay well below the smearing width.
+pub fn calculate_pdos_histogram(
+    band_structure: &BandStructure,
+    projected_weights: &[&SpinData<KpointVec<EigenvalueVec<AngularChannels>>>],
+    energy_grid: &[f64],
+    kernel: &BroadeningKernel,
+    smearing: f64,
+    subdivisions: usize,
+) -> Result<Vec<SpinData<PDOSResult>>, HistogramError> {
+    if subdivisions == 0 {
+        return Err(HistogramError::ZeroSubdivisions);
+    }
+    let step = uniform_step(energy_grid).ok_or(HistogramError::NonUniformGrid)?;
+    let eigenvalues = fermi_shifted_eigenvalues(band_structure);
+    let (eigen_min, eigen_max) = {
+        let mut bounds = (f64::INFINITY, f64::NEG_INFINITY);
+        eigenvalues.for_each(|kpts| {
+            kpts.iter()
+                .flat_map(|eigens| eigens.iter())
+                .for_each(|&e| bounds = (bounds.0.min(e), bounds.1.max(e)))
+        });
+        bounds
+    };
+    let convolution = Convolution::new(
+        energy_grid,
+        step / subdivisions as f64,
+        subdivisions,
+        (eigen_min, eigen_max),
+        kernel,
+        smearing,
+    );
+    let kpoint_weights = &band_structure.kpoint_weights;
+    let weights = split_spins(&eigenvalues, projected_weights)?;
+    Ok(merge_spins(eigenvalues.map_pair_with_spin(
+        &weights,
+        |eigens, weights| convolution.spin_pdos(eigens, weights, kpoint_weights, 2.0),
+        |eigens, weights| convolution.spin_pdos(eigens, weights, kpoint_weights, 1.0),
+    )))
+}
+
+/// Step of a uniform, increasing grid of at least two points
+fn uniform_step(energy_grid: &[f64]) -> Option<f64> {
+    let (first, last) = (energy_grid.first()?, energy_grid.last()?);
+    let intervals = energy_grid.len().checked_sub(1).filter(|n| *n > 0)?;
+    let step = (last - first) / intervals as f64;
+    (step > 0.0
+        && energy_grid
+            .iter()
+            .enumerate()
+            .all(|(i, e)| (e - first - i as f64 * step).abs() <= UNIFORM_TOLERANCE * step))
+    .then_some(step)
+}
+
+/// Fine grid of the bins and the kernel spectrum on it
+struct Convolution {
+    /// Energy of the first bin
+    start: f64,
+    /// Fine grid step
+    step: f64,
+    /// Number of bins
+    len: usize,
+    /// Bin of the first point of the energy grid
+    grid_offset: usize,
+    /// Bins per step of the energy grid
+    subdivisions: usize,
+    /// Points of the energy grid
+    grid_len: usize,
+    /// Kernel samples run from -`half_width` to `half_width` bins
+    half_width: usize,
+    /// FFT of the kernel samples, zero padded to the FFT length
+    kernel_spectrum: Vec<Complex<f64>>,
+    forward: Arc<dyn Fft<f64>>,
+    inverse: Arc<dyn Fft<f64>>,
+}
+
+impl Convolution {
+    fn new(
+        energy_grid: &[f64],
+        step: f64,
+        subdivisions: usize,
+        (eigen_min, eigen_max): (f64, f64),
+        kernel: &BroadeningKernel,
+        smearing: f64,
+    ) -> Self {
+        let (grid_min, grid_max) = (energy_grid[0], energy_grid[energy_grid.len() - 1]);
+        // States further than the cutoff from the grid do not reach it,
+        // and kernels with long tails need every state
+        let (lower, upper) = match kernel.cutoff(smearing) {
+            Some(cutoff) => (grid_min - cutoff, grid_max + cutoff),
+            None => (grid_min.min(eigen_min), grid_max.max(eigen_max)),
+        };
+        let grid_offset = ((grid_min - lower) / step).ceil() as usize;
+        let grid_span = (energy_grid.len() - 1) * subdivisions;
+        let len = grid_offset + grid_span + ((upper - grid_max) / step).ceil() as usize + 1;
+        let half_width = match kernel.cutoff(smearing) {
+            Some(cutoff) => ((cutoff / step).ceil() as usize).min(len),
+            None => len,
+        };
+        // Linear, not circular, convolution
+        let fft_len = (len + 2 * half_width).next_power_of_two();
+        let mut planner = FftPlanner::new();
+        let forward = planner.plan_fft_forward(fft_len);
+        let inverse = planner.plan_fft_inverse(fft_len);
+        let mut kernel_spectrum = vec![Complex::new(0.0, 0.0); fft_len];
+        (0..=2 * half_width).for_each(|i| {
+            let delta = (i as f64 - half_width as f64) * step;
+            kernel_spectrum[i] = Complex::new(kernel.value(delta, smearing), 0.0);
+        });
+        forward.process(&mut kernel_spectrum);
+        Self {
+            start: grid_min - grid_offset as f64 * step,
+            step,
+            len,
+            grid_offset,
+            subdivisions,
+            grid_len: energy_grid.len(),
+            half_width,
+            kernel_spectrum,
+            forward,
+            inverse,
+        }
+    }
+
+    /// PDOS of one spin for each projector
+    fn spin_pdos(
+        &self,
+        eigenvalues: &KpointVec<EigenvalueVec<f64>>,
+        projected_weights: &[&KpointVec<EigenvalueVec<AngularChannels>>],
+        kpoint_weights: &KpointVec<KpointWeight>,
+        spin_coeff: f64,
+    ) -> Vec<PDOSResult> {
+        projected_weights
+            .par_iter()
+            .map(|weights| {
+                let mut bins = [(); 4].map(|_| vec![0.0; self.len]);
+                eigenvalues
+                    .iter()
+                    .zip(weights.iter())
+                    .zip(kpoint_weights.iter())
+                    .for_each(|((eigen_k, weights_k), kw)| {
+                        let coeff = kw.value() * spin_coeff;
+                        eigen_k
+                            .iter()
+                            .zip(weights_k.iter())
+                            .for_each(|(&eigen, am_weights)| {
+                                [am_weights.s, am_weights.p, am_weights.d, am_weights.f]
+                                    .into_iter()
+                                    .zip(bins.iter_mut())
+                                    .for_each(|(weight, bins)| {
+                                        self.bin(bins, eigen, coeff * weight)
+                                    });
+                            });
+                    });
+                let [s, p, d, f] = bins.map(|bins| self.convolve(bins));
+                PDOSResult { s, p, d, f }
+            })
+            .collect()
+    }
+
+    /// Share `weight` at `energy` between the two nearest bins
+    fn bin(&self, bins: &mut [f64], energy: f64, weight: f64) {
+        let position = (energy - self.start) / self.step;
+        if position < 0.0 || weight == 0.0 {
+            return;
+        }
+        let lower = position.floor() as usize;
+        let fraction = position - lower as f64;
+        if lower + 1 < self.len {
+            bins[lower] += weight * (1.0 - fraction);
+            bins[lower + 1] += weight * fraction;
+        } else if lower + 1 == self.len && fraction == 0.0 {
+            bins[lower] += weight;
+        }
+    }
+
+    /// DOS on the energy grid of the binned states
+    fn convolve(&self, bins: Vec<f64>) -> Vec<f64> {
+        if bins.iter().all(|b| *b == 0.0) {
+            return vec![0.0; self.grid_len];
+        }
+        let fft_len = self.kernel_spectrum.len();
+        let mut buffer = bins
+            .into_iter()
+            .map(|b| Complex::new(b, 0.0))
+            .chain(std::iter::repeat(Complex::new(0.0, 0.0)))
+            .take(fft_len)
+            .collect::<Vec<Complex<f64>>>();
+        self.forward.process(&mut buffer);
+        buffer
+            .iter_mut()
+            .zip(self.kernel_spectrum.iter())
+            .for_each(|(b, k)| *b *= k);
+        self.inverse.process(&mut buffer);
+        // rustfft does not normalize the inverse transform
+        (0..self.grid_len)
+            .map(|j| {
+                buffer[self.grid_offset + j * self.subdivisions + self.half_width].re
+                    / fft_len as f64
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        fundamental::{AngularChannels, SpinData, SpinLayoutError},
+        pdos_compute::{BroadeningKernel, PDOSResult, calculate_pdos},
+        test_fixtures::sample_grid_band_structure,
+    };
+
+    use super::{HistogramError, calculate_pdos_histogram};
+
+    #[test]
+    fn test_histogram_matches_exact() {
+        // The bands span -0.2 to 0.5 Ha, Fermi energy at 0
+        let energy_grid = (0..=4000)
+            .map(|i| -20.0 + 0.01 * i as f64)
+            .collect::<Vec<f64>>();
+        [false, true].into_iter().for_each(|spin_polarized| {
+            let band_structure = sample_grid_band_structure(8, spin_polarized);
+            let weights = band_structure.eigenvalues.map(|kpts| {
+                kpts.iter()
+                    .map(|eigens| {
+                        eigens
+                            .iter()
+                            .map(|_| AngularChannels::new(0.1, 0.2, 0.3, 0.4))
+                            .collect()
+                    })
+                    .collect()
+            });
+            [
+                (BroadeningKernel::Gaussian, 0.1),
+                (BroadeningKernel::Lorentzian, 0.1),
+                (BroadeningKernel::MethfesselPaxton { order: 1 }, 0.1),
+            ]
+            .into_iter()
+            .for_each(|(kernel, smearing)| {
+                let exact =
+                    calculate_pdos(&band_structure, &weights, &energy_grid, &kernel, smearing)
+                        .unwrap();
+                let fast = calculate_pdos_histogram(
+                    &band_structure,
+                    &[&weights],
+                    &energy_grid,
+                    &kernel,
+                    smearing,
+                    4,
+                )
+                .unwrap()
+                .remove(0);
+                let channels = |result: &SpinData<PDOSResult>| {
+                    let mut values = Vec::new();
+                    result.for_each(|pdos| {
+                        [&pdos.s, &pdos.p, &pdos.d, &pdos.f]
+                            .into_iter()
+                            .for_each(|channel| values.push(channel.clone()))
+                    });
+                    values
+                };
+                channels(&exact)
+                    .iter()
+                    .zip(channels(&fast))
+                    .for_each(|(exact, fast)| {
+                        // A few times (h/σ)²/8 = 8e-5 of the peak
+                        let peak = exact.iter().copied().fold(0.0, f64::max);
+                        exact.iter().zip(fast.iter()).for_each(|(e, f)| {
+                            assert!((e - f).abs() < 5e-4 * peak, "{kernel:?}: {e} {f}");
+                        });
+                    });
+            });
+        });
+    }
+
+    #[test]
+    fn test_histogram_errors() {
+        let band_structure = sample_grid_band_structure(4, false);
+        let weights = band_structure.eigenvalues.map(|kpts| {
+            kpts.iter()
+                .map(|eigens| eigens.iter().map(|_| AngularChannels::zero()).collect())
+                .collect()
+        });
+        let kernel = BroadeningKernel::Gaussian;
+        let fast = |energy_grid: &[f64], subdivisions: usize| {
+            calculate_pdos_histogram(
+                &band_structure,
+                &[&weights],
+                energy_grid,
+                &kernel,
+                0.1,
+                subdivisions,
+            )
+            .map(|results| results.len())
+        };
+        assert_eq!(
+            fast(&[0.0, 0.1, 0.3], 4),
+            Err(HistogramError::NonUniformGrid)
+        );
+        assert_eq!(fast(&[0.0], 4), Err(HistogramError::NonUniformGrid));
+        assert_eq!(
+            fast(&[0.0, 0.1, 0.2], 0),
+            Err(HistogramError::ZeroSubdivisions)
+        );
+        assert_eq!(fast(&[0.0, 0.1, 0.2], 4), Ok(1));
+        // Non-polarized weights on spin polarized bands
+        let polarized = sample_grid_band_structure(4, true);
+        assert_eq!(
+            calculate_pdos_histogram(&polarized, &[&weights], &[0.0, 0.1, 0.2], &kernel, 0.1, 4)
+                .map(|results| results.len()),
+            Err(HistogramError::SpinLayout(SpinLayoutError(
+                "eigenvalues",
+                "projected weights"
+            )))
+        );
+    }
+}
@@ -0,0 +1,324 @@
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+/// Gaussian-like kernels are dropped beyond this many σ, exp(-32) of the
+/// peak
+const GAUSSIAN_CUTOFF: f64 = 8.0;
+/// `FermiDirac` is dropped beyond this many kT, about exp(-40) of the peak
+const FERMI_DIRAC_CUTOFF: f64 = 40.0;
+
+/// Line shape used to broaden each eigenvalue into the DOS.
+/// Every kernel integrates to one, `width` is the `smearing` in eV:
+/// - `Gaussian`: standard deviation σ
+/// - `Lorentzian`: half width at half maximum γ
+/// - `Voigt`: σ of the Gaussian part, the Lorentzian HWHM is `gamma`
+/// - `MethfesselPaxton`: σ of the Gaussian envelope
+/// - `FermiDirac`: thermal energy kT
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BroadeningKernel {
+    /// Gaussian
+    #[default]
+    Gaussian,
+    /// Lorentzian, for comparing with photoemission spectra
+    Lorentzian,
+    /// Pseudo-Voigt approximation (Thompson, Cox and Hastings, 1987)
+    /// of the convolution of a Gaussian and a Lorentzian.
+    Voigt {
+        /// Lorentzian half width at half maximum in eV
+        gamma: f64,
+    },
+    /// Methfessel-Paxton smearing of order N, as in the `CASTEP` SCF.
+    /// Order 0 is a Gaussian of standard deviation σ/√2. The kernel is
+    /// negative at some energies for N > 0.
+    MethfesselPaxton {
+        /// Order of the Hermite expansion
+        order: u32,
+    },
+    /// Negative derivative of the Fermi-Dirac distribution
+    FermiDirac,
+}
+
+impl BroadeningKernel {
+    /// Kernel value at `delta` = E - ε (eV) for the given `width` (eV)
+    pub fn value(&self, delta: f64, width: f64) -> f64 {
+        match self {
+            BroadeningKernel::Gaussian => gaussian(delta, width),
+            BroadeningKernel::Lorentzian => lorentzian(delta, width),
+            BroadeningKernel::Voigt { gamma } => pseudo_voigt(delta, width, *gamma),
+            BroadeningKernel::MethfesselPaxton { order } => methfessel_paxton(delta, width, *order),
+            BroadeningKernel::FermiDirac => fermi_dirac_derivative(delta, width),
+        }
+    }
+
+    /// Distance from the eigenvalue (eV) beyond which the kernel is
+    /// negligible, `None` for the long tails of `Lorentzian` and `Voigt`
+    pub fn cutoff(&self, width: f64) -> Option<f64> {
+        match self {
+            BroadeningKernel::Gaussian | BroadeningKernel::MethfesselPaxton { .. } => {
+                Some(GAUSSIAN_CUTOFF * width)
+            }
+            BroadeningKernel::FermiDirac => Some(FERMI_DIRAC_CUTOFF * width),
+            BroadeningKernel::Lorentzian | BroadeningKernel::Voigt { .. } => None,
+        }
+    }
+
+    /// Occupation of a state at `delta` = ε - μ (eV): the integral of the
+    /// kernel from `delta` to infinity. Above one or below zero in places
+    /// for `MethfesselPaxton` with N > 0.
+    pub fn occupation(&self, delta: f64, width: f64) -> f64 {
+        match self {
+            BroadeningKernel::Gaussian => gaussian_occupation(delta, width),
+            BroadeningKernel::Lorentzian => lorentzian_occupation(delta, width),
+            BroadeningKernel::Voigt { gamma } => {
+                let (eta, fwhm) = pseudo_voigt_parameters(width, *gamma);
+                eta * lorentzian_occupation(delta, fwhm / 2.0)
+                    + (1.0 - eta) * gaussian_occupation(delta, fwhm_to_sigma(fwhm))
+            }
+            BroadeningKernel::MethfesselPaxton { order } => {
+                methfessel_paxton_occupation(delta, width, *order)
+            }
+            BroadeningKernel::FermiDirac => fermi_dirac(delta, width),
+        }
+    }
+}
+
+fn gaussian(delta: f64, sigma: f64) -> f64 {
+    (-0.5 * (delta / sigma).powi(2)).exp() / (sigma * (2.0 * PI).sqrt())
+}
+
+fn lorentzian(delta: f64, gamma: f64) -> f64 {
+    gamma / (PI * (delta.powi(2) + gamma.powi(2)))
+}
+
+fn gaussian_occupation(delta: f64, sigma: f64) -> f64 {
+    0.5 * erfc(delta / (sigma * 2.0_f64.sqrt()))
+}
+
+fn lorentzian_occupation(delta: f64, gamma: f64) -> f64 {
+    0.5 - (delta / gamma).atan() / PI
+}
+
+/// η-weighted sum of a Lorentzian and a Gaussian sharing the total FWHM
+fn pseudo_voigt(delta: f64, sigma: f64, gamma: f64) -> f64 {
+    let (eta, fwhm) = pseudo_voigt_parameters(sigma, gamma);
+    eta * lorentzian(delta, fwhm / 2.0) + (1.0 - eta) * gaussian(delta, fwhm_to_sigma(fwhm))
+}
+
+fn fwhm_to_sigma(fwhm: f64) -> f64 {
+    fwhm / (2.0 * (2.0 * 2.0_f64.ln()).sqrt())
+}
+
+/// Mixing η and total FWHM of the pseudo-Voigt
+fn pseudo_voigt_parameters(sigma: f64, gamma: f64) -> (f64, f64) {
+    let fwhm_g = 2.0 * sigma * (2.0 * 2.0_f64.ln()).sqrt();
+    let fwhm_l = 2.0 * gamma;
+    let fwhm = (fwhm_g.powi(5)
+        + 2.69269 * fwhm_g.powi(4) * fwhm_l
+        + 2.42843 * fwhm_g.powi(3) * fwhm_l.powi(2)
+        + 4.47163 * fwhm_g.powi(2) * fwhm_l.powi(3)
+        + 0.07842 * fwhm_g * fwhm_l.powi(4)
+        + fwhm_l.powi(5))
+    .powf(0.2);
+    let ratio = fwhm_l / fwhm;
+    let eta = 1.36603 * ratio - 0.47719 * ratio.powi(2) + 0.11116 * ratio.powi(3);
+    (eta, fwhm)
+}
+
+/// δ_N(x) = Σ_{n=0}^{N} A_n H_2n(x) exp(-x²), A_n = (-1)^n / (n! 4^n √π), x = δ/σ
+fn methfessel_paxton(delta: f64, sigma: f64, order: u32) -> f64 {
+    let x = delta / sigma;
+    let gauss = (-x * x).exp();
+    // Hermite polynomials by H_{k+1} = 2x H_k - 2k H_{k-1}
+    let (mut h_prev, mut h_curr) = (1.0, 2.0 * x);
+    let mut a_n = 1.0 / PI.sqrt();
+    let mut sum = a_n;
+    (1..=order).for_each(|n| {
+        // Advance from H_{2n-2} to H_{2n}
+        let k = 2 * n - 1;
+        let h_next = 2.0 * x * h_curr - 2.0 * k as f64 * h_prev;
+        h_prev = h_next;
+        h_curr = 2.0 * x * h_next - 2.0 * (k + 1) as f64 * h_curr;
+        a_n *= -1.0 / (4.0 * n as f64);
+        sum += a_n * h_prev;
+    });
+    sum * gauss / sigma
+}
+
+/// S_N(x) = erfc(x) / 2 + Σ_{n=1}^{N} A_n H_2n-1(x) exp(-x²), x = δ/σ
+fn methfessel_paxton_occupation(delta: f64, sigma: f64, order: u32) -> f64 {
+    let x = delta / sigma;
+    let gauss = (-x * x).exp();
+    // (H_{2n-2}, H_{2n-1}), starting from (H_0, H_1)
+    let (mut h_prev, mut h_curr) = (1.0, 2.0 * x);
+    let mut a_n = 1.0 / PI.sqrt();
+    let mut sum = 0.0;
+    (1..=order).for_each(|n| {
+        a_n *= -1.0 / (4.0 * n as f64);
+        sum += a_n * h_curr;
+        let k = (2 * n - 1) as f64;
+        let h_even = 2.0 * x * h_curr - 2.0 * k * h_prev;
+        let h_odd = 2.0 * x * h_even - 2.0 * (k + 1.0) * h_curr;
+        (h_prev, h_curr) = (h_even, h_odd);
+    });
+    0.5 * erfc(x) + sum * gauss
+}
+
+/// f = 1 / (1 + e^x), x = δ/kT
+fn fermi_dirac(delta: f64, kt: f64) -> f64 {
+    let x = delta / kt;
+    // Written with e^-|x| to avoid overflow
+    let e = (-x.abs()).exp();
+    if x > 0.0 {
+        e / (1.0 + e)
+    } else {
+        1.0 / (1.0 + e)
+    }
+}
+
+/// Complementary error function, fractional error below 1.2e-7
+/// (Chebyshev fit of Numerical Recipes, section 6.2)
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let poly = [
+        -1.26551223,
+        1.00002368,
+        0.37409196,
+        0.09678418,
+        -0.18628806,
+        0.27886807,
+        -1.13520398,
+        1.48851587,
+        -0.82215223,
+        0.17087277,
+    ]
+    .iter()
+    .rev()
+    .fold(0.0, |acc, c| acc * t + c);
+    let value = t * (-z * z + poly).exp();
+    if x >= 0.0 { value } else { 2.0 - value }
+}
+
+/// -∂f/∂E = 1 / (kT (2 + e^x + e^-x)), x = δ/kT
+fn fermi_dirac_derivative(delta: f64, kt: f64) -> f64 {
+    let x = (delta / kt).abs();
+    // Written with e^-|x| to avoid overflow
+    let e = (-x).exp();
+    e / (kt * (1.0 + e).powi(2))
+}
+
+#[cfg(test)]
+mod test {
+    use serde::Deserialize;
+
+    use super::BroadeningKernel;
+
+    fn integrate(kernel: &BroadeningKernel, width: f64, range: f64) -> f64 {
+        let step = 1e-3;
+        let n = (2.0 * range / step) as usize;
+        (0..=n)
+            .map(|i| kernel.value(-range + i as f64 * step, width) * step)
+            .sum()
+    }
+
+    #[test]
+    fn test_normalization() {
+        [
+            (BroadeningKernel::Gaussian, 1e-9),
+            (BroadeningKernel::MethfesselPaxton { order: 0 }, 1e-9),
+            (BroadeningKernel::MethfesselPaxton { order: 1 }, 1e-9),
+            (BroadeningKernel::MethfesselPaxton { order: 2 }, 1e-9),
+            (BroadeningKernel::FermiDirac, 1e-6),
+            // Lorentzian tails decay as 1/x², a finite range misses 2γ/(π range) ≈ 3e-3
+            (BroadeningKernel::Lorentzian, 4e-3),
+            (BroadeningKernel::Voigt { gamma: 0.05 }, 4e-3),
+        ]
+        .iter()
+        .for_each(|(kernel, tol)| {
+            let integral = integrate(kernel, 0.1, 20.0);
+            assert!((integral - 1.0).abs() < *tol, "{kernel:?}: {integral}");
+        });
+    }
+
+    #[test]
+    fn test_kernel_shapes() {
+        let sigma = 0.2;
+        // Order 0 is the Gaussian with σ' = σ/√2
+        let mp0 = BroadeningKernel::MethfesselPaxton { order: 0 };
+        let gaussian = BroadeningKernel::Gaussian;
+        assert!(
+            (mp0.value(0.1, sigma) - gaussian.value(0.1, sigma / 2.0_f64.sqrt())).abs() < 1e-12
+        );
+        // MP1: (1/√π)(1 - (4x² - 2)/4) exp(-x²)/σ = (1.5 - x²) exp(-x²)/(σ√π)
+        let x: f64 = 0.7;
+        let mp1 = BroadeningKernel::MethfesselPaxton { order: 1 }.value(x * sigma, sigma);
+        let expected = (1.5 - x * x) * (-x * x).exp() / (sigma * std::f64::consts::PI.sqrt());
+        assert!((mp1 - expected).abs() < 1e-12);
+        // Voigt reduces to the Gaussian without Lorentzian part
+        let voigt = BroadeningKernel::Voigt { gamma: 0.0 };
+        assert!((voigt.value(0.1, sigma) - gaussian.value(0.1, sigma)).abs() < 1e-9);
+        // Fermi-Dirac derivative peaks at 1/(4kT)
+        assert!(
+            (BroadeningKernel::FermiDirac.value(0.0, sigma) - 1.0 / (4.0 * sigma)).abs() < 1e-12
+        );
+    }
+
+    #[test]
+    fn test_occupation() {
+        [
+            BroadeningKernel::Gaussian,
+            BroadeningKernel::Lorentzian,
+            BroadeningKernel::Voigt { gamma: 0.05 },
+            BroadeningKernel::MethfesselPaxton { order: 0 },
+            BroadeningKernel::MethfesselPaxton { order: 1 },
+            BroadeningKernel::MethfesselPaxton { order: 2 },
+            BroadeningKernel::FermiDirac,
+        ]
+        .iter()
+        .for_each(|kernel| {
+            let width = 0.1;
+            assert!(
+                (kernel.occupation(0.0, width) - 0.5).abs() < 1e-7,
+                "{kernel:?}"
+            );
+            // The occupation drops by the integral of the kernel
+            let (a, b) = (-0.15, 0.23);
+            let step = 1e-5;
+            let n = ((b - a) / step) as usize;
+            let integral = (0..n)
+                .map(|i| kernel.value(a + (i as f64 + 0.5) * step, width) * step)
+                .sum::<f64>();
+            let drop = kernel.occupation(a, width) - kernel.occupation(b, width);
+            assert!(
+                (drop - integral).abs() < 1e-6,
+                "{kernel:?}: {drop} {integral}"
+            );
+        });
+        assert!(BroadeningKernel::FermiDirac.occupation(-1e3, 0.1) == 1.0);
+        assert!(BroadeningKernel::Gaussian.occupation(5.0, 0.1) < 1e-12);
+    }
+
+    #[test]
+    fn test_kernel_config() {
+        #[derive(Deserialize)]
+        struct Config {
+            kernel: BroadeningKernel,
+        }
+        let config = toml::from_str::<Config>(
+            r#"
+[kernel]
+type = "methfessel_paxton"
+order = 1
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.kernel,
+            BroadeningKernel::MethfesselPaxton { order: 1 }
+        );
+        let config = toml::from_str::<Config>(r#"kernel = { type = "fermi_dirac" }"#).unwrap();
+        assert_eq!(config.kernel, BroadeningKernel::FermiDirac);
+    }
+}
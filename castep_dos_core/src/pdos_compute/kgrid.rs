@@ -0,0 +1,228 @@
+use thiserror::Error;
+
+use crate::fundamental::{BandStructure, KpointCoords};
+
+/// Largest grid size searched along each axis
+const MAX_GRID: usize = 128;
+/// Tolerance of `k * N - shift` being an integer, `.bands` keeps 8 decimals
+const GRID_TOLERANCE: f64 = 1e-4;
+
+#[derive(Debug, Error, PartialEq)]
+/// The k-points do not form a Monkhorst-Pack grid
+pub enum KpointGridError {
+    /// No Monkhorst-Pack grid up to `MAX_GRID` fits the coordinates
+    #[error("No Monkhorst-Pack grid fits the k-point coordinates along axis {axis}")]
+    NoGrid {
+        /// Reciprocal lattice axis, starts from 1
+        axis: usize,
+    },
+    /// Two k-points sit on the same grid point
+    #[error("K-points {first} and {second} are the same point of the {grid:?} grid")]
+    DuplicatePoint {
+        /// Index of the first k-point
+        first: u32,
+        /// Index of the second k-point
+        second: u32,
+        /// Grid size
+        grid: [usize; 3],
+    },
+    /// Symmetry reduced the k-points beyond time reversal
+    #[error(
+        "{missing} of {total} points of the {grid:?} grid are not covered by the k-points or their time-reversal partners. The k-points must be reduced by time reversal only, e.g. a DOS run with symmetry off"
+    )]
+    IncompleteGrid {
+        /// Number of grid points without k-point
+        missing: usize,
+        /// Total grid points
+        total: usize,
+        /// Grid size
+        grid: [usize; 3],
+    },
+}
+
+/// Monkhorst-Pack grid implied by the k-point coordinates of `.bands`.
+/// The k-points may be reduced by time reversal (k and -k) only, since
+/// `.bands` carries no symmetry operations to unfold them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KpointGrid {
+    /// Grid size along the three reciprocal lattice vectors
+    pub size: [usize; 3],
+    /// Grid coordinates of each k-point, in the order of `BandStructure::kpoints`
+    pub coordinates: Vec<[usize; 3]>,
+    /// Reciprocal lattice vectors in 1/Bohr, without the 2π factor
+    pub reciprocal_vectors: [[f64; 3]; 3],
+    /// Position in `BandStructure::kpoints` of every grid point,
+    /// flattened with axis 1 fastest
+    lookup: Vec<usize>,
+}
+
+impl KpointGrid {
+    /// Reconstruct the grid from the k-point coordinates
+    pub fn from_band_structure(band_structure: &BandStructure) -> Result<Self, KpointGridError> {
+        let kpoints = &band_structure.kpoints;
+        let axes =
+            [0, 1, 2].map(|axis| detect_axis(kpoints.iter().map(|kpt| kpt.coords[axis]), axis));
+        let [axis1, axis2, axis3] = axes;
+        let [(n1, s1), (n2, s2), (n3, s3)] = [axis1?, axis2?, axis3?];
+        let size = [n1, n2, n3];
+        let shifts = [s1, s2, s3];
+        let (lookup, coordinates) = grid_lookup(kpoints, size, shifts)?;
+        Ok(Self {
+            size,
+            coordinates,
+            reciprocal_vectors: reciprocal_vectors(&band_structure.lattice_vectors),
+            lookup,
+        })
+    }
+
+    /// Position in `BandStructure::kpoints` of grid point `m`,
+    /// periodic in each axis
+    pub fn kpoint(&self, m: [i64; 3]) -> usize {
+        let [n1, n2, n3] = self.size.map(|n| n as i64);
+        let flat = m[0].rem_euclid(n1) + n1 * (m[1].rem_euclid(n2) + n2 * m[2].rem_euclid(n3));
+        self.lookup[flat as usize]
+    }
+
+    /// Total number of grid points
+    pub fn total_points(&self) -> usize {
+        self.lookup.len()
+    }
+}
+
+/// Smallest `N` and shift `s` such that every `k * N - s` is an integer
+fn detect_axis(
+    coords: impl Iterator<Item = f64> + Clone,
+    axis: usize,
+) -> Result<(usize, f64), KpointGridError> {
+    (1..=MAX_GRID)
+        .find_map(|n| {
+            let mut values = coords.clone().map(|k| k * n as f64);
+            let first = values.next()?;
+            let shift = first - first.round();
+            values
+                .all(|v| {
+                    let d = v - shift;
+                    (d - d.round()).abs() < GRID_TOLERANCE
+                })
+                .then_some((n, shift))
+        })
+        .ok_or(KpointGridError::NoGrid { axis: axis + 1 })
+}
+
+/// Position in `kpoints` of every grid point and grid coordinates of every
+/// k-point. Grid points without k-point take the one of their
+/// time-reversal partner.
+fn grid_lookup(
+    kpoints: &[KpointCoords],
+    grid: [usize; 3],
+    shifts: [f64; 3],
+) -> Result<(Vec<usize>, Vec<[usize; 3]>), KpointGridError> {
+    let total = grid.iter().product::<usize>();
+    let grid_coordinates = |coords: [f64; 3]| -> Option<[usize; 3]> {
+        let mut m = [0; 3];
+        for axis in 0..3 {
+            let d = coords[axis] * grid[axis] as f64 - shifts[axis];
+            if (d - d.round()).abs() >= GRID_TOLERANCE {
+                return None;
+            }
+            m[axis] = (d.round() as i64).rem_euclid(grid[axis] as i64) as usize;
+        }
+        Some(m)
+    };
+    let flat_index = |m: [usize; 3]| m[0] + grid[0] * (m[1] + grid[1] * m[2]);
+    let coordinates = kpoints
+        .iter()
+        // Coordinates fitted the grid in `detect_axis`
+        .map(|kpt| grid_coordinates(kpt.coords).expect("k-point on the detected grid"))
+        .collect::<Vec<[usize; 3]>>();
+    let mut lookup: Vec<Option<usize>> = vec![None; total];
+    coordinates.iter().enumerate().try_for_each(|(i, &m)| {
+        let flat = flat_index(m);
+        match lookup[flat] {
+            Some(j) => Err(KpointGridError::DuplicatePoint {
+                first: kpoints[j].index,
+                second: kpoints[i].index,
+                grid,
+            }),
+            None => {
+                lookup[flat] = Some(i);
+                Ok(())
+            }
+        }
+    })?;
+    kpoints.iter().enumerate().for_each(|(i, kpt)| {
+        // -k is off the grid for shifts other than 0 and 1/2
+        if let Some(m) = grid_coordinates(kpt.coords.map(|k| -k)) {
+            lookup[flat_index(m)].get_or_insert(i);
+        }
+    });
+    let missing = lookup.iter().filter(|point| point.is_none()).count();
+    if missing > 0 {
+        return Err(KpointGridError::IncompleteGrid {
+            missing,
+            total,
+            grid,
+        });
+    }
+    Ok((lookup.into_iter().flatten().collect(), coordinates))
+}
+
+/// Reciprocal lattice vectors without the 2π factor
+fn reciprocal_vectors(lattice_vectors: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let [a1, a2, a3] = *lattice_vectors;
+    let volume: f64 = a1.iter().zip(cross(a2, a3)).map(|(x, y)| x * y).sum();
+    [cross(a2, a3), cross(a3, a1), cross(a1, a2)].map(|b| b.map(|x| x / volume))
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::{KpointGrid, KpointGridError};
+    use crate::test_fixtures::sample_grid_band_structure;
+
+    #[test]
+    fn test_kpoint_grid() {
+        let band_structure = sample_grid_band_structure(4, false);
+        let grid = KpointGrid::from_band_structure(&band_structure).unwrap();
+        assert_eq!(grid.size, [4, 4, 1]);
+        assert_eq!(grid.total_points(), 16);
+        // Every k-point is found at its own coordinates, and stands for
+        // its time-reversal partner
+        grid.coordinates.iter().enumerate().for_each(|(i, m)| {
+            assert_eq!(grid.kpoint(m.map(|x| x as i64)), i);
+        });
+        let mut counts = vec![0; band_structure.kpoints.len()];
+        (0..4).for_each(|m1| (0..4).for_each(|m2| counts[grid.kpoint([m1, m2, 0])] += 1));
+        assert!(counts.iter().all(|&c| c == 2));
+        // Periodic
+        assert_eq!(grid.kpoint([-1, 5, 3]), grid.kpoint([3, 1, 0]));
+
+        let mut reduced = band_structure.clone();
+        reduced.kpoints.pop();
+        assert!(matches!(
+            KpointGrid::from_band_structure(&reduced),
+            Err(KpointGridError::IncompleteGrid {
+                missing: 2,
+                total: 16,
+                ..
+            })
+        ));
+        let mut duplicated = band_structure.clone();
+        duplicated.kpoints[1].coords = duplicated.kpoints[0].coords;
+        assert!(matches!(
+            KpointGrid::from_band_structure(&duplicated),
+            Err(KpointGridError::DuplicatePoint {
+                first: 1,
+                second: 2,
+                ..
+            })
+        ));
+    }
+}
@@ -1,15 +1,64 @@
-use std::f64::consts::PI;
-
-use ndarray::{Array1, Array2};
-use rayon::iter::{
-    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator,
-};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
 
 use crate::fundamental::{
-    AngularChannels, BandStructure, EigenvalueVec, KpointVec, KpointWeight, SpinData, SpinPolarized,
+    AngularChannels, BandStructure, EigenvalueVec, HATREE_TO_EV, KpointVec, SpinData,
+    SpinLayoutError,
 };
 
-const HATREE_TO_EV: f64 = 27.211396641308;
+mod adaptive;
+mod broadening;
+mod energy_reference;
+mod fermi_level;
+mod histogram;
+mod kernel;
+mod kgrid;
+mod tetrahedron;
+
+pub use adaptive::AdaptiveBroadening;
+pub use broadening::BroadeningFactors;
+pub use energy_reference::EnergyReference;
+pub use fermi_level::{FermiLevelError, FermiLevelSolver};
+pub use histogram::{HistogramError, calculate_pdos_histogram};
+pub use kernel::BroadeningKernel;
+pub use kgrid::{KpointGrid, KpointGridError};
+pub use tetrahedron::{TetrahedronMesh, calculate_pdos_tetrahedron};
+
+/// How the DOS is integrated over the Brillouin zone
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DosMethod {
+    /// Broaden each eigenvalue with the kernel, see `calculate_pdos`
+    #[default]
+    Smearing,
+    /// Same as `Smearing`, binned on a finer grid and convolved with the
+    /// kernel by FFT, see `calculate_pdos_histogram`. Much faster on
+    /// large energy grids, within a small documented tolerance.
+    FastSmearing {
+        /// Fine bins per energy grid step
+        #[serde(default = "default_subdivisions")]
+        subdivisions: usize,
+    },
+    /// Broaden each eigenvalue with the kernel and a width following the
+    /// band gradient, see `AdaptiveBroadening`
+    Adaptive(AdaptiveBroadening),
+    /// Linear tetrahedron method, see `calculate_pdos_tetrahedron`.
+    /// Needs the k-points of a full Monkhorst-Pack grid, reduced by time
+    /// reversal at most.
+    Tetrahedron {
+        /// Apply the Blöchl correction
+        #[serde(default = "default_blochl_correction")]
+        blochl_correction: bool,
+    },
+}
+
+fn default_blochl_correction() -> bool {
+    true
+}
+
+fn default_subdivisions() -> usize {
+    4
+}
 
 /// Angular momentum-resolved projected DOS result
 #[derive(Debug, Clone, PartialEq)]
@@ -25,23 +74,87 @@ pub struct PDOSResult {
 }
 
 impl PDOSResult {
-    /// Write as csv
+    /// Write as csv, the DOS of each channel followed by its integrated DOS
     pub fn csv_output(&self, energy_grid: &[f64]) -> String {
-        let header = "E,DOS_s,DOS_p,DOS_d,DOS_f";
+        let header = "E,DOS_s,DOS_p,DOS_d,DOS_f,IDOS_s,IDOS_p,IDOS_d,IDOS_f";
+        let idos = self.integrated(energy_grid);
         let contents = energy_grid
             .iter()
-            .zip(self.s.iter())
-            .zip(self.p.iter())
-            .zip(self.d.iter())
-            .zip(self.f.iter())
-            .map(|((((e, s), p), d), f)| {
-                format!("{:.16},{:.16},{:.16},{:.16},{:.16}", e, s, p, d, f)
+            .enumerate()
+            .map(|(i, e)| {
+                format!(
+                    "{:.16},{:.16},{:.16},{:.16},{:.16},{:.16},{:.16},{:.16},{:.16}",
+                    e,
+                    self.s[i],
+                    self.p[i],
+                    self.d[i],
+                    self.f[i],
+                    idos.s[i],
+                    idos.p[i],
+                    idos.d[i],
+                    idos.f[i]
+                )
             })
             .collect::<Vec<String>>()
             .join("\n");
         [header.to_string(), contents].join("\n")
     }
 
+    /// Integrated DOS of each channel: number of states from the start of
+    /// `energy_grid` up to each point, by the trapezoidal rule
+    pub fn integrated(&self, energy_grid: &[f64]) -> PDOSResult {
+        let cumulative = |dos: &[f64]| -> Vec<f64> {
+            let mut total = 0.0;
+            (0..dos.len())
+                .map(|i| {
+                    if i > 0 {
+                        total +=
+                            0.5 * (dos[i] + dos[i - 1]) * (energy_grid[i] - energy_grid[i - 1]);
+                    }
+                    total
+                })
+                .collect()
+        };
+        PDOSResult {
+            s: cumulative(&self.s),
+            p: cumulative(&self.p),
+            d: cumulative(&self.d),
+            f: cumulative(&self.f),
+        }
+    }
+
+    /// Number of states of all channels below `energy`, interpolated
+    /// linearly between the grid points. Energies beyond the grid count the
+    /// states of the whole grid, or none.
+    pub fn states_below(&self, energy_grid: &[f64], energy: f64) -> f64 {
+        let channels = self.channel_states_below(energy_grid, energy);
+        channels.s + channels.p + channels.d + channels.f
+    }
+
+    /// Same as `states_below`, for each channel
+    pub fn channel_states_below(&self, energy_grid: &[f64], energy: f64) -> AngularChannels {
+        let idos = self.integrated(energy_grid);
+        let at = |i: usize| AngularChannels::new(idos.s[i], idos.p[i], idos.d[i], idos.f[i]);
+        let upper = energy_grid.partition_point(|&e| e < energy);
+        if energy_grid.is_empty() || upper == 0 {
+            AngularChannels::zero()
+        } else if upper == energy_grid.len() {
+            at(upper - 1)
+        } else {
+            let lower = upper - 1;
+            let fraction =
+                (energy - energy_grid[lower]) / (energy_grid[upper] - energy_grid[lower]);
+            let (below, above) = (at(lower), at(upper));
+            let interpolate = |a: f64, b: f64| a + fraction * (b - a);
+            AngularChannels::new(
+                interpolate(below.s, above.s),
+                interpolate(below.p, above.p),
+                interpolate(below.d, above.d),
+                interpolate(below.f, above.f),
+            )
+        }
+    }
+
     /// Get the max PDOS value for y-axis limit in plotting
     pub fn max(&self) -> f64 {
         let s_max = self.s.iter().copied().reduce(f64::max).unwrap_or(20.0);
@@ -56,124 +169,106 @@ impl PDOSResult {
 }
 
 /// Calculate projected DOS with band structure data and projected angular momentum
-/// resolved weights
+/// resolved weights. Each eigenvalue is broadened by `kernel` with
+/// width `smearing` in eV.
+/// `energy_grid` is relative to `band_structure.fermi_energy` of each spin;
+/// replace it with `EnergyReference::resolve` for another zero.
 /// # Returns
 /// The result will inherently keep the spin-polarization settings:
 /// - `SpinData::NonPolarized(PDOSResult { s, p, d, f, })'
 /// - `SpinData::SpinPolarized([PDOSResult { s_up, p_up, d_up, f_up, }, PDOSResult {s_down, p_down, d_down,f_down}])
+///
+/// Fails when `projected_weights` is not in the spin layout of the bands.
 pub fn calculate_pdos(
     band_structure: &BandStructure,
     projected_weights: &SpinData<KpointVec<EigenvalueVec<AngularChannels>>>,
     energy_grid: &[f64],
+    kernel: &BroadeningKernel,
     smearing: f64,
-) -> SpinData<PDOSResult> {
-    let band_eigenvalues = &band_structure.eigenvalues;
-    let kpoint_weights = &band_structure.kpoint_weights;
-    let fermi_energys = &band_structure.fermi_energy;
-    band_eigenvalues
-        .map_pair(fermi_energys, |kpts, &e_fermi| {
+) -> Result<SpinData<PDOSResult>, SpinLayoutError> {
+    let widths = band_structure.eigenvalues.map(|kpts| {
+        kpts.iter()
+            .map(|eigens| eigens.iter().map(|_| smearing).collect())
+            .collect()
+    });
+    calculate_pdos_adaptive(
+        band_structure,
+        projected_weights,
+        energy_grid,
+        kernel,
+        &widths,
+    )
+}
+
+/// Same as `calculate_pdos`, with a broadening width in eV for each state,
+/// e.g. from `AdaptiveBroadening::widths`. `widths` has the layout of
+/// `BandStructure::eigenvalues`.
+pub fn calculate_pdos_adaptive(
+    band_structure: &BandStructure,
+    projected_weights: &SpinData<KpointVec<EigenvalueVec<AngularChannels>>>,
+    energy_grid: &[f64],
+    kernel: &BroadeningKernel,
+    widths: &SpinData<KpointVec<EigenvalueVec<f64>>>,
+) -> Result<SpinData<PDOSResult>, SpinLayoutError> {
+    BroadeningFactors::adaptive(band_structure, energy_grid, kernel, widths)
+        .apply(&[projected_weights])
+        .map(|mut results| results.remove(0))
+}
+
+/// Eigenvalues relative to the Fermi energy of their spin, in eV
+fn fermi_shifted_eigenvalues(
+    band_structure: &BandStructure,
+) -> SpinData<KpointVec<EigenvalueVec<f64>>> {
+    band_structure
+        .eigenvalues
+        .map_pair(&band_structure.fermi_energy, |kpts, &e_fermi| {
             kpts.par_iter()
                 .map(|eigens| {
                     eigens
                         .par_iter()
-                        // Precompute the fermi energy shift
                         .map(|eigenvalue| (eigenvalue - e_fermi) * HATREE_TO_EV)
                         .collect()
                 })
-                .collect::<KpointVec<EigenvalueVec<f64>>>()
+                .collect()
         })
-        .map_pair_with_spin(
-            projected_weights,
-            |eigens, pdos_weights| {
-                calc_spin_pdos(
-                    eigens,
-                    pdos_weights,
-                    kpoint_weights,
-                    energy_grid,
-                    smearing,
-                    SpinPolarized::False,
-                )
-            },
-            |eigens, pdos_weights| {
-                calc_spin_pdos(
-                    eigens,
-                    pdos_weights,
-                    kpoint_weights,
-                    energy_grid,
-                    smearing,
-                    SpinPolarized::True,
-                )
-            },
-        )
 }
 
-fn calc_spin_pdos(
-    eigenvalues: &KpointVec<EigenvalueVec<f64>>,
-    pdos_weights: &KpointVec<EigenvalueVec<AngularChannels>>,
-    kpoint_weights: &KpointVec<KpointWeight>,
-    energy_grid: &[f64],
-    smearing: f64,
-    spin_polarized: SpinPolarized,
-) -> PDOSResult {
-    // precompute Gaussian smearing
-    let spin_coeff = match spin_polarized {
-        SpinPolarized::True => 1.0,
-        SpinPolarized::False => 2.0,
-    };
-    let norm = (spin_coeff / smearing) / (2.0 * PI).sqrt();
-    // let two_sigma_sq = 2.0 * smearing.powi(2);
-    // Create energy grid as `Array1` for vectorized operations
-    let energy_arr = Array1::from_vec(energy_grid.to_vec());
-    // Initialize result array [channels x energy_points]
-    // let mut result = Array2::zeros((4, energy_grid.len()));
-
-    // Process each k-point
-    // Since the number of k-points is very limited, we don't use parallel
-    // iterator here
-    let result = eigenvalues
-        .par_iter()
-        .zip(pdos_weights.par_iter())
-        .zip(kpoint_weights.par_iter())
-        .map(|((eigen_k, weights_k), kw)| {
-            let kw_val = kw.value();
-
-            // Precompute Gaussian factors for all eigenvalues at this k-point
-            let factors = eigen_k
-                .par_iter()
-                .map(|&eigen| {
-                    let mut e_delta = &energy_arr - eigen;
-                    // kw * 1 / (ω * sqrt(2π)) * exp(-1/2 * (-d / ω)^2)
-                    e_delta.par_mapv_inplace(|d| {
-                        let gaussian_smearing = (d.powi(2) / smearing.powi(2) * -0.5).exp();
-                        kw_val * norm * gaussian_smearing
-                    });
-                    e_delta
-                })
-                .collect::<Vec<Array1<f64>>>();
-
-            // Process eigenvalues in parallel
+/// Data of several projectors split by spin, in the spin layout of
+/// `layout`. Fails when a projector is in the other spin layout.
+fn split_spins<'a, T, U>(
+    layout: &SpinData<U>,
+    data: &[&'a SpinData<T>],
+) -> Result<SpinData<Vec<&'a T>>, SpinLayoutError> {
+    let mismatch = SpinLayoutError("eigenvalues", "projected weights");
+    match layout {
+        SpinData::NonPolarized(_) => data
+            .iter()
+            .map(|data| match data {
+                SpinData::NonPolarized(data) => Ok(data),
+                SpinData::SpinPolarized(_) => Err(mismatch),
+            })
+            .collect::<Result<Vec<&T>, SpinLayoutError>>()
+            .map(SpinData::NonPolarized),
+        SpinData::SpinPolarized(_) => data
+            .iter()
+            .map(|data| match data {
+                SpinData::SpinPolarized([up, down]) => Ok((up, down)),
+                SpinData::NonPolarized(_) => Err(mismatch),
+            })
+            .collect::<Result<(Vec<&T>, Vec<&T>), SpinLayoutError>>()
+            .map(|(up, down)| SpinData::SpinPolarized([up, down])),
+    }
+}
 
-            // Return a result array for four am_channels
-            factors
-                .into_par_iter()
-                .zip(weights_k.par_iter())
-                .map(|(factor_arr, am_weights)| {
-                    let mut local = Array2::zeros((4, energy_grid.len()));
-                    local.row_mut(0).assign(&(&factor_arr * am_weights.s));
-                    local.row_mut(1).assign(&(&factor_arr * am_weights.p));
-                    local.row_mut(2).assign(&(&factor_arr * am_weights.d));
-                    local.row_mut(3).assign(&(&factor_arr * am_weights.f));
-                    local
-                })
-                // Reduce all results of eigenvalues
-                .reduce(|| Array2::zeros((4, energy_grid.len())), |acc, e| acc + e)
-        })
-        .reduce(|| Array2::zeros((4, energy_grid.len())), |acc, e| acc + e);
-    PDOSResult {
-        s: result.row(0).to_vec(),
-        p: result.row(1).to_vec(),
-        d: result.row(2).to_vec(),
-        f: result.row(3).to_vec(),
+/// Inverse of `split_spins`: the spin data of each projector
+fn merge_spins<T>(data: SpinData<Vec<T>>) -> Vec<SpinData<T>> {
+    match data {
+        SpinData::NonPolarized(data) => data.into_iter().map(SpinData::NonPolarized).collect(),
+        SpinData::SpinPolarized([up, down]) => up
+            .into_iter()
+            .zip(down)
+            .map(|(up, down)| SpinData::SpinPolarized([up, down]))
+            .collect(),
     }
 }
 
@@ -195,7 +290,7 @@ mod test {
         bands::BandsParser, pdos_weights_parser::parse_pdos_weight_file, projectors::PDOSConfig,
     };
 
-    use super::{PDOSResult, calculate_pdos};
+    use super::{BroadeningKernel, PDOSResult, calculate_pdos};
 
     const BANDS_FILE: &str = "/home/tony/Downloads/cosxmos2_DOS/cosxmos2_DOS.bands";
     const PDOS_FILE: &str = "/home/tony/Downloads/cosxmos2_DOS/cosxmos2_DOS.pdos_bin";
@@ -337,10 +432,19 @@ atoms = [1,]
             .projectors
             .iter()
             .map(|proj_conf| {
-                proj_conf.project_pdos_from_config(&config.species_mapping(), &pdos_weights)
+                proj_conf
+                    .project_pdos_from_config(&config.species_mapping(None).unwrap(), &pdos_weights)
+                    .unwrap()
             })
             .for_each(|projected_weights| {
-                let result = calculate_pdos(&band_structure, &projected_weights, &energy_grid, 0.1);
+                let result = calculate_pdos(
+                    &band_structure,
+                    &projected_weights,
+                    &energy_grid,
+                    &BroadeningKernel::Gaussian,
+                    0.1,
+                )
+                .unwrap();
                 match result {
                     crate::fundamental::SpinData::NonPolarized(_item) => todo!(),
                     crate::fundamental::SpinData::SpinPolarized([up, down]) => {
@@ -377,10 +481,19 @@ atoms = [1,]
             .projectors
             .iter()
             .map(|proj_conf| {
-                proj_conf.project_pdos_from_config(&config.species_mapping(), &pdos_weights)
+                proj_conf
+                    .project_pdos_from_config(&config.species_mapping(None).unwrap(), &pdos_weights)
+                    .unwrap()
             })
             .for_each(|projected_weights| {
-                let result = calculate_pdos(&band_structure, &projected_weights, &energy_grid, 0.1);
+                let result = calculate_pdos(
+                    &band_structure,
+                    &projected_weights,
+                    &energy_grid,
+                    &BroadeningKernel::Gaussian,
+                    0.1,
+                )
+                .unwrap();
                 match result {
                     crate::fundamental::SpinData::NonPolarized(res) => {
                         let csv_path = "Mg2SiO4_Dy_Bandstr_edft_Dy_pdos.csv";
@@ -0,0 +1,131 @@
+use crate::{
+    fundamental::BandStructure,
+    pdos_compute::{KpointGrid, KpointGridError},
+};
+
+/// Tetrahedra tiling the Brillouin zone, built from the Monkhorst-Pack grid
+/// implied by the k-point coordinates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TetrahedronMesh {
+    /// Grid size along the three reciprocal lattice vectors
+    pub grid: [usize; 3],
+    /// Corners of each tetrahedron, as positions in `BandStructure::kpoints`
+    pub tetrahedra: Vec<[usize; 4]>,
+}
+
+impl TetrahedronMesh {
+    /// Reconstruct the grid and split it into tetrahedra, see `TetrahedronMesh::new`
+    pub fn from_band_structure(band_structure: &BandStructure) -> Result<Self, KpointGridError> {
+        KpointGrid::from_band_structure(band_structure).map(|grid| Self::new(&grid))
+    }
+
+    /// Split every grid cell into six tetrahedra sharing its shortest
+    /// main diagonal (Blöchl, Jepsen and Andersen, 1994).
+    pub fn new(grid: &KpointGrid) -> Self {
+        let [n1, n2, n3] = grid.size;
+        let corners = cell_tetrahedra(&grid.reciprocal_vectors, grid.size);
+        let tetrahedra = (0..n3)
+            .flat_map(|m3| (0..n2).flat_map(move |m2| (0..n1).map(move |m1| [m1, m2, m3])))
+            .flat_map(|origin| {
+                corners.map(|tetra| {
+                    tetra.map(|offset| {
+                        grid.kpoint([0, 1, 2].map(|axis| (origin[axis] + offset[axis]) as i64))
+                    })
+                })
+            })
+            .collect();
+        Self {
+            grid: grid.size,
+            tetrahedra,
+        }
+    }
+
+    /// Fraction of the Brillouin zone of each tetrahedron
+    pub fn tetrahedron_volume(&self) -> f64 {
+        1.0 / self.tetrahedra.len() as f64
+    }
+}
+
+/// Corner offsets of the six tetrahedra of a grid cell, all sharing
+/// the shortest of the four main diagonals.
+fn cell_tetrahedra(reciprocal: &[[f64; 3]; 3], grid: [usize; 3]) -> [[[usize; 3]; 4]; 6] {
+    // Edges of a grid cell in cartesian coordinates
+    let edges: [[f64; 3]; 3] =
+        [0, 1, 2].map(|axis| reciprocal[axis].map(|x| x / grid[axis] as f64));
+    // Diagonals start from corner `c` and go along `d_i = 1 - 2 c_i`
+    let starts = [[0, 0, 0], [1, 0, 0], [0, 1, 0], [0, 0, 1]];
+    let length = |start: &[usize; 3]| -> f64 {
+        (0..3)
+            .map(|x| {
+                (0..3)
+                    .map(|axis| (1.0 - 2.0 * start[axis] as f64) * edges[axis][x])
+                    .sum::<f64>()
+                    .powi(2)
+            })
+            .sum()
+    };
+    let start = *starts
+        .iter()
+        .min_by(|a, b| length(a).total_cmp(&length(b)))
+        .unwrap();
+    // Each permutation of the axes is a path along the cell edges
+    // from `start` to the opposite corner
+    let permutations = [
+        [0, 1, 2],
+        [0, 2, 1],
+        [1, 0, 2],
+        [1, 2, 0],
+        [2, 0, 1],
+        [2, 1, 0],
+    ];
+    permutations.map(|order| {
+        let mut corner = start;
+        let mut path = [start; 4];
+        order.iter().enumerate().for_each(|(step, &axis)| {
+            corner[axis] = 1 - corner[axis];
+            path[step + 1] = corner;
+        });
+        path
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TetrahedronMesh, cell_tetrahedra};
+    use crate::test_fixtures::sample_grid_band_structure;
+
+    #[test]
+    fn test_mesh() {
+        let band_structure = sample_grid_band_structure(4, false);
+        let mesh = TetrahedronMesh::from_band_structure(&band_structure).unwrap();
+        assert_eq!(mesh.grid, [4, 4, 1]);
+        assert_eq!(mesh.tetrahedra.len(), 16 * 6);
+        // 96 tetrahedra x 4 corners spread evenly over the 16 grid points,
+        // each k-point stands for itself and its time-reversal partner
+        let mut counts = vec![0; band_structure.kpoints.len()];
+        mesh.tetrahedra
+            .iter()
+            .flatten()
+            .for_each(|&i| counts[i] += 1);
+        assert!(counts.iter().all(|&c| c == 2 * 96 * 4 / 16));
+    }
+
+    #[test]
+    fn test_cell_tetrahedra() {
+        // Cubic cell: all diagonals are equally long, the first one is taken
+        let reciprocal = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        let tetrahedra = cell_tetrahedra(&reciprocal, [2, 2, 2]);
+        tetrahedra.iter().for_each(|tetra| {
+            assert_eq!(tetra[0], [0, 0, 0]);
+            assert_eq!(tetra[3], [1, 1, 1]);
+        });
+        // Reciprocal lattice of body-centred cubic is face-centred,
+        // b1 + b2 + b3 is longer than -b1 + b2 + b3
+        let fcc = [[0.0, 1.0, 1.0], [1.0, 0.0, 1.0], [1.0, 1.0, 0.0]];
+        let tetrahedra = cell_tetrahedra(&fcc, [4, 4, 4]);
+        tetrahedra.iter().for_each(|tetra| {
+            assert_eq!(tetra[0], [1, 0, 0]);
+            assert_eq!(tetra[3], [0, 1, 1]);
+        });
+    }
+}
@@ -0,0 +1,337 @@
+use ndarray::Array2;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+use crate::fundamental::{
+    AngularChannels, BandStructure, EigenvalueVec, KpointVec, SpinData, SpinLayoutError,
+};
+
+use super::{PDOSResult, fermi_shifted_eigenvalues};
+
+mod mesh;
+
+pub use mesh::TetrahedronMesh;
+
+/// Calculate projected DOS by the linear tetrahedron method.
+/// Eigenvalues and projected weights are linearly interpolated inside each
+/// tetrahedron of `mesh`, the DOS at each grid point is the number of states
+/// falling into the bin around it divided by the bin width, so the PDOS
+/// integrates exactly to the projected weights within the grid.
+/// `blochl_correction` adds the correction of Blöchl, Jepsen and Andersen
+/// (1994) for the curvature of the bands, which removes most of the error of
+/// the linear interpolation.
+/// # Returns
+/// Same spin layout as `calculate_pdos`, fails when `projected_weights` is
+/// not in the spin layout of the bands.
+pub fn calculate_pdos_tetrahedron(
+    band_structure: &BandStructure,
+    projected_weights: &SpinData<KpointVec<EigenvalueVec<AngularChannels>>>,
+    energy_grid: &[f64],
+    mesh: &TetrahedronMesh,
+    blochl_correction: bool,
+) -> Result<SpinData<PDOSResult>, SpinLayoutError> {
+    let eigenvalues = fermi_shifted_eigenvalues(band_structure);
+    let spin_pdos = |(eigens, pdos_weights), spin_coeff| {
+        calc_spin_pdos_tetrahedron(
+            eigens,
+            pdos_weights,
+            energy_grid,
+            mesh,
+            blochl_correction,
+            spin_coeff,
+        )
+    };
+    match eigenvalues
+        .zip(projected_weights)
+        .ok_or(SpinLayoutError("eigenvalues", "projected weights"))?
+    {
+        SpinData::NonPolarized(channel) => Ok(SpinData::NonPolarized(spin_pdos(channel, 2.0))),
+        SpinData::SpinPolarized(channels) => Ok(SpinData::SpinPolarized(
+            channels.map(|channel| spin_pdos(channel, 1.0)),
+        )),
+    }
+}
+
+fn calc_spin_pdos_tetrahedron(
+    eigenvalues: &KpointVec<EigenvalueVec<f64>>,
+    pdos_weights: &KpointVec<EigenvalueVec<AngularChannels>>,
+    energy_grid: &[f64],
+    mesh: &TetrahedronMesh,
+    blochl_correction: bool,
+    spin_coeff: f64,
+) -> PDOSResult {
+    let points = energy_grid.len();
+    let edges = bin_edges(energy_grid);
+    let volume = mesh.tetrahedron_volume();
+    let nbands = eigenvalues
+        .iter()
+        .map(|eigens| eigens.len())
+        .min()
+        .unwrap_or(0);
+    let result = mesh
+        .tetrahedra
+        .par_iter()
+        .fold(
+            || Array2::zeros((4, points)),
+            |mut acc, tetra| {
+                (0..nbands).for_each(|band| {
+                    let energies = tetra.map(|k| eigenvalues[k][band]);
+                    let mut order = [0, 1, 2, 3];
+                    order.sort_by(|&a, &b| energies[a].total_cmp(&energies[b]));
+                    let sorted = order.map(|i| energies[i]);
+                    // Only the edges between the lowest and highest corner
+                    // energies see the integrated weights change
+                    let first = edges.partition_point(|&e| e <= sorted[0]).saturating_sub(1);
+                    let last = edges.partition_point(|&e| e < sorted[3]).min(points);
+                    if first >= last {
+                        return;
+                    }
+                    let channels = tetra.map(|k| pdos_weights[k][band]);
+                    let corner_weights = |energy: f64| -> [f64; 4] {
+                        let sorted_weights =
+                            integrated_weights(sorted, energy, volume, blochl_correction);
+                        let mut weights = [0.0; 4];
+                        order
+                            .iter()
+                            .zip(sorted_weights)
+                            .for_each(|(&corner, w)| weights[corner] = w);
+                        weights
+                    };
+                    let mut lower = corner_weights(edges[first]);
+                    (first..last).for_each(|bin| {
+                        let upper = corner_weights(edges[bin + 1]);
+                        let width = edges[bin + 1] - edges[bin];
+                        let mut local = AngularChannels::default();
+                        (0..4).for_each(|corner| {
+                            let states = (upper[corner] - lower[corner]) / width;
+                            local.s += states * channels[corner].s;
+                            local.p += states * channels[corner].p;
+                            local.d += states * channels[corner].d;
+                            local.f += states * channels[corner].f;
+                        });
+                        acc[[0, bin]] += spin_coeff * local.s;
+                        acc[[1, bin]] += spin_coeff * local.p;
+                        acc[[2, bin]] += spin_coeff * local.d;
+                        acc[[3, bin]] += spin_coeff * local.f;
+                        lower = upper;
+                    });
+                });
+                acc
+            },
+        )
+        .reduce(|| Array2::zeros((4, points)), |acc, e| acc + e);
+    PDOSResult {
+        s: result.row(0).to_vec(),
+        p: result.row(1).to_vec(),
+        d: result.row(2).to_vec(),
+        f: result.row(3).to_vec(),
+    }
+}
+
+/// Edges of the bins around each grid point: midpoints between neighbours,
+/// extended by half a step at both ends
+fn bin_edges(energy_grid: &[f64]) -> Vec<f64> {
+    let points = energy_grid.len();
+    match points {
+        0 => Vec::new(),
+        1 => vec![energy_grid[0] - 0.5, energy_grid[0] + 0.5],
+        _ => {
+            let mut edges = Vec::with_capacity(points + 1);
+            edges.push(energy_grid[0] - 0.5 * (energy_grid[1] - energy_grid[0]));
+            edges.extend(energy_grid.windows(2).map(|pair| 0.5 * (pair[0] + pair[1])));
+            edges.push(
+                energy_grid[points - 1] + 0.5 * (energy_grid[points - 1] - energy_grid[points - 2]),
+            );
+            edges
+        }
+    }
+}
+
+/// Integrated weights of the four corners for the states below `energy`,
+/// with `sorted` corner energies in ascending order (Blöchl, Jepsen and
+/// Andersen, 1994, Appendix B). The weights sum to the occupied fraction
+/// of `volume`.
+fn integrated_weights(
+    sorted: [f64; 4],
+    energy: f64,
+    volume: f64,
+    blochl_correction: bool,
+) -> [f64; 4] {
+    let [e1, e2, e3, e4] = sorted;
+    let quarter = volume / 4.0;
+    let mut weights = if energy <= e1 {
+        return [0.0; 4];
+    } else if energy < e2 {
+        let x = energy - e1;
+        let c = quarter * x.powi(3) / ((e2 - e1) * (e3 - e1) * (e4 - e1));
+        let ratios = [0.0, x / (e2 - e1), x / (e3 - e1), x / (e4 - e1)];
+        let sum: f64 = ratios.iter().sum();
+        [c * (4.0 - sum), c * ratios[1], c * ratios[2], c * ratios[3]]
+    } else if energy < e3 {
+        let c1 = quarter * (energy - e1).powi(2) / ((e4 - e1) * (e3 - e1));
+        let c2 = quarter * (energy - e1) * (energy - e2) * (e3 - energy)
+            / ((e4 - e1) * (e3 - e2) * (e3 - e1));
+        let c3 =
+            quarter * (energy - e2).powi(2) * (e4 - energy) / ((e4 - e2) * (e3 - e2) * (e4 - e1));
+        [
+            c1 + (c1 + c2) * (e3 - energy) / (e3 - e1) + (c1 + c2 + c3) * (e4 - energy) / (e4 - e1),
+            c1 + c2 + c3 + (c2 + c3) * (e3 - energy) / (e3 - e2) + c3 * (e4 - energy) / (e4 - e2),
+            (c1 + c2) * (energy - e1) / (e3 - e1) + (c2 + c3) * (energy - e2) / (e3 - e2),
+            (c1 + c2 + c3) * (energy - e1) / (e4 - e1) + c3 * (energy - e2) / (e4 - e2),
+        ]
+    } else if energy < e4 {
+        let x = e4 - energy;
+        let c = quarter * x.powi(3) / ((e4 - e1) * (e4 - e2) * (e4 - e3));
+        let ratios = [x / (e4 - e1), x / (e4 - e2), x / (e4 - e3), 0.0];
+        let sum: f64 = ratios.iter().sum();
+        [
+            quarter - c * ratios[0],
+            quarter - c * ratios[1],
+            quarter - c * ratios[2],
+            quarter - c * (4.0 - sum),
+        ]
+    } else {
+        return [quarter; 4];
+    };
+    if blochl_correction {
+        let dos = tetrahedron_dos(sorted, energy, volume);
+        let sum: f64 = sorted.iter().sum();
+        weights
+            .iter_mut()
+            .zip(sorted)
+            .for_each(|(w, e)| *w += dos / 40.0 * (sum - 4.0 * e));
+    }
+    weights
+}
+
+/// DOS of one tetrahedron at `energy`, the derivative of the sum of
+/// `integrated_weights` without correction
+fn tetrahedron_dos(sorted: [f64; 4], energy: f64, volume: f64) -> f64 {
+    let [e1, e2, e3, e4] = sorted;
+    if energy <= e1 || energy >= e4 {
+        0.0
+    } else if energy < e2 {
+        volume * 3.0 * (energy - e1).powi(2) / ((e2 - e1) * (e3 - e1) * (e4 - e1))
+    } else if energy < e3 {
+        volume / ((e3 - e1) * (e4 - e1))
+            * (3.0 * (e2 - e1) + 6.0 * (energy - e2)
+                - 3.0 * (e3 - e1 + e4 - e2) * (energy - e2).powi(2) / ((e3 - e2) * (e4 - e2)))
+    } else {
+        volume * 3.0 * (e4 - energy).powi(2) / ((e4 - e1) * (e4 - e2) * (e4 - e3))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        fundamental::{AngularChannels, EigenvalueVec, KpointVec, SpinData, SpinLayoutError},
+        test_fixtures::sample_grid_band_structure,
+    };
+
+    use super::{TetrahedronMesh, calculate_pdos_tetrahedron, integrated_weights, tetrahedron_dos};
+
+    #[test]
+    fn test_integrated_weights() {
+        let sorted = [-1.0, -0.3, 0.2, 1.1];
+        let volume = 0.5;
+        // Continuous at the corner energies and full above the highest one
+        [false, true].into_iter().for_each(|blochl| {
+            sorted.iter().for_each(|&e| {
+                let below: f64 = integrated_weights(sorted, e - 1e-9, volume, blochl)
+                    .iter()
+                    .sum();
+                let above: f64 = integrated_weights(sorted, e + 1e-9, volume, blochl)
+                    .iter()
+                    .sum();
+                assert!((below - above).abs() < 1e-7);
+            });
+            let full = integrated_weights(sorted, 2.0, volume, blochl);
+            assert!(full.iter().all(|&w| (w - volume / 4.0).abs() < 1e-15));
+        });
+        // The summed weights integrate the tetrahedron DOS
+        let steps = 10000;
+        let step = (sorted[3] - sorted[0]) / steps as f64;
+        let mut integral = 0.0;
+        (0..steps).for_each(|i| {
+            let e = sorted[0] + (i as f64 + 0.5) * step;
+            integral += tetrahedron_dos(sorted, e, volume) * step;
+            let total: f64 = integrated_weights(sorted, e + 0.5 * step, volume, false)
+                .iter()
+                .sum();
+            assert!((total - integral).abs() < 1e-6, "{e}: {total} {integral}");
+        });
+        // Blöchl correction only moves weights between the corners
+        let plain: f64 = integrated_weights(sorted, 0.0, volume, false).iter().sum();
+        let corrected: f64 = integrated_weights(sorted, 0.0, volume, true).iter().sum();
+        assert!((plain - corrected).abs() < 1e-15);
+    }
+
+    #[test]
+    fn test_tetrahedron_pdos() {
+        [false, true].into_iter().for_each(|spin_polarized| {
+            let band_structure = sample_grid_band_structure(8, spin_polarized);
+            let mesh = TetrahedronMesh::from_band_structure(&band_structure).unwrap();
+            // s weight on the lower band, p weight on the upper band
+            let channels: KpointVec<EigenvalueVec<AngularChannels>> = band_structure
+                .kpoints
+                .iter()
+                .map(|_| {
+                    EigenvalueVec(vec![
+                        AngularChannels::new(1.0, 0.0, 0.0, 0.0),
+                        AngularChannels::new(0.0, 0.5, 0.0, 0.0),
+                    ])
+                })
+                .collect();
+            let projected_weights = if spin_polarized {
+                SpinData::SpinPolarized([channels.clone(), channels])
+            } else {
+                SpinData::NonPolarized(channels)
+            };
+            let step = 0.01;
+            let energy_grid = (0..2400)
+                .map(|i| -7.0 + i as f64 * step)
+                .collect::<Vec<f64>>();
+            [false, true].into_iter().for_each(|blochl| {
+                let result = calculate_pdos_tetrahedron(
+                    &band_structure,
+                    &projected_weights,
+                    &energy_grid,
+                    &mesh,
+                    blochl,
+                )
+                .unwrap();
+                let spin_coeff = if spin_polarized { 1.0 } else { 2.0 };
+                result.for_each(|pdos| {
+                    let s: f64 = pdos.s.iter().sum::<f64>() * step;
+                    let p: f64 = pdos.p.iter().sum::<f64>() * step;
+                    assert!((s - spin_coeff).abs() < 1e-9, "{s}");
+                    assert!((p - 0.5 * spin_coeff).abs() < 1e-9, "{p}");
+                    assert!(pdos.d.iter().all(|&d| d == 0.0));
+                    if !blochl {
+                        assert!(pdos.s.iter().all(|&s| s >= 0.0));
+                    }
+                    // The lower band spans ±0.2 Hartree ≈ ±5.44 eV, shifted by 0.27 eV for spin down
+                    let outside = energy_grid
+                        .iter()
+                        .zip(pdos.s.iter())
+                        .filter(|(e, _)| e.abs() > 5.8)
+                        .all(|(_, &s)| s == 0.0);
+                    assert!(outside);
+                });
+            });
+        });
+        // Non-polarized weights on spin polarized bands
+        let band_structure = sample_grid_band_structure(8, true);
+        let mesh = TetrahedronMesh::from_band_structure(&band_structure).unwrap();
+        let weights = sample_grid_band_structure(8, false)
+            .eigenvalues
+            .map(|kpts| {
+                kpts.iter()
+                    .map(|eigens| eigens.iter().map(|_| AngularChannels::zero()).collect())
+                    .collect()
+            });
+        assert_eq!(
+            calculate_pdos_tetrahedron(&band_structure, &weights, &[0.0, 0.1], &mesh, false),
+            Err(SpinLayoutError("eigenvalues", "projected weights"))
+        );
+    }
+}
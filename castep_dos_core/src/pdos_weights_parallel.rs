@@ -0,0 +1,179 @@
+use std::fs::File;
+
+use memmap2::Mmap;
+use ndarray::{Array2, Array4, ArrayViewMut3, Axis, parallel::prelude::*};
+
+use crate::{
+    fundamental::{Header, KpointCoords, KpointVec, OrbitalWeights, PDOSWeights, SpinIndex},
+    helper::{parse_scalar, parse_vec_into},
+    pdos_weights_parser::{
+        ParsingError, parse_bin_header, parse_header, parse_kpoint_coords, parse_nbands_occ,
+    },
+};
+
+/// Bytes of a record holding `size` bytes, with its two size markers
+const fn record_len(size: usize) -> usize {
+    size + 8
+}
+
+/// Memory-map `file` and parse it with `parse_pdos_weight_file_parallel`.
+/// The pages are read by the OS as the k-points are decoded, the file is
+/// never copied as a whole.
+pub fn parse_pdos_weight_mmap(file: &File) -> Result<PDOSWeights, ParsingError> {
+    // SAFETY: the map is only read, and the `.pdos_weights` or `.pdos_bin`
+    // of a finished `CASTEP` run is not written while we parse it
+    let mmap = unsafe { Mmap::map(file)? };
+    parse_pdos_weight_file_parallel(&mmap)
+}
+
+/// Same as `parse_pdos_weight_file`, decoding the k-points in parallel.
+/// Once the `Header` is known the offset of each k-point follows from the
+/// record sizes. When every spin of every k-point records `max_bands`
+/// bands, as `CASTEP` writes in most runs, the offsets are computed
+/// directly. Otherwise the record markers are scanned once to find them.
+pub fn parse_pdos_weight_file_parallel(input: &[u8]) -> Result<PDOSWeights, ParsingError> {
+    let mut rest = input;
+    parse_bin_header(&mut rest)?;
+    let header = parse_header(&mut rest)?;
+    let start = input.len() - rest.len();
+    let offsets = kpoint_offsets(input, start, &header)?;
+
+    let num_spins = header.num_spins.spin_count();
+    let mut weights = Array4::zeros((
+        num_spins,
+        header.total_kpoints as usize,
+        header.max_bands as usize,
+        header.num_orbitals as usize,
+    ));
+    let kpoints = weights
+        .axis_iter_mut(Axis(1))
+        .into_par_iter()
+        .zip(offsets.par_iter())
+        .map(|(weights_k, &offset)| decode_kpoint(&mut &input[offset..], &header, weights_k))
+        .collect::<Result<Vec<(KpointCoords, Vec<usize>)>, ParsingError>>()?;
+    let band_counts =
+        Array2::from_shape_fn((num_spins, kpoints.len()), |(spin, k)| kpoints[k].1[spin]);
+    Ok(PDOSWeights::new(
+        header.spin_polarized(),
+        header.extract_orbital_states(),
+        kpoints
+            .into_iter()
+            .map(|(coords, _)| coords)
+            .collect::<KpointVec<KpointCoords>>(),
+        OrbitalWeights::new(weights, band_counts),
+    ))
+}
+
+/// Byte offset of each k-point, the data of the first one at `start`
+fn kpoint_offsets(input: &[u8], start: usize, header: &Header) -> Result<Vec<usize>, ParsingError> {
+    let num_spins = header.num_spins.spin_count();
+    let band_len = record_len(8 * header.num_orbitals as usize);
+    let spin_len = |nbands: usize| 2 * record_len(4) + nbands * band_len;
+    let kpoint_len = record_len(28) + num_spins * spin_len(header.max_bands as usize);
+    let total_kpoints = header.total_kpoints as usize;
+    // No spin records more than `max_bands`, so the sizes only add up when
+    // all of them record exactly `max_bands`
+    if input.len() - start == total_kpoints * kpoint_len {
+        return Ok((0..total_kpoints).map(|k| start + k * kpoint_len).collect());
+    }
+    let mut rest = &input[start..];
+    (0..total_kpoints)
+        .map(|_| {
+            let offset = input.len() - rest.len();
+            rest = rest
+                .get(record_len(28)..)
+                .ok_or(ParsingError::InvalidFormat)?;
+            (0..num_spins).try_for_each(|_| {
+                parse_scalar::<u32, 4>(&mut rest)?;
+                let nbands_occ = parse_nbands_occ(&mut rest, header)? as usize;
+                rest = rest
+                    .get(nbands_occ * band_len..)
+                    .ok_or(ParsingError::InvalidFormat)?;
+                Ok::<(), ParsingError>(())
+            })?;
+            Ok(offset)
+        })
+        .collect()
+}
+
+/// Decode the k-point at the start of `input` into its weights
+/// [spin, band, orbital], returning its coordinates and the bands of each
+/// spin
+fn decode_kpoint(
+    input: &mut &[u8],
+    header: &Header,
+    mut weights: ArrayViewMut3<f64>,
+) -> Result<(KpointCoords, Vec<usize>), ParsingError> {
+    let (index, coords) = parse_kpoint_coords(input)?;
+    let band_counts = weights
+        .outer_iter_mut()
+        .map(|mut weights_spin| {
+            SpinIndex::try_from(parse_scalar::<u32, 4>(input)?)?;
+            let nbands_occ = parse_nbands_occ(input, header)? as usize;
+            weights_spin
+                .outer_iter_mut()
+                .take(nbands_occ)
+                .try_for_each(|mut weights_band| {
+                    let weights_band = weights_band
+                        .as_slice_mut()
+                        .expect("orbitals are contiguous");
+                    parse_vec_into::<f64, 8>(input, weights_band).map_err(ParsingError::from)
+                })?;
+            Ok(nbands_occ)
+        })
+        .collect::<Result<Vec<usize>, ParsingError>>()?;
+    Ok((KpointCoords::new(index, coords), band_counts))
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{File, remove_file, write};
+
+    use crate::{
+        pdos_weights_parser::{ParsingError, parse_pdos_weight_file},
+        pdos_weights_writer::write_pdos_weight_file,
+        test_fixtures::{sample_pdos_bin_header, sample_pdos_weights_file},
+    };
+
+    use super::{parse_pdos_weight_file_parallel, parse_pdos_weight_mmap};
+
+    #[test]
+    fn test_parallel_parser() -> Result<(), ParsingError> {
+        [1, 2].into_iter().try_for_each(|num_spins| {
+            let mut records = sample_pdos_weights_file(num_spins);
+            records.bin_header = Some(sample_pdos_bin_header());
+            // Offsets computed from the header
+            let file = write_pdos_weight_file(&records);
+            assert_eq!(
+                parse_pdos_weight_file_parallel(&file)?,
+                parse_pdos_weight_file(&mut &file[..])?
+            );
+            // The last spin of the second k-point records one band less,
+            // found by scanning the records
+            let spin = records.kpoints[1].spins.last_mut().unwrap();
+            spin.bands.pop();
+            spin.nbands_occ -= 1;
+            let file = write_pdos_weight_file(&records);
+            let parsed = parse_pdos_weight_file_parallel(&file)?;
+            assert_eq!(parsed, parse_pdos_weight_file(&mut &file[..])?);
+            assert_eq!(
+                parsed.orbital_weights.band_count(num_spins as usize - 1, 1),
+                3
+            );
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_mmap_parser() -> Result<(), ParsingError> {
+        let file = write_pdos_weight_file(&sample_pdos_weights_file(2));
+        let path = std::env::temp_dir().join("castep_dos_test_mmap_parser.pdos_weights");
+        write(&path, &file)?;
+        let parsed = parse_pdos_weight_mmap(&File::open(&path)?);
+        remove_file(&path)?;
+        assert_eq!(parsed?, parse_pdos_weight_file(&mut &file[..])?);
+        // Cut in the middle of the last k-point
+        assert!(parse_pdos_weight_file_parallel(&file[..file.len() - 10]).is_err());
+        Ok(())
+    }
+}
@@ -2,12 +2,12 @@ use thiserror::Error;
 
 use crate::{
     fundamental::{
-        AngularMomentum, AngularMomentumConvertError, EigenvalueVec, Header, HeaderBuilder,
-        HeaderBuilderError, KpointVec, NumSpins, NumSpinsConvertError, OrbitalWeight,
-        OrbitalWeightVec, PDOSWeights, SpinData, SpinIndex, SpinIndexConvertError, WeightsPerEigen,
+        AngularMomentum, AngularMomentumConvertError, Header, HeaderBuilder, HeaderBuilderError,
+        KpointCoords, KpointVec, NumSpins, NumSpinsConvertError, OrbitalWeights, PDOSBinHeader,
+        PDOSWeights, PDOSWeightsFile, SpinIndex, SpinIndexConvertError, WeightsPerEigen,
         WeightsPerKPoint, WeightsPerSpin,
     },
-    helper::{HelperError, parse_record, parse_scalar, parse_vec, peek_record},
+    helper::{HelperError, parse_record, parse_scalar, parse_vec, parse_vec_into, peek_record},
 };
 
 #[derive(Debug, Error)]
@@ -31,77 +31,83 @@ pub enum ParsingError {
     /// Invalid format
     #[error("This is neither a valid `.pdos_weights` nor `.pdos_bin`")]
     InvalidFormat,
+    /// Error from reading a stream, see `PDOSWeightsReader`
+    #[error("When reading the input: {0}")]
+    Io(#[from] std::io::Error),
+    /// A k-point records more bands than the header declares
+    #[error("A k-point records {nbands_occ} bands, more than the {max_bands} of the header")]
+    TooManyBands {
+        /// Bands recorded at the k-point
+        nbands_occ: u32,
+        /// Maximum number of bands of the header
+        max_bands: u32,
+    },
 }
-/// Handles both `.pdos_weights` and `.pdos_bin`
+/// Handles both `.pdos_weights` and `.pdos_bin`. The weights are read
+/// straight into the array of `PDOSWeights`, without the records of
+/// `parse_pdos_weight_records`.
 pub fn parse_pdos_weight_file<'a>(input: &'a mut &'a [u8]) -> Result<PDOSWeights, ParsingError> {
-    // Skip the version and header output in the first two records of `.pdos_bin`
-    let _version: Result<f64, HelperError> = parse_scalar::<f64, 8>(input);
-    if _version.is_ok() {
-        let (_, size) = peek_record(input)?;
-        let _pdos_bin_header = parse_record(input, size)?;
-        parse_pdos_weight(input)
-    } else {
-        parse_pdos_weight(input)
-    }
+    parse_bin_header(input)?;
+    let header = parse_header(input)?;
+    let mut orbital_weights = OrbitalWeights::zeros(
+        header.num_spins.spin_count(),
+        header.total_kpoints as usize,
+        header.max_bands as usize,
+        header.num_orbitals as usize,
+    );
+    let kpoints = (0..header.total_kpoints as usize)
+        .map(|k| {
+            let (index, coords) = parse_kpoint_coords(input)?;
+            (0..header.num_spins.spin_count()).try_for_each(|spin| {
+                SpinIndex::try_from(parse_scalar::<u32, 4>(input)?)?;
+                let nbands_occ = parse_nbands_occ(input, &header)?;
+                orbital_weights
+                    .kpoint_mut(spin, k, nbands_occ as usize)
+                    .rows_mut()
+                    .into_iter()
+                    .try_for_each(|mut weights| {
+                        let weights = weights.as_slice_mut().expect("rows are contiguous");
+                        parse_vec_into::<f64, 8>(input, weights).map_err(ParsingError::from)
+                    })
+            })?;
+            Ok(KpointCoords::new(index, coords))
+        })
+        .collect::<Result<KpointVec<KpointCoords>, ParsingError>>()?;
+    Ok(PDOSWeights::new(
+        header.spin_polarized(),
+        header.extract_orbital_states(),
+        kpoints,
+        orbital_weights,
+    ))
 }
 
-/// function to parse the `.pdos_weight`
-fn parse_pdos_weight(input: &mut &[u8]) -> Result<PDOSWeights, ParsingError> {
+/// Handles both `.pdos_weights` and `.pdos_bin`, keeping every record
+/// including the version and header records of `.pdos_bin`
+pub fn parse_pdos_weight_records(input: &mut &[u8]) -> Result<PDOSWeightsFile, ParsingError> {
+    let bin_header = parse_bin_header(input)?;
     let header = parse_header(input)?;
     let kpoints = (0..header.total_kpoints)
         .map(|_| parse_kpoint(input, &header))
         .collect::<Result<Vec<WeightsPerKPoint>, ParsingError>>()?;
-    let spin_polarized = header.spin_polarized();
-    let orbital_states = header.extract_orbital_states();
-    let per_spin_to_per_kpt_data =
-        |weights_per_spin: &WeightsPerSpin| -> EigenvalueVec<OrbitalWeightVec> {
-            weights_per_spin
-                .bands
-                .iter()
-                .map(|weights_per_eigen| {
-                    weights_per_eigen
-                        .weights
-                        .iter()
-                        .map(|weight| OrbitalWeight::new(*weight))
-                        .collect::<OrbitalWeightVec>()
-                })
-                .collect::<EigenvalueVec<OrbitalWeightVec>>()
-        };
-    let orbital_weights = match header.num_spins {
-        NumSpins::One => {
-            SpinData::NonPolarized(
-                kpoints
-                    .iter() // per k-point
-                    .map(
-                        |weights_per_kpoint| {
-                            per_spin_to_per_kpt_data(weights_per_kpoint.spins.first().unwrap()) // Only one spin
-                        }, // per eigenvalue
-                    )
-                    .collect::<KpointVec<EigenvalueVec<OrbitalWeightVec>>>(),
-            )
-        }
-        NumSpins::Two => {
-            let (up, down) = kpoints
-                .iter()
-                .map(|weights_per_kpoint| {
-                    (
-                        per_spin_to_per_kpt_data(weights_per_kpoint.spins.first().unwrap()),
-                        per_spin_to_per_kpt_data(weights_per_kpoint.spins.get(1).unwrap()),
-                    )
-                })
-                .unzip();
-            SpinData::SpinPolarized([up, down])
+    Ok(PDOSWeightsFile::new(bin_header, header, kpoints))
+}
+
+/// The version and header output in the first two records of `.pdos_bin`,
+/// `None` for `.pdos_weights`
+pub(crate) fn parse_bin_header(input: &mut &[u8]) -> Result<Option<PDOSBinHeader>, ParsingError> {
+    let version: Result<f64, HelperError> = parse_scalar::<f64, 8>(input);
+    match version {
+        Ok(version) => {
+            let (_, size) = peek_record(input)?;
+            let pdos_bin_header = parse_record(input, size)?;
+            Ok(Some(PDOSBinHeader::new(version, pdos_bin_header.to_vec())))
         }
-    };
-    Ok(PDOSWeights::new(
-        spin_polarized,
-        orbital_states,
-        orbital_weights,
-    ))
+        Err(_) => Ok(None),
+    }
 }
 
 /// function to parse the header section of  `.pdos_weight`
-fn parse_header(input: &mut &[u8]) -> Result<Header, ParsingError> {
+pub(crate) fn parse_header(input: &mut &[u8]) -> Result<Header, ParsingError> {
     let total_kpoints = parse_scalar::<u32, 4>(input)?;
     let num_spins: NumSpins = parse_scalar::<u32, 4>(input)?.try_into()?;
     let num_orbitals = parse_scalar::<u32, 4>(input)?;
@@ -128,6 +134,15 @@ fn parse_header(input: &mut &[u8]) -> Result<Header, ParsingError> {
 
 /// Parse data for each k-point
 fn parse_kpoint(input: &mut &[u8], header: &Header) -> Result<WeightsPerKPoint, ParsingError> {
+    let (index, kpoint) = parse_kpoint_coords(input)?;
+    let spins = (0..header.num_spins.spin_count())
+        .map(|_| parse_weight_per_spin(input, header))
+        .collect::<Result<Vec<WeightsPerSpin>, ParsingError>>()?;
+    Ok(WeightsPerKPoint::new(index, kpoint, spins))
+}
+
+/// Global index and coordinates leading the data of a k-point
+pub(crate) fn parse_kpoint_coords(input: &mut &[u8]) -> Result<(u32, [f64; 3]), ParsingError> {
     let kp_data = parse_record(input, 28)?;
     let index = u32::from_be_bytes(
         kp_data[0..4]
@@ -149,11 +164,19 @@ fn parse_kpoint(input: &mut &[u8], header: &Header) -> Result<WeightsPerKPoint,
             .try_into()
             .map_err(HelperError::BytesIntoArray)?,
     );
-    let kpoint = [kx, ky, kz];
-    let spins = (0..header.num_spins.spin_count())
-        .map(|_| parse_weight_per_spin(input, header))
-        .collect::<Result<Vec<WeightsPerSpin>, ParsingError>>()?;
-    Ok(WeightsPerKPoint::new(index, kpoint, spins))
+    Ok((index, [kx, ky, kz]))
+}
+
+/// Number of occupied bands of a spin, at most `max_bands` of the header
+pub(crate) fn parse_nbands_occ(input: &mut &[u8], header: &Header) -> Result<u32, ParsingError> {
+    let nbands_occ = parse_scalar::<u32, 4>(input)?;
+    if nbands_occ > header.max_bands {
+        return Err(ParsingError::TooManyBands {
+            nbands_occ,
+            max_bands: header.max_bands,
+        });
+    }
+    Ok(nbands_occ)
 }
 
 /// Parse weight for each spin inside the record of a k-point
@@ -163,7 +186,7 @@ fn parse_weight_per_spin(
 ) -> Result<WeightsPerSpin, ParsingError> {
     let index = parse_scalar::<u32, 4>(input)?;
     let spin_index = SpinIndex::try_from(index)?;
-    let nbands_occ = parse_scalar::<u32, 4>(input)?;
+    let nbands_occ = parse_nbands_occ(input, header)?;
     // Parse band weights
     let bands = (0..nbands_occ)
         .map(|_| {
@@ -175,42 +198,65 @@ fn parse_weight_per_spin(
 }
 #[cfg(test)]
 mod test {
-    use std::fs::read;
-
-    use crate::pdos_weights_parser::parse_pdos_weight_file;
+    use crate::{
+        fundamental::{NumSpins, SpinPolarized},
+        pdos_weights_parser::{parse_pdos_weight_file, parse_pdos_weight_records},
+        pdos_weights_writer::write_pdos_weight_file,
+        test_fixtures::{sample_pdos_bin_header, sample_pdos_weights_file},
+    };
 
     use super::{ParsingError, parse_header};
 
-    const TEST_PDOS_WEIGHT: &str = "/home/tony/Downloads/cosxmos2_DOS/cosxmos2_DOS.pdos_weights";
-    const TEST_PDOS_WEIGHT_NO_SPIN: &str = "/home/tony/Downloads/Si/Si_DOS.pdos_weights";
-    const TEST_PDOS_BIN: &str = "/home/tony/Downloads/Si/Si_DOS.pdos_bin";
     #[test]
     fn test_header() {
-        let pdos_file = read(TEST_PDOS_WEIGHT).unwrap();
+        let pdos_file = write_pdos_weight_file(&sample_pdos_weights_file(2));
         let header = parse_header(&mut pdos_file.as_ref()).unwrap();
-        dbg!(header.total_kpoints);
-        dbg!(header.num_spins);
-        dbg!(header.num_orbitals);
-        dbg!(header.max_bands);
-        dbg!(header.orbital_am.len());
+        assert_eq!(header.total_kpoints, 3);
+        assert_eq!(header.num_spins, NumSpins::Two);
+        assert_eq!(header.num_orbitals, 6);
+        assert_eq!(header.max_bands, 4);
+        assert_eq!(header.orbital_am.len(), 6);
     }
     #[test]
     fn test_spin_pdos_weight() -> Result<(), ParsingError> {
-        let pdos_file = read(TEST_PDOS_WEIGHT).unwrap();
+        let pdos_file = write_pdos_weight_file(&sample_pdos_weights_file(2));
         let parsed_pdos = parse_pdos_weight_file(&mut &pdos_file[..])?;
-        dbg!(parsed_pdos.orbital_states);
+        assert_eq!(parsed_pdos.spin_polarized, SpinPolarized::True);
+        assert_eq!(parsed_pdos.orbital_states.len(), 6);
+        // Read into the array directly, same as through the records
+        assert_eq!(
+            parsed_pdos,
+            parse_pdos_weight_records(&mut &pdos_file[..])?.to_pdos_weights()
+        );
         Ok(())
     }
     #[test]
     fn test_pdos_weight() -> Result<(), ParsingError> {
-        let pdos_file = read(TEST_PDOS_WEIGHT_NO_SPIN).unwrap();
+        let pdos_file = write_pdos_weight_file(&sample_pdos_weights_file(1));
         let parsed_pdos = parse_pdos_weight_file(&mut &pdos_file[..])?;
-        dbg!(parsed_pdos.orbital_states);
+        assert_eq!(parsed_pdos.spin_polarized, SpinPolarized::False);
+        assert_eq!(parsed_pdos.orbital_states[5].ion_id, 2);
+        assert_eq!(parsed_pdos, sample_pdos_weights_file(1).to_pdos_weights());
         Ok(())
     }
     #[test]
+    fn test_too_many_bands() {
+        let mut records = sample_pdos_weights_file(1);
+        records.header.max_bands = 3;
+        let pdos_file = write_pdos_weight_file(&records);
+        assert!(matches!(
+            parse_pdos_weight_file(&mut &pdos_file[..]),
+            Err(ParsingError::TooManyBands {
+                nbands_occ: 4,
+                max_bands: 3
+            })
+        ));
+    }
+    #[test]
     fn test_pdos_bin() -> Result<(), ParsingError> {
-        let pdos_bin = read(TEST_PDOS_BIN).unwrap();
+        let mut records = sample_pdos_weights_file(1);
+        records.bin_header = Some(sample_pdos_bin_header());
+        let pdos_bin = write_pdos_weight_file(&records);
         let parsed_dos = parse_pdos_weight_file(&mut &pdos_bin[..]);
         assert!(parsed_dos.is_ok());
         Ok(())
@@ -0,0 +1,253 @@
+use std::io::Read;
+
+use crate::{
+    fundamental::{
+        Header, PDOSBinHeader, SpinIndex, WeightsPerEigen, WeightsPerKPoint, WeightsPerSpin,
+    },
+    helper::{parse_record, parse_scalar, parse_vec},
+    pdos_weights_parser::{ParsingError, parse_header, parse_kpoint_coords, parse_nbands_occ},
+};
+
+/// Scalar records leading the header section of `.pdos_weights`: the
+/// numbers of k-points, spins, orbitals and bands, see `parse_header`
+const COUNT_RECORDS: usize = 4;
+/// Records of `num_orbitals` `u32` following the counts: species, ion and
+/// angular momentum of each orbital
+const ORBITAL_RECORDS: usize = 3;
+/// Record of the index and coordinates of a k-point, `u32` and 3 `f64`
+const KPOINT_RECORD: usize = 28;
+/// Record of a `u32` scalar
+const SCALAR_RECORD: usize = 4;
+/// Record of the `f64` version leading `.pdos_bin`
+const VERSION_RECORD: usize = 8;
+/// Largest header string of `.pdos_bin` accepted, `CASTEP` writes 80
+/// characters
+const MAX_BIN_HEADER_RECORD: usize = 1024;
+
+/// Reads `.pdos_weights` and `.pdos_bin` from any `Read`, one k-point at a
+/// time. The header is read on construction, then each item of the
+/// iterator is the next `WeightsPerKPoint` of the file. Only one k-point is
+/// held in memory, so projection and broadening can run k-point by k-point
+/// on files too large to load, see `WeightsPerKPoint::orbital_weights`.
+///
+/// Wrap unbuffered readers such as `File` in a `BufReader`, each record is
+/// a separate read.
+pub struct PDOSWeightsReader<R: Read> {
+    reader: R,
+    /// `Some` for `.pdos_bin`
+    bin_header: Option<PDOSBinHeader>,
+    /// Header section
+    header: Header,
+    /// K-points read so far
+    kpoints_read: u32,
+    /// A failed read ends the iteration
+    failed: bool,
+}
+
+impl<R: Read> PDOSWeightsReader<R> {
+    /// Read the headers, leaving `reader` at the first k-point
+    /// Every record is checked against its known size, or the number of
+    /// orbitals of the header, before it is allocated.
+    pub fn new(mut reader: R) -> Result<Self, ParsingError> {
+        let is_scalar = |size: usize| size == SCALAR_RECORD;
+        // `.pdos_bin` starts with an `f64` version, `.pdos_weights` with
+        // the `u32` number of k-points
+        let first = read_record(&mut reader, |size| {
+            size == VERSION_RECORD || size == SCALAR_RECORD
+        })?;
+        let (bin_header, first_count) = match parse_scalar::<f64, 8>(&mut &first[..]) {
+            Ok(version) => {
+                let bin_header = read_record(&mut reader, |size| size <= MAX_BIN_HEADER_RECORD)?;
+                let size = bin_header.len() - 8;
+                let bin_header = parse_record(&mut &bin_header[..], size)?.to_vec();
+                let first_count = read_record(&mut reader, is_scalar)?;
+                (Some(PDOSBinHeader::new(version, bin_header)), first_count)
+            }
+            Err(_) => (None, first),
+        };
+        let mut counts = vec![first_count];
+        (1..COUNT_RECORDS).try_for_each(|_| {
+            counts.push(read_record(&mut reader, is_scalar)?);
+            Ok::<(), ParsingError>(())
+        })?;
+        let num_orbitals = parse_scalar::<u32, 4>(&mut &counts[2][..])? as usize;
+        let mut header_records = counts.concat();
+        (0..ORBITAL_RECORDS).try_for_each(|_| {
+            header_records.extend(read_record(&mut reader, |size| {
+                size == SCALAR_RECORD * num_orbitals
+            })?);
+            Ok::<(), ParsingError>(())
+        })?;
+        let header = parse_header(&mut &header_records[..])?;
+        Ok(Self {
+            reader,
+            bin_header,
+            header,
+            kpoints_read: 0,
+            failed: false,
+        })
+    }
+
+    /// Header section of the file
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Version and header records of `.pdos_bin`, `None` for `.pdos_weights`
+    pub fn bin_header(&self) -> Option<&PDOSBinHeader> {
+        self.bin_header.as_ref()
+    }
+
+    /// Read the records of the next k-point, mirrors `parse_kpoint`.
+    /// The length of each record follows from the header.
+    fn read_kpoint(&mut self) -> Result<WeightsPerKPoint, ParsingError> {
+        let num_orbitals = self.header.num_orbitals as usize;
+        let record = read_record(&mut self.reader, |size| size == KPOINT_RECORD)?;
+        let (index, kpoint) = parse_kpoint_coords(&mut &record[..])?;
+        let spins = (0..self.header.num_spins.spin_count())
+            .map(|_| {
+                let record = read_record(&mut self.reader, |size| size == SCALAR_RECORD)?;
+                let spin_index = SpinIndex::try_from(parse_scalar::<u32, 4>(&mut &record[..])?)?;
+                let record = read_record(&mut self.reader, |size| size == SCALAR_RECORD)?;
+                let nbands_occ = parse_nbands_occ(&mut &record[..], &self.header)?;
+                let bands = (0..nbands_occ)
+                    .map(|_| {
+                        let record =
+                            read_record(&mut self.reader, |size| size == 8 * num_orbitals)?;
+                        let weights = parse_vec::<f64, 8>(&mut &record[..], num_orbitals)?;
+                        Ok(WeightsPerEigen::new(weights))
+                    })
+                    .collect::<Result<Vec<WeightsPerEigen>, ParsingError>>()?;
+                Ok(WeightsPerSpin::new(spin_index, nbands_occ, bands))
+            })
+            .collect::<Result<Vec<WeightsPerSpin>, ParsingError>>()?;
+        Ok(WeightsPerKPoint::new(index, kpoint, spins))
+    }
+}
+
+impl<R: Read> Iterator for PDOSWeightsReader<R> {
+    type Item = Result<WeightsPerKPoint, ParsingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.kpoints_read == self.header.total_kpoints {
+            return None;
+        }
+        let kpoint = self.read_kpoint();
+        match kpoint {
+            Ok(_) => self.kpoints_read += 1,
+            Err(_) => self.failed = true,
+        }
+        Some(kpoint)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.header.total_kpoints - self.kpoints_read) as usize;
+        (0, Some(remaining))
+    }
+}
+
+/// One Fortran record with its leading and ending size markers, as the
+/// parsers of `helper` expect it. A record of a size not `accepted` is
+/// rejected before its content is allocated, so a corrupt size marker can
+/// not request gigabytes.
+fn read_record<R: Read>(
+    reader: &mut R,
+    accepted: impl Fn(usize) -> bool,
+) -> Result<Vec<u8>, ParsingError> {
+    let mut marker = [0_u8; 4];
+    reader.read_exact(&mut marker)?;
+    let size = u32::from_be_bytes(marker) as usize;
+    if !accepted(size) {
+        return Err(ParsingError::InvalidFormat);
+    }
+    let mut record = vec![0_u8; size + 8];
+    record[..4].copy_from_slice(&marker);
+    reader.read_exact(&mut record[4..])?;
+    Ok(record)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use crate::{
+        fundamental::WeightsPerKPoint,
+        pdos_weights_parser::ParsingError,
+        pdos_weights_writer::write_pdos_weight_file,
+        test_fixtures::{sample_pdos_bin_header, sample_pdos_weights_file},
+    };
+
+    use super::{COUNT_RECORDS, ORBITAL_RECORDS, PDOSWeightsReader};
+
+    #[test]
+    fn test_streaming_reader() -> Result<(), ParsingError> {
+        [(1, false), (2, false), (1, true)]
+            .into_iter()
+            .try_for_each(|(num_spins, bin)| {
+                let mut records = sample_pdos_weights_file(num_spins);
+                if bin {
+                    records.bin_header = Some(sample_pdos_bin_header());
+                }
+                let file = write_pdos_weight_file(&records);
+                let reader = PDOSWeightsReader::new(Cursor::new(&file))?;
+                assert_eq!(reader.header(), &records.header);
+                assert_eq!(reader.bin_header(), records.bin_header.as_ref());
+                let kpoints = reader.collect::<Result<Vec<WeightsPerKPoint>, ParsingError>>()?;
+                assert_eq!(kpoints, records.kpoints);
+                // Each k-point alone holds its slice of the whole array
+                let orbital_weights = records.to_pdos_weights().orbital_weights;
+                kpoints.iter().enumerate().for_each(|(k, kpoint)| {
+                    let alone = kpoint.orbital_weights(&records.header);
+                    (0..num_spins as usize).for_each(|spin| {
+                        assert_eq!(alone.kpoint(spin, 0), orbital_weights.kpoint(spin, k));
+                    });
+                });
+                Ok(())
+            })
+    }
+
+    #[test]
+    fn test_truncated_stream() -> Result<(), ParsingError> {
+        let file = write_pdos_weight_file(&sample_pdos_weights_file(1));
+        let mut reader = PDOSWeightsReader::new(Cursor::new(&file[..file.len() - 10]))?;
+        assert!(reader.next().unwrap().is_ok());
+        assert!(reader.next().unwrap().is_ok());
+        assert!(matches!(reader.next(), Some(Err(ParsingError::Io(_)))));
+        assert!(reader.next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupt_record_size() -> Result<(), ParsingError> {
+        let file = write_pdos_weight_file(&sample_pdos_weights_file(1));
+        // Offset of the size marker of the record after the first `records`
+        let marker_offset = |records: usize| {
+            (0..records).fold(0, |offset, _| {
+                let size = u32::from_be_bytes(file[offset..offset + 4].try_into().unwrap());
+                offset + size as usize + 8
+            })
+        };
+        let corrupt = |offset: usize| {
+            let mut corrupt = file.clone();
+            corrupt[offset..offset + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+            corrupt
+        };
+        // The first k-point
+        let kpoint = corrupt(marker_offset(COUNT_RECORDS + ORBITAL_RECORDS));
+        let mut reader = PDOSWeightsReader::new(Cursor::new(&kpoint))?;
+        assert!(matches!(
+            reader.next(),
+            Some(Err(ParsingError::InvalidFormat))
+        ));
+        assert!(reader.next().is_none());
+        // The number of orbitals, and the orbital species of the header
+        [2, COUNT_RECORDS].into_iter().for_each(|records| {
+            let header = corrupt(marker_offset(records));
+            assert!(matches!(
+                PDOSWeightsReader::new(Cursor::new(&header)),
+                Err(ParsingError::InvalidFormat)
+            ));
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{
+    fundamental::{Header, PDOSWeightsFile, WeightsPerKPoint, WeightsPerSpin},
+    helper::{write_record, write_scalar, write_vec},
+};
+
+/// Serialize to the Fortran big-endian record format of `CASTEP`.
+/// Writes `.pdos_bin` when `bin_header` is present, otherwise `.pdos_weights`.
+pub fn write_pdos_weight_file(pdos_weights_file: &PDOSWeightsFile) -> Vec<u8> {
+    let mut output = Vec::new();
+    if let Some(bin_header) = &pdos_weights_file.bin_header {
+        write_scalar::<f64, 8>(&mut output, bin_header.version);
+        write_record(&mut output, &bin_header.header);
+    }
+    write_header(&mut output, &pdos_weights_file.header);
+    pdos_weights_file
+        .kpoints
+        .iter()
+        .for_each(|kpoint| write_kpoint(&mut output, kpoint));
+    output
+}
+
+/// Write the header section, mirrors `parse_header`
+fn write_header(output: &mut Vec<u8>, header: &Header) {
+    write_scalar::<u32, 4>(output, header.total_kpoints);
+    write_scalar::<u32, 4>(output, header.num_spins.spin_count() as u32);
+    write_scalar::<u32, 4>(output, header.num_orbitals);
+    write_scalar::<u32, 4>(output, header.max_bands);
+    write_vec::<u32, 4>(output, &header.orbital_species);
+    write_vec::<u32, 4>(output, &header.orbital_ion);
+    let orbital_am = header
+        .orbital_am
+        .iter()
+        .map(|&am| u32::from(am))
+        .collect::<Vec<u32>>();
+    write_vec::<u32, 4>(output, &orbital_am);
+}
+
+/// Write data of each k-point, mirrors `parse_kpoint`
+fn write_kpoint(output: &mut Vec<u8>, kpoint: &WeightsPerKPoint) {
+    let mut kp_data = Vec::with_capacity(28);
+    kp_data.extend_from_slice(&kpoint.index.to_be_bytes());
+    kpoint
+        .kpoint
+        .iter()
+        .for_each(|k| kp_data.extend_from_slice(&k.to_be_bytes()));
+    write_record(output, &kp_data);
+    kpoint
+        .spins
+        .iter()
+        .for_each(|spin| write_weight_per_spin(output, spin));
+}
+
+/// Write weight for each spin, mirrors `parse_weight_per_spin`
+fn write_weight_per_spin(output: &mut Vec<u8>, weights_per_spin: &WeightsPerSpin) {
+    write_scalar::<u32, 4>(output, weights_per_spin.index as u32);
+    write_scalar::<u32, 4>(output, weights_per_spin.nbands_occ);
+    weights_per_spin
+        .bands
+        .iter()
+        .for_each(|band| write_vec::<f64, 8>(output, &band.weights));
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        fundamental::{PDOSWeightsFile, SpinIndex},
+        pdos_weights_parser::{parse_pdos_weight_file, parse_pdos_weight_records},
+        test_fixtures::{sample_pdos_bin_header, sample_pdos_weights_file},
+    };
+
+    use super::write_pdos_weight_file;
+
+    #[test]
+    fn test_round_trip() {
+        [1, 2].into_iter().for_each(|num_spins| {
+            [None, Some(sample_pdos_bin_header())]
+                .into_iter()
+                .for_each(|bin_header| {
+                    let mut records = sample_pdos_weights_file(num_spins);
+                    records.bin_header = bin_header;
+                    let bytes = write_pdos_weight_file(&records);
+                    let parsed = parse_pdos_weight_records(&mut &bytes[..]).unwrap();
+                    assert_eq!(parsed, records);
+                    assert_eq!(write_pdos_weight_file(&parsed), bytes);
+                })
+        });
+    }
+
+    #[test]
+    fn test_from_pdos_weights() {
+        let records = sample_pdos_weights_file(2);
+        let bytes = write_pdos_weight_file(&records);
+        let pdos_weights = parse_pdos_weight_file(&mut &bytes[..]).unwrap();
+        let rebuilt = PDOSWeightsFile::from_pdos_weights(&pdos_weights, None);
+        assert_eq!(rebuilt, records);
+        assert_eq!(rebuilt.kpoints[0].spins[1].index, SpinIndex::Two);
+        assert_eq!(write_pdos_weight_file(&rebuilt), bytes);
+    }
+}
@@ -2,19 +2,63 @@ use std::collections::HashMap;
 use std::ops::Deref;
 
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 use crate::fundamental::{
-    AngularChannels, AngularMomentum, EigenvalueVec, KpointVec, OrbitalState, OrbitalWeightVec,
+    AngularChannels, AngularMomentum, EigenvalueVec, KpointVec, OrbitalLabel, OrbitalState,
     PDOSWeights, SpinData,
 };
+use ndarray::Array2;
 use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
 
+#[derive(Debug, Error, PartialEq)]
+/// Invalid selection in a projector config
+pub enum ProjectionError {
+    /// The species symbol is not in `mapping`
+    #[error(
+        "projector `{projector}`, selection {selection}: species `{species}` is not in `mapping`"
+    )]
+    UnknownSpecies {
+        /// Name of the projector
+        projector: String,
+        /// Position of the selection in the projector, starts from 1
+        selection: usize,
+        /// Species symbol
+        species: String,
+    },
+    /// The species has no ion with this index in `orbital_states`
+    #[error(
+        "projector `{projector}`, selection {selection}: species `{species}` has no atom {atom}"
+    )]
+    UnknownAtom {
+        /// Name of the projector
+        projector: String,
+        /// Position of the selection in the projector, starts from 1
+        selection: usize,
+        /// Species symbol
+        species: String,
+        /// Ion index within the species
+        atom: u32,
+    },
+    /// The species is in `mapping`, but no orbital in `orbital_states` has its rank
+    #[error("projector `{projector}`, selection {selection}: species `{species}` has no orbitals")]
+    SpeciesWithoutOrbitals {
+        /// Name of the projector
+        projector: String,
+        /// Position of the selection in the projector, starts from 1
+        selection: usize,
+        /// Species symbol
+        species: String,
+    },
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 /// Config file for projector specifications
 pub struct PDOSConfig {
-    /// Species mapping defined following the seed.cell
-    #[serde(rename = "mapping")]
-    pub species_mapping: Vec<Mapping>,
+    /// Species mapping defined following the seed.cell.
+    /// If omitted, the mapping is derived from the `.cell` of the seed.
+    #[serde(rename = "mapping", default, skip_serializing_if = "Option::is_none")]
+    pub species_mapping: Option<Vec<Mapping>>,
     #[serde(rename = "projector")]
     /// Groups of projector config
     pub projectors: Vec<ProjectorConfig>,
@@ -24,34 +68,45 @@ pub struct PDOSConfig {
 }
 
 impl PDOSConfig {
-    /// Generate the species_mapping `HashMap`
-    pub fn species_mapping(&self) -> HashMap<&str, u32> {
+    /// Generate the species_mapping `HashMap`.
+    /// The `mapping` in config is prioritized, `fallback` (e.g. from
+    /// `CellFile::species_mapping`) is used when it is left out.
+    /// Returns `None` if neither is available.
+    pub fn species_mapping<'a>(
+        &'a self,
+        fallback: Option<&'a [Mapping]>,
+    ) -> Option<HashMap<&'a str, u32>> {
         self.species_mapping
-            .iter()
-            .map(|mapping| (mapping.species.as_ref(), mapping.rank))
-            .collect()
+            .as_deref()
+            .or(fallback)
+            .map(|mappings| {
+                mappings
+                    .iter()
+                    .map(|mapping| (mapping.species.as_ref(), mapping.rank))
+                    .collect()
+            })
     }
 
     /// Generate example
     pub fn example() -> Self {
         Self {
-            species_mapping: vec![Mapping {
-                species: SpeciesSymbol("C".to_string()),
-                rank: 1,
-            }],
+            species_mapping: Some(vec![Mapping::new("C", 1)]),
             projectors: vec![ProjectorConfig {
                 name: Some("Example".to_string()),
                 label: None,
                 selections: Some(vec![Selection {
                     species: SpeciesSymbol("C".to_string()),
                     atoms: Some(vec![1, 2]),
+                    orbitals: None,
                 }]),
+                m_resolved: false,
             }],
         }
     }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
+/// Bind the species symbol to its rank in seed.cell
 pub struct Mapping {
     /// Species symbol
     species: SpeciesSymbol,
@@ -59,6 +114,26 @@ pub struct Mapping {
     rank: u32,
 }
 
+impl Mapping {
+    /// Constructor
+    pub fn new(species: &str, rank: u32) -> Self {
+        Self {
+            species: SpeciesSymbol(species.to_string()),
+            rank,
+        }
+    }
+
+    /// Access method
+    pub fn species(&self) -> &SpeciesSymbol {
+        &self.species
+    }
+
+    /// Access method
+    pub fn rank(&self) -> u32 {
+        self.rank
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(transparent)]
 /// Newtype to represent the symbol of species
@@ -89,6 +164,10 @@ pub struct ProjectorConfig {
     /// If none is provided it is equivalent to calculating
     /// total density of states for the whole system
     pub selections: Option<Vec<Selection>>,
+    /// Split into one projector for each sub-orbital of the selections,
+    /// see `m_resolved_projectors`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub m_resolved: bool,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -99,6 +178,10 @@ pub struct Selection {
     /// Ions id of this species (Start at 1!!)
     /// If none is provided, default to all atoms of this species.
     atoms: Option<Vec<u32>>,
+    /// Sub-orbitals to take, e.g. `["t2g"]` or `["px", "py"]`.
+    /// If none is provided, default to all orbitals.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    orbitals: Option<Vec<OrbitalLabel>>,
 }
 
 impl Selection {
@@ -111,6 +194,20 @@ impl Selection {
     pub fn atoms(&self) -> Option<&Vec<u32>> {
         self.atoms.as_ref()
     }
+
+    /// Access method
+    pub fn orbitals(&self) -> Option<&Vec<OrbitalLabel>> {
+        self.orbitals.as_ref()
+    }
+
+    /// The selection takes the orbital
+    fn takes_orbital(&self, state: &OrbitalState) -> bool {
+        self.orbitals.as_ref().is_none_or(|labels| {
+            labels
+                .iter()
+                .any(|label| label.contains(state.angular_momentum, state.m_index))
+        })
+    }
 }
 
 // #[derive(Debug, Clone, Copy, Deserialize, Serialize)]
@@ -159,97 +256,187 @@ impl Selection {
 //     }
 // }
 impl ProjectorConfig {
-    /// Project PDOS weights for a single projector configuration
+    /// Project PDOS weights for a single projector configuration.
+    /// Every species and atom of the selections must exist in
+    /// `pdos_weights.orbital_states`.
     pub fn project_pdos_from_config(
         &self,
         species_mapping: &HashMap<&str, u32>,
         pdos_weights: &PDOSWeights,
-    ) -> SpinData<KpointVec<EigenvalueVec<AngularChannels>>> {
+    ) -> Result<SpinData<KpointVec<EigenvalueVec<AngularChannels>>>, ProjectionError> {
         // obtain selected orbital ids
         let orbital_states = &pdos_weights.orbital_states;
 
-        let selected_orbital_ids = self
-            .selections
-            .as_ref()
-            .map(|selections| extract_selections(selections, species_mapping, orbital_states))
-            .unwrap_or((0..pdos_weights.orbital_states.len()).collect());
-        let angular_indices = [
-            AngularMomentum::S,
-            AngularMomentum::P,
-            AngularMomentum::D,
-            AngularMomentum::F,
-        ]
-        .map(|am| {
-            selected_orbital_ids
+        let selected_orbital_ids = match self.selections.as_ref() {
+            Some(selections) => extract_selections(
+                self.display_name(),
+                selections,
+                species_mapping,
+                orbital_states,
+            )?,
+            None => (0..pdos_weights.orbital_states.len()).collect(),
+        };
+        // Orbital to channel selection matrix
+        let mut selection = Array2::zeros((orbital_states.len(), 4));
+        selected_orbital_ids.iter().for_each(|&idx| {
+            let channel = match orbital_states[idx].angular_momentum {
+                AngularMomentum::S => 0,
+                AngularMomentum::P => 1,
+                AngularMomentum::D => 2,
+                AngularMomentum::F => 3,
+            };
+            selection[[idx, channel]] += 1.0;
+        });
+        Ok(pdos_weights.orbital_weights.project(&selection))
+    }
+
+    /// One projector for each sub-orbital found in the selections, named
+    /// `<name>_<sub-orbital>`, in the order of `.pdos_weights`. Their s, p, d
+    /// and f columns hold the single sub-orbital of that l.
+    pub fn m_resolved_projectors(
+        &self,
+        species_mapping: &HashMap<&str, u32>,
+        pdos_weights: &PDOSWeights,
+    ) -> Result<Vec<ProjectorConfig>, ProjectionError> {
+        let orbital_states = &pdos_weights.orbital_states;
+        let selected_orbital_ids = match self.selections.as_ref() {
+            Some(selections) => extract_selections(
+                self.display_name(),
+                selections,
+                species_mapping,
+                orbital_states,
+            )?,
+            None => (0..orbital_states.len()).collect(),
+        };
+        let mut labels: Vec<OrbitalLabel> = Vec::new();
+        selected_orbital_ids
+            .iter()
+            .filter_map(|&idx| orbital_states[idx].orbital_label())
+            .for_each(|label| {
+                if !labels.contains(&label) {
+                    labels.push(label);
+                }
+            });
+        let selections = self.selections.clone().unwrap_or_else(|| {
+            // Every species, to attach the sub-orbital to
+            let mut species = species_mapping
                 .iter()
-                .filter(|&&idx| orbital_states[idx].angular_momentum == am)
-                .copied()
-                .collect::<Vec<usize>>()
+                .map(|(symbol, rank)| (*rank, symbol.to_string()))
+                .collect::<Vec<(u32, String)>>();
+            species.sort();
+            species
+                .into_iter()
+                .map(|(_, symbol)| Selection {
+                    species: SpeciesSymbol(symbol),
+                    atoms: None,
+                    orbitals: None,
+                })
+                .collect()
         });
-        pdos_weights
-            .orbital_weights
-            .map_on_data_of_eigenvalue(|weights| AngularChannels {
-                s: sum_weights_per_eigenvalue(&angular_indices[0], weights),
-                p: sum_weights_per_eigenvalue(&angular_indices[1], weights),
-                d: sum_weights_per_eigenvalue(&angular_indices[2], weights),
-                f: sum_weights_per_eigenvalue(&angular_indices[3], weights),
+        Ok(labels
+            .into_iter()
+            .map(|label| ProjectorConfig {
+                name: Some(format!("{}_{label}", self.display_name())),
+                label: self.label.clone(),
+                selections: Some(
+                    selections
+                        .iter()
+                        .map(|sel| Selection {
+                            orbitals: Some(vec![label]),
+                            ..sel.clone()
+                        })
+                        .collect(),
+                ),
+                m_resolved: false,
             })
+            .collect())
+    }
+
+    /// Name used in error messages
+    fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or("unnamed")
     }
 }
 
 /// From selections, compare the species symbol and ion index,
 /// obtain the usize index in each orbital weight array
 fn extract_selections(
+    projector: &str,
     selections: &[Selection],
     species_mapping: &HashMap<&str, u32>,
     orbital_states: &[OrbitalState],
-) -> Vec<usize> {
-    // For every selection
-    selections.iter().flat_map(|sel| {
-        let species_id = species_mapping.get(sel.species().as_str()).expect("The species symbol in `selection` does not match with any record in `mapping. Please double check the config toml file.`");
-        let atoms = sel.atoms();
-        match atoms {
-            // If we have selected atoms
-            Some(atoms) =>{
-                atoms.iter().flat_map(|atom_id| {
-                    orbital_states.par_iter().enumerate().filter_map(|(i,state)| {
-                        if state.species_id == *species_id && state.ion_id == *atom_id {
-                            Some(i)
-                        }else{
-                            None
+) -> Result<Vec<usize>, ProjectionError> {
+    let orbitals_of = |species_id: u32, atom_id: Option<u32>| -> Vec<usize> {
+        orbital_states
+            .par_iter()
+            .enumerate()
+            .filter_map(|(i, state)| {
+                let selected = state.species_id == species_id
+                    && atom_id.is_none_or(|atom_id| state.ion_id == atom_id);
+                selected.then_some(i)
+            })
+            .collect()
+    };
+    // Orbitals of the atom, of the sub-orbitals asked by the selection
+    let take_orbitals = |sel: &Selection, ids: Vec<usize>| -> Vec<usize> {
+        ids.into_iter()
+            .filter(|&idx| sel.takes_orbital(&orbital_states[idx]))
+            .collect()
+    };
+    selections
+        .iter()
+        .enumerate()
+        .map(|(i, sel)| {
+            let species_id = species_mapping
+                .get(sel.species().as_str())
+                .copied()
+                .ok_or_else(|| ProjectionError::UnknownSpecies {
+                    projector: projector.to_string(),
+                    selection: i + 1,
+                    species: sel.species().to_string(),
+                })?;
+            match sel.atoms() {
+                // If we have selected atoms
+                Some(atoms) => atoms
+                    .iter()
+                    .map(|&atom_id| {
+                        let ids = orbitals_of(species_id, Some(atom_id));
+                        if ids.is_empty() {
+                            Err(ProjectionError::UnknownAtom {
+                                projector: projector.to_string(),
+                                selection: i + 1,
+                                species: sel.species().to_string(),
+                                atom: atom_id,
+                            })
+                        } else {
+                            Ok(ids)
                         }
-                    }).collect::<Vec<usize>>()
-                }).collect::<Vec<usize>>()
-            }
-            None => {
-                orbital_states.par_iter().enumerate().filter_map(|(i, state)| {
-                    if state.species_id == *species_id {
-                        Some(i)
+                    })
+                    .collect::<Result<Vec<Vec<usize>>, ProjectionError>>()
+                    .map(|ids| take_orbitals(sel, ids.concat())),
+                None => {
+                    let ids = orbitals_of(species_id, None);
+                    if ids.is_empty() {
+                        Err(ProjectionError::SpeciesWithoutOrbitals {
+                            projector: projector.to_string(),
+                            selection: i + 1,
+                            species: sel.species().to_string(),
+                        })
                     } else {
-                        None
+                        Ok(take_orbitals(sel, ids))
                     }
-                }).collect()
+                }
             }
-        }
-    }).collect()
-}
-
-/// Gather weights at each eigenvalue's orbital weight array
-fn sum_weights_per_eigenvalue(indices: &[usize], weights: &OrbitalWeightVec) -> f64 {
-    indices
-        .par_iter()
-        .map(|&idx| weights[idx].value())
-        // .filter(|&w| w > 0.0)
-        .sum()
+        })
+        .collect::<Result<Vec<Vec<usize>>, ProjectionError>>()
+        .map(|ids| ids.concat())
 }
 
 #[cfg(test)]
 mod test {
-    use std::fs::read;
-
-    use crate::pdos_weights_parser::parse_pdos_weight_file;
+    use crate::{fundamental::SpinIndex, test_fixtures::sample_pdos_weights_file};
 
-    use super::PDOSConfig;
+    use super::{Mapping, PDOSConfig, ProjectionError};
 
     const CONFIG: &str = r#"
 mapping=[{species="Mo", rank=2}, {species="S", rank=1}]
@@ -260,18 +447,157 @@ atoms = [1,]
 [[projector.selections]]
 species = "S"
 atoms = [1,]
+"#;
+    const NO_MAPPING_CONFIG: &str = r#"
+[[projector]]
+[[projector.selections]]
+species = "Mo"
 "#;
+    #[test]
+    fn test_mapping_fallback() {
+        let fallback = vec![Mapping::new("S", 1), Mapping::new("Mo", 2)];
+        let config = toml::from_str::<PDOSConfig>(CONFIG).unwrap();
+        let ignored = [Mapping::new("Mo", 5)];
+        let mapping = config.species_mapping(Some(&ignored)).unwrap();
+        assert_eq!(mapping.get("Mo"), Some(&2));
+        let config = toml::from_str::<PDOSConfig>(NO_MAPPING_CONFIG).unwrap();
+        assert!(config.species_mapping(None).is_none());
+        let mapping = config.species_mapping(Some(&fallback)).unwrap();
+        assert_eq!(mapping.get("Mo"), Some(&2));
+        assert_eq!(mapping.get("S"), Some(&1));
+        assert!(!toml::to_string(&config).unwrap().contains("mapping"));
+    }
     #[test]
     fn test_config() {
-        let pdos_file =
-            read("/home/tony/Downloads/cosxmos2_DOS/cosxmos2_DOS.pdos_weights").unwrap();
-        let pdos_weights = parse_pdos_weight_file(&mut &pdos_file[..]).unwrap();
+        let pdos_weights = sample_pdos_weights_file(1).to_pdos_weights();
         let config = toml::from_str::<PDOSConfig>(CONFIG).unwrap();
-        dbg!(&config);
-        let species_mapping = config.species_mapping();
-        println!("{}", toml::to_string(&config).unwrap());
-        config.projectors.iter().for_each(|projector| {
-            dbg!(projector.project_pdos_from_config(&species_mapping, &pdos_weights));
-        });
+        let species_mapping = config.species_mapping(None).unwrap();
+        let projected = config.projectors[0]
+            .project_pdos_from_config(&species_mapping, &pdos_weights)
+            .unwrap();
+        // Mo 1 has only an s orbital, S 1 has s and p
+        let channels = projected.get(SpinIndex::One).unwrap()[0][0];
+        let weights = pdos_weights.orbital_weights.kpoint(0, 0).row(0).to_vec();
+        let expected_s = weights[0] + weights[4];
+        assert!((channels.s - expected_s).abs() < 1e-12);
+        assert!(channels.p > 0.0);
+        assert_eq!(channels.d, 0.0);
+    }
+
+    #[test]
+    fn test_m_resolved() {
+        let pdos_weights = sample_pdos_weights_file(1).to_pdos_weights();
+        let m_indices = pdos_weights
+            .orbital_states
+            .iter()
+            .map(|state| state.m_index)
+            .collect::<Vec<u32>>();
+        assert_eq!(m_indices, vec![0, 0, 1, 2, 0, 0]);
+        let config = toml::from_str::<PDOSConfig>(
+            r#"
+mapping=[{species="Mo", rank=2}, {species="S", rank=1}]
+[[projector]]
+name = "S"
+m_resolved = true
+[[projector.selections]]
+species = "S"
+[[projector]]
+name = "S px py"
+[[projector.selections]]
+species = "S"
+orbitals = ["px", "py"]
+"#,
+        )
+        .unwrap();
+        let species_mapping = config.species_mapping(None).unwrap();
+        let split = config.projectors[0]
+            .m_resolved_projectors(&species_mapping, &pdos_weights)
+            .unwrap();
+        let names = split
+            .iter()
+            .map(|projector| projector.name.clone().unwrap())
+            .collect::<Vec<String>>();
+        assert_eq!(names, vec!["S_s", "S_px", "S_py", "S_pz"]);
+        let weights = pdos_weights.orbital_weights.kpoint(0, 0).row(0).to_vec();
+        let project = |projector: &super::ProjectorConfig| {
+            projector
+                .project_pdos_from_config(&species_mapping, &pdos_weights)
+                .unwrap()
+                .get(SpinIndex::One)
+                .unwrap()[0][0]
+        };
+        let pz = project(&split[3]);
+        assert_eq!((pz.s, pz.p), (0.0, weights[3]));
+        let px_py = project(&config.projectors[1]);
+        assert!((px_py.p - weights[1] - weights[2]).abs() < 1e-12);
+        assert_eq!(px_py.s, 0.0);
+    }
+
+    #[test]
+    fn test_projection_error() {
+        let pdos_weights = sample_pdos_weights_file(1).to_pdos_weights();
+        let config = toml::from_str::<PDOSConfig>(
+            r#"
+mapping=[{species="Mo", rank=2}, {species="S", rank=1}]
+[[projector]]
+name = "bad atom"
+[[projector.selections]]
+species = "Mo"
+[[projector.selections]]
+species = "S"
+atoms = [1, 2]
+[[projector]]
+[[projector.selections]]
+species = "W"
+"#,
+        )
+        .unwrap();
+        let species_mapping = config.species_mapping(None).unwrap();
+        let errors = config
+            .projectors
+            .iter()
+            .map(|projector| {
+                projector
+                    .project_pdos_from_config(&species_mapping, &pdos_weights)
+                    .unwrap_err()
+            })
+            .collect::<Vec<ProjectionError>>();
+        assert_eq!(
+            errors[0],
+            ProjectionError::UnknownAtom {
+                projector: "bad atom".to_string(),
+                selection: 2,
+                species: "S".to_string(),
+                atom: 2
+            }
+        );
+        assert_eq!(
+            errors[1].to_string(),
+            "projector `unnamed`, selection 1: species `W` is not in `mapping`"
+        );
+        // A rank beyond the species in `.pdos_weights`, e.g. a wrong mapping
+        let config = toml::from_str::<PDOSConfig>(
+            r#"
+mapping=[{species="Mo", rank=3}, {species="S", rank=1}]
+[[projector]]
+name = "Mo"
+[[projector.selections]]
+species = "S"
+[[projector.selections]]
+species = "Mo"
+"#,
+        )
+        .unwrap();
+        let species_mapping = config.species_mapping(None).unwrap();
+        assert_eq!(
+            config.projectors[0]
+                .project_pdos_from_config(&species_mapping, &pdos_weights)
+                .unwrap_err(),
+            ProjectionError::SpeciesWithoutOrbitals {
+                projector: "Mo".to_string(),
+                selection: 2,
+                species: "Mo".to_string(),
+            }
+        );
     }
 }
@@ -1,3 +1,3 @@
 mod config;
 
-pub use config::{PDOSConfig, ProjectorConfig, Selection, SpeciesSymbol};
+pub use config::{Mapping, PDOSConfig, ProjectionError, ProjectorConfig, Selection, SpeciesSymbol};
@@ -0,0 +1,296 @@
+use std::fmt::Display;
+
+use crate::{
+    fundamental::{
+        AngularChannels, BandStructure, EigenvalueVec, KpointVec, PDOSWeights, SpinData,
+        SpinLayoutError,
+    },
+    pdos_compute::PDOSResult,
+};
+
+/// Weight of one in the s column of every state. Any PDOS method run on
+/// these weights gives the total DOS from the eigenvalues alone, in the
+/// `s` channel of the result.
+pub fn total_dos_weights(
+    band_structure: &BandStructure,
+) -> SpinData<KpointVec<EigenvalueVec<AngularChannels>>> {
+    band_structure.eigenvalues.map(|kpts| {
+        kpts.iter()
+            .map(|eigens| {
+                EigenvalueVec(vec![AngularChannels::new(1.0, 0.0, 0.0, 0.0); eigens.len()])
+            })
+            .collect()
+    })
+}
+
+/// Total DOS from the eigenvalues next to the DOS projected on all orbitals.
+/// The difference is the spilling: states the atomic projectors miss.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TotalDOS {
+    /// DOS of the eigenvalues
+    pub eigenvalues: Vec<f64>,
+    /// Sum of the channels of the PDOS on all orbitals
+    pub projected: Vec<f64>,
+}
+
+impl TotalDOS {
+    /// `total` is computed on `total_dos_weights`, `projected` on all
+    /// orbitals, over the same energy grid
+    pub fn new(total: &PDOSResult, projected: &PDOSResult) -> Self {
+        Self {
+            eigenvalues: total.s.clone(),
+            projected: (0..projected.s.len())
+                .map(|i| projected.s[i] + projected.p[i] + projected.d[i] + projected.f[i])
+                .collect(),
+        }
+    }
+
+    /// Write as csv, the DOS followed by the integrated DOS
+    pub fn csv_output(&self, energy_grid: &[f64]) -> String {
+        let header = "E,DOS_total,DOS_projected,IDOS_total,IDOS_projected";
+        let cumulative = |dos: &[f64]| -> Vec<f64> {
+            PDOSResult {
+                s: dos.to_vec(),
+                p: vec![0.0; dos.len()],
+                d: vec![0.0; dos.len()],
+                f: vec![0.0; dos.len()],
+            }
+            .integrated(energy_grid)
+            .s
+        };
+        let (idos_total, idos_projected) =
+            (cumulative(&self.eigenvalues), cumulative(&self.projected));
+        let contents = energy_grid
+            .iter()
+            .enumerate()
+            .map(|(i, e)| {
+                format!(
+                    "{:.16},{:.16},{:.16},{:.16},{:.16}",
+                    e, self.eigenvalues[i], self.projected[i], idos_total[i], idos_projected[i]
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        [header.to_string(), contents].join("\n")
+    }
+}
+
+/// Charge spilling 1 - Σ orbital weights of the states of one spin
+/// (Sánchez-Portal, Artacho and Soler, 1995)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spilling {
+    /// Spilling of each band, averaged over the k-points with their weights
+    pub bands: Vec<f64>,
+    /// Average over all states
+    pub total: f64,
+    /// Average over the states below the Fermi energy, `None` without them
+    pub occupied: Option<f64>,
+}
+
+impl Spilling {
+    /// Spilling of each spin. `band_structure` provides the k-point weights
+    /// and the eigenvalues, its k-points must be aligned with
+    /// `pdos_weights`, see `align_kpoints`. The states at or below
+    /// `fermi_energy` (Hartree) of their spin are occupied; it is passed
+    /// apart as `band_structure.fermi_energy` may hold an energy reference.
+    /// Fails when the three are not in the same spin layout.
+    pub fn from_weights(
+        pdos_weights: &PDOSWeights,
+        band_structure: &BandStructure,
+        fermi_energy: &SpinData<f64>,
+    ) -> Result<SpinData<Spilling>, SpinLayoutError> {
+        let kpoint_weights = &band_structure.kpoint_weights;
+        let spilling = pdos_weights
+            .orbital_weights
+            .map_on_data_of_eigenvalue(|weights| 1.0 - weights.sum());
+        let channels = spilling
+            .zip(&band_structure.eigenvalues)
+            .ok_or(SpinLayoutError("orbital weights", "eigenvalues"))?;
+        let channels = channels
+            .zip(fermi_energy)
+            .ok_or(SpinLayoutError("eigenvalues", "fermi_energy"))?;
+        let spin_spilling = |spilling: &KpointVec<EigenvalueVec<f64>>,
+                             eigenvalues: &KpointVec<EigenvalueVec<f64>>,
+                             fermi: f64| {
+            // (k-point weight, band, eigenvalue, spilling) of every state
+            let states =
+                spilling
+                    .iter()
+                    .zip(eigenvalues.iter())
+                    .zip(kpoint_weights.iter())
+                    .flat_map(|((spilling_k, eigens), kw)| {
+                        spilling_k.iter().zip(eigens.iter()).enumerate().map(
+                            move |(band, (spilling, eigen))| (kw.value(), band, *eigen, *spilling),
+                        )
+                    })
+                    .collect::<Vec<(f64, usize, f64, f64)>>();
+            let average = |states: &mut dyn Iterator<Item = &(f64, usize, f64, f64)>| {
+                let (weighted, total_weight) = states
+                    .fold((0.0, 0.0), |(sum, total), (kw, _, _, spilling)| {
+                        (sum + kw * spilling, total + kw)
+                    });
+                (total_weight > 0.0).then(|| weighted / total_weight)
+            };
+            let nbands = states.iter().map(|(_, band, _, _)| band + 1).max();
+            let bands = (0..nbands.unwrap_or(0))
+                .map(|b| {
+                    average(&mut states.iter().filter(|(_, band, _, _)| *band == b)).unwrap_or(0.0)
+                })
+                .collect::<Vec<f64>>();
+            Spilling {
+                bands,
+                total: average(&mut states.iter()).unwrap_or(0.0),
+                occupied: average(&mut states.iter().filter(|(_, _, e, _)| *e <= fermi)),
+            }
+        };
+        Ok(match channels {
+            SpinData::NonPolarized(((spilling, eigenvalues), fermi)) => {
+                SpinData::NonPolarized(spin_spilling(spilling, eigenvalues, *fermi))
+            }
+            SpinData::SpinPolarized(spins) => {
+                SpinData::SpinPolarized(spins.map(|((spilling, eigenvalues), fermi)| {
+                    spin_spilling(spilling, eigenvalues, *fermi)
+                }))
+            }
+        })
+    }
+}
+
+/// Write the spilling of each band as csv, a column for each spin
+pub fn spilling_csv(spilling: &SpinData<Spilling>) -> String {
+    let (header, columns) = match spilling {
+        SpinData::NonPolarized(spilling) => ("band,spilling", vec![&spilling.bands]),
+        SpinData::SpinPolarized([up, down]) => (
+            "band,spilling_up,spilling_down",
+            vec![&up.bands, &down.bands],
+        ),
+    };
+    let nbands = columns.iter().map(|bands| bands.len()).max().unwrap_or(0);
+    let contents = (0..nbands)
+        .map(|b| {
+            let values = columns
+                .iter()
+                .map(|bands| bands.get(b).map(|s| format!("{s:.16}")).unwrap_or_default())
+                .collect::<Vec<String>>()
+                .join(",");
+            format!("{},{values}", b + 1)
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    [header.to_string(), contents].join("\n")
+}
+
+impl Display for Spilling {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "all states {:.2}%", self.total * 100.0)?;
+        if let Some(occupied) = self.occupied {
+            write!(f, ", occupied states {:.2}%", occupied * 100.0)?;
+        }
+        if let Some((band, worst)) = self
+            .bands
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        {
+            write!(f, ", worst band {} at {:.2}%", band + 1, worst * 100.0)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ndarray::s;
+
+    use crate::{
+        fundamental::{SpinData, SpinLayoutError},
+        pdos_compute::{BroadeningKernel, EnergyReference, FermiLevelSolver, calculate_pdos},
+        test_fixtures::{sample_bands_file, sample_grid_band_structure, sample_pdos_weights_file},
+    };
+
+    use super::{Spilling, spilling_csv, total_dos_weights};
+
+    #[test]
+    fn test_total_dos() {
+        let band_structure = sample_grid_band_structure(8, false);
+        // The bands span -0.2 to 0.5 Ha
+        let energy_grid = (0..=4000)
+            .map(|i| -20.0 + 0.01 * i as f64)
+            .collect::<Vec<f64>>();
+        let SpinData::NonPolarized(total) = calculate_pdos(
+            &band_structure,
+            &total_dos_weights(&band_structure),
+            &energy_grid,
+            &BroadeningKernel::Gaussian,
+            0.05,
+        )
+        .unwrap() else {
+            panic!("non polarized expected");
+        };
+        // Two bands of two electrons
+        let states = total.states_below(&energy_grid, 20.0);
+        assert!((states - 4.0).abs() < 1e-6, "{states}");
+        assert!(total.p.iter().all(|dos| *dos == 0.0));
+    }
+
+    #[test]
+    fn test_spilling() {
+        // Each band of the fixture holds 0.9 of the weight
+        [1, 2].into_iter().for_each(|num_spins| {
+            let pdos_weights = sample_pdos_weights_file(num_spins).to_pdos_weights();
+            let band_structure = sample_bands_file(num_spins).to_band_structure();
+            let spilling = Spilling::from_weights(
+                &pdos_weights,
+                &band_structure,
+                &band_structure.fermi_energy,
+            )
+            .unwrap();
+            spilling.for_each(|spilling| {
+                assert_eq!(spilling.bands.len(), 4);
+                assert!(spilling.bands.iter().all(|s| (s - 0.1).abs() < 1e-12));
+                assert!((spilling.total - 0.1).abs() < 1e-12);
+                assert!((spilling.occupied.unwrap() - 0.1).abs() < 1e-12);
+            });
+            let csv = spilling_csv(&spilling);
+            assert_eq!(csv.lines().count(), 5);
+            assert_eq!(
+                csv.lines().next().unwrap().split(',').count(),
+                num_spins as usize + 1
+            );
+        });
+    }
+
+    #[test]
+    fn test_spilling_with_energy_reference() {
+        // Bands 1 and 2 are below the Fermi energy at 0 Ha, the projectors
+        // miss bands 3 and 4 entirely
+        let mut pdos_weights = sample_pdos_weights_file(2).to_pdos_weights();
+        (0..2).for_each(|spin| {
+            (0..pdos_weights.orbital_weights.num_kpoints()).for_each(|k| {
+                pdos_weights
+                    .orbital_weights
+                    .kpoint_mut(spin, k, 4)
+                    .slice_mut(s![2.., ..])
+                    .fill(0.0)
+            })
+        });
+        let mut band_structure = sample_bands_file(2).to_band_structure();
+        let fermi_energy = band_structure.fermi_energy.clone();
+        // The PDOS is computed relative to a reference below all bands
+        band_structure.fermi_energy = EnergyReference::Value { energy: -20.0 }
+            .resolve(&band_structure, &FermiLevelSolver::default())
+            .unwrap();
+        Spilling::from_weights(&pdos_weights, &band_structure, &fermi_energy)
+            .unwrap()
+            .for_each(|spilling| {
+                assert!((spilling.occupied.unwrap() - 0.1).abs() < 1e-12);
+                assert!((spilling.total - 0.55).abs() < 1e-12);
+            });
+        // Spin polarized weights on non-polarized bands
+        let band_structure = sample_bands_file(1).to_band_structure();
+        assert_eq!(
+            Spilling::from_weights(&pdos_weights, &band_structure, &band_structure.fermi_energy),
+            Err(SpinLayoutError("orbital weights", "eigenvalues"))
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use std::fmt::Display;
+
+use crate::{
+    fundamental::{AngularChannels, SpinData},
+    moments::pdos_moments,
+    pdos_compute::PDOSResult,
+};
+
+/// Spin polarization below this total DOS (states/eV) is set to zero,
+/// where (up - down) / (up + down) is noise
+const POLARIZATION_DOS_THRESHOLD: f64 = 1e-6;
+
+/// Spin polarization P(E) = (up - down) / (up + down) of each channel
+/// and of their sum, on the energy grid
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpinPolarization {
+    /// Channel s
+    pub s: Vec<f64>,
+    /// Channel p
+    pub p: Vec<f64>,
+    /// Channel d
+    pub d: Vec<f64>,
+    /// Channel f
+    pub f: Vec<f64>,
+    /// Sum of the channels
+    pub total: Vec<f64>,
+}
+
+impl SpinPolarization {
+    /// Polarization of spin polarized PDOS, `None` without spin polarization
+    pub fn from_pdos(pdos: &SpinData<PDOSResult>) -> Option<Self> {
+        let SpinData::SpinPolarized([up, down]) = pdos else {
+            return None;
+        };
+        let polarization = |up: &[f64], down: &[f64]| -> Vec<f64> {
+            up.iter()
+                .zip(down.iter())
+                .map(|(u, d)| ratio(*u, *d))
+                .collect()
+        };
+        let total = (0..up.s.len())
+            .map(|i| {
+                ratio(
+                    up.s[i] + up.p[i] + up.d[i] + up.f[i],
+                    down.s[i] + down.p[i] + down.d[i] + down.f[i],
+                )
+            })
+            .collect();
+        Some(Self {
+            s: polarization(&up.s, &down.s),
+            p: polarization(&up.p, &down.p),
+            d: polarization(&up.d, &down.d),
+            f: polarization(&up.f, &down.f),
+            total,
+        })
+    }
+
+    /// Write as csv
+    pub fn csv_output(&self, energy_grid: &[f64]) -> String {
+        let header = "E,P_s,P_p,P_d,P_f,P_total";
+        let contents = energy_grid
+            .iter()
+            .enumerate()
+            .map(|(i, e)| {
+                format!(
+                    "{:.16},{:.16},{:.16},{:.16},{:.16},{:.16}",
+                    e, self.s[i], self.p[i], self.d[i], self.f[i], self.total[i]
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        [header.to_string(), contents].join("\n")
+    }
+}
+
+fn ratio(up: f64, down: f64) -> f64 {
+    let total = up + down;
+    if total.abs() < POLARIZATION_DOS_THRESHOLD {
+        0.0
+    } else {
+        (up - down) / total
+    }
+}
+
+/// Occupied states of each spin, the magnetic moment and the exchange
+/// splitting of a projector
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpinMoments {
+    /// Occupied states of each channel, spin up
+    pub occupied_up: AngularChannels,
+    /// Occupied states of each channel, spin down
+    pub occupied_down: AngularChannels,
+    /// d-band centre of spin down minus that of spin up in eV, `None`
+    /// without d states in either spin
+    pub exchange_splitting: Option<f64>,
+}
+
+impl SpinMoments {
+    /// Integrate spin polarized PDOS up to the Fermi energy of each spin,
+    /// `None` without spin polarization.
+    /// `fermi_energy` is where the Fermi energy of each spin sits on
+    /// `energy_grid`, and `zero` is the absolute energy of the zero of
+    /// `energy_grid` of each spin, both in eV, so the d-band centres of
+    /// the two spins are compared on the same scale. The centres are taken
+    /// within `window` (min, max) of the energy grid.
+    pub fn from_pdos(
+        pdos: &SpinData<PDOSResult>,
+        energy_grid: &[f64],
+        fermi_energy: &SpinData<f64>,
+        zero: &SpinData<f64>,
+        window: (f64, f64),
+    ) -> Option<Self> {
+        let (
+            SpinData::SpinPolarized([up, down]),
+            SpinData::SpinPolarized([fermi_up, fermi_down]),
+            SpinData::SpinPolarized([zero_up, zero_down]),
+        ) = (pdos, fermi_energy, zero)
+        else {
+            return None;
+        };
+        let centre_up = pdos_moments(up, energy_grid, window).d;
+        let centre_down = pdos_moments(down, energy_grid, window).d;
+        let exchange_splitting = centre_up
+            .zip(centre_down)
+            .map(|(up, down)| (down.centre + zero_down) - (up.centre + zero_up));
+        Some(Self {
+            occupied_up: up.channel_states_below(energy_grid, *fermi_up),
+            occupied_down: down.channel_states_below(energy_grid, *fermi_down),
+            exchange_splitting,
+        })
+    }
+
+    /// Magnetic moment of each channel in Bohr magneton
+    pub fn channel_moments(&self) -> AngularChannels {
+        let (up, down) = (self.occupied_up, self.occupied_down);
+        AngularChannels::new(up.s - down.s, up.p - down.p, up.d - down.d, up.f - down.f)
+    }
+
+    /// Magnetic moment in Bohr magneton
+    pub fn moment(&self) -> f64 {
+        let moments = self.channel_moments();
+        moments.s + moments.p + moments.d + moments.f
+    }
+}
+
+impl Display for SpinMoments {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{:<8}{:>12}{:>12}{:>12}",
+            "channel", "up", "down", "moment"
+        )?;
+        let moments = self.channel_moments();
+        [
+            ("s", self.occupied_up.s, self.occupied_down.s, moments.s),
+            ("p", self.occupied_up.p, self.occupied_down.p, moments.p),
+            ("d", self.occupied_up.d, self.occupied_down.d, moments.d),
+            ("f", self.occupied_up.f, self.occupied_down.f, moments.f),
+        ]
+        .iter()
+        .try_for_each(|(name, up, down, moment)| {
+            writeln!(f, "{:<8}{:>12.4}{:>12.4}{:>12.4}", name, up, down, moment)
+        })?;
+        writeln!(f, "Magnetic moment: {:.4} μB", self.moment())?;
+        match self.exchange_splitting {
+            Some(splitting) => {
+                writeln!(f, "Exchange splitting of d-band centres: {splitting:.4} eV")
+            }
+            None => writeln!(f, "Exchange splitting of d-band centres: -"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{fundamental::SpinData, pdos_compute::PDOSResult};
+
+    use super::{SpinMoments, SpinPolarization};
+
+    /// A Gaussian peak of each spin in the d channel at `centre_up` and
+    /// `centre_down`, holding `weight_up` and `weight_down` states
+    fn gaussian_pdos(
+        energy_grid: &[f64],
+        (centre_up, weight_up): (f64, f64),
+        (centre_down, weight_down): (f64, f64),
+    ) -> SpinData<PDOSResult> {
+        let sigma: f64 = 0.2;
+        let peak = |centre: f64, weight: f64| -> Vec<f64> {
+            energy_grid
+                .iter()
+                .map(|e| {
+                    weight * (-0.5 * ((e - centre) / sigma).powi(2)).exp()
+                        / (sigma * (2.0 * std::f64::consts::PI).sqrt())
+                })
+                .collect()
+        };
+        let zeros = vec![0.0; energy_grid.len()];
+        let result = |d: Vec<f64>| PDOSResult {
+            s: zeros.clone(),
+            p: zeros.clone(),
+            d,
+            f: zeros.clone(),
+        };
+        SpinData::SpinPolarized([
+            result(peak(centre_up, weight_up)),
+            result(peak(centre_down, weight_down)),
+        ])
+    }
+
+    #[test]
+    fn test_spin_analysis() {
+        let energy_grid = (0..=2000)
+            .map(|i| -10.0 + 0.01 * i as f64)
+            .collect::<Vec<f64>>();
+        // Majority d band well below the Fermi energy, minority across it
+        let pdos = gaussian_pdos(&energy_grid, (-3.0, 5.0), (0.0, 5.0));
+        let polarization = SpinPolarization::from_pdos(&pdos).unwrap();
+        let at = |energy: f64| energy_grid.partition_point(|&e| e < energy - 1e-9);
+        assert!(polarization.d[at(-3.0)] > 0.99);
+        assert!(polarization.total[at(0.0)] < -0.99);
+        assert!((polarization.d[at(-1.5)]).abs() < 1e-9);
+        assert_eq!(polarization.s[at(-3.0)], 0.0);
+
+        let zero = SpinData::SpinPolarized([0.0, 0.0]);
+        let fermi_energy = SpinData::SpinPolarized([0.0, 0.0]);
+        let window = (f64::NEG_INFINITY, f64::INFINITY);
+        let moments =
+            SpinMoments::from_pdos(&pdos, &energy_grid, &fermi_energy, &zero, window).unwrap();
+        assert!((moments.occupied_up.d - 5.0).abs() < 1e-6);
+        assert!((moments.occupied_down.d - 2.5).abs() < 1e-6);
+        assert!((moments.moment() - 2.5).abs() < 1e-6);
+        assert!((moments.exchange_splitting.unwrap() - 3.0).abs() < 1e-6);
+
+        // Spin down on an axis 1 eV lower: same bands, shifted grid
+        let shifted = gaussian_pdos(&energy_grid, (-3.0, 5.0), (1.0, 5.0));
+        let moments = SpinMoments::from_pdos(
+            &shifted,
+            &energy_grid,
+            &SpinData::SpinPolarized([0.0, 1.0]),
+            &SpinData::SpinPolarized([0.0, -1.0]),
+            window,
+        )
+        .unwrap();
+        assert!((moments.moment() - 2.5).abs() < 1e-6);
+        assert!((moments.exchange_splitting.unwrap() - 3.0).abs() < 1e-6);
+
+        let non_polarized = SpinData::NonPolarized(PDOSResult {
+            s: vec![],
+            p: vec![],
+            d: vec![],
+            f: vec![],
+        });
+        assert!(SpinPolarization::from_pdos(&non_polarized).is_none());
+    }
+}
@@ -0,0 +1,228 @@
+use std::fmt::Display;
+
+use crate::{
+    bands::{BandsFile, Eigenvalues, FermiEnergy},
+    fundamental::{
+        AngularMomentum, BOHR_TO_ANGSTROM, HATREE_TO_EV, PDOSWeightsFile, SpinPolarized,
+    },
+    projectors::Mapping,
+};
+
+/// Projected orbitals of one ion, grouped from `Header::extract_orbital_states`
+#[derive(Debug, Clone, PartialEq)]
+pub struct IonOrbitals {
+    /// Species rank
+    pub species_id: u32,
+    /// Ion index within the species, starts from 1
+    pub ion_id: u32,
+    /// Angular momentum and number of orbital entries of it,
+    /// in the order of `.pdos_weights`
+    pub orbitals: Vec<(AngularMomentum, usize)>,
+}
+
+/// What the parsed seed files contain, for `castep_dos info`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeedSummary {
+    /// Spin polarization of `.bands`
+    pub spin_polarized: SpinPolarized,
+    /// Number of k-points
+    pub kpoint_count: usize,
+    /// Number of bands of each spin
+    pub bands_per_spin: Vec<usize>,
+    /// Fermi energy of each spin in eV
+    pub fermi_energies: Vec<f64>,
+    /// Lattice vectors in Angstroms (row-major)
+    pub lattice_vectors: [[f64; 3]; 3],
+    /// Species symbols and ranks, if known
+    pub species: Vec<(String, u32)>,
+    /// Projected orbitals of each ion
+    pub ions: Vec<IonOrbitals>,
+}
+
+impl SeedSummary {
+    /// Gather the summary. `species_mapping` comes from the config or the
+    /// `.cell`/`.castep` of the seed, it is only used to label the species.
+    pub fn new(
+        bands_file: &BandsFile,
+        pdos_weights_file: &PDOSWeightsFile,
+        species_mapping: Option<&[Mapping]>,
+    ) -> Self {
+        let bands_per_spin = match &bands_file.eigenvalues {
+            Eigenvalues::NonPolarized(kpts) => vec![max_bands(kpts)],
+            Eigenvalues::SpinPolarized([up, down]) => vec![max_bands(up), max_bands(down)],
+        };
+        let fermi_energies = match bands_file.fermi_energy {
+            FermiEnergy::NonPolarized(fermi) => vec![fermi * HATREE_TO_EV],
+            FermiEnergy::Polarized(up, down) => vec![up * HATREE_TO_EV, down * HATREE_TO_EV],
+        };
+        let lattice_vectors = bands_file
+            .lattice_vectors
+            .map(|vector| vector.map(|x| x * BOHR_TO_ANGSTROM));
+        let mut species = species_mapping
+            .map(|mappings| {
+                mappings
+                    .iter()
+                    .map(|mapping| (mapping.species().to_string(), mapping.rank()))
+                    .collect::<Vec<(String, u32)>>()
+            })
+            .unwrap_or_default();
+        species.sort_by_key(|(_, rank)| *rank);
+        Self {
+            spin_polarized: bands_file.spin_polarized,
+            kpoint_count: bands_file.kpoints.len(),
+            bands_per_spin,
+            fermi_energies,
+            lattice_vectors,
+            species,
+            ions: group_orbitals(pdos_weights_file),
+        }
+    }
+
+    /// Cell volume in cubic Angstroms
+    pub fn cell_volume(&self) -> f64 {
+        let [a, b, c] = self.lattice_vectors;
+        let b_cross_c = [
+            b[1] * c[2] - b[2] * c[1],
+            b[2] * c[0] - b[0] * c[2],
+            b[0] * c[1] - b[1] * c[0],
+        ];
+        (a[0] * b_cross_c[0] + a[1] * b_cross_c[1] + a[2] * b_cross_c[2]).abs()
+    }
+
+    fn species_symbol(&self, species_id: u32) -> Option<&str> {
+        self.species
+            .iter()
+            .find(|(_, rank)| *rank == species_id)
+            .map(|(symbol, _)| symbol.as_str())
+    }
+}
+
+fn max_bands(kpts: &[Vec<f64>]) -> usize {
+    kpts.iter().map(|eigens| eigens.len()).max().unwrap_or(0)
+}
+
+/// Group orbital states by ion, in the order of first appearance
+fn group_orbitals(pdos_weights_file: &PDOSWeightsFile) -> Vec<IonOrbitals> {
+    let mut ions: Vec<IonOrbitals> = Vec::new();
+    pdos_weights_file
+        .header
+        .extract_orbital_states()
+        .into_iter()
+        .for_each(|state| {
+            let ion = match ions
+                .iter_mut()
+                .find(|ion| ion.species_id == state.species_id && ion.ion_id == state.ion_id)
+            {
+                Some(ion) => ion,
+                None => {
+                    ions.push(IonOrbitals {
+                        species_id: state.species_id,
+                        ion_id: state.ion_id,
+                        orbitals: Vec::new(),
+                    });
+                    ions.last_mut().unwrap()
+                }
+            };
+            match ion.orbitals.last_mut() {
+                Some((am, count)) if *am == state.angular_momentum => *count += 1,
+                _ => ion.orbitals.push((state.angular_momentum, 1)),
+            }
+        });
+    ions
+}
+
+impl Display for SeedSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let spin = match self.spin_polarized {
+            SpinPolarized::True => "yes",
+            SpinPolarized::False => "no",
+        };
+        let join = |values: Vec<String>| values.join(" / ");
+        writeln!(f, "Spin polarized: {spin}")?;
+        writeln!(f, "K-points: {}", self.kpoint_count)?;
+        writeln!(
+            f,
+            "Bands per spin: {}",
+            join(self.bands_per_spin.iter().map(|n| n.to_string()).collect())
+        )?;
+        writeln!(
+            f,
+            "Fermi energy (eV): {}",
+            join(
+                self.fermi_energies
+                    .iter()
+                    .map(|e| format!("{e:.4}"))
+                    .collect()
+            )
+        )?;
+        writeln!(f, "Lattice vectors (Angstrom):")?;
+        self.lattice_vectors.iter().try_for_each(|vector| {
+            writeln!(
+                f,
+                "  {:>12.6}{:>12.6}{:>12.6}",
+                vector[0], vector[1], vector[2]
+            )
+        })?;
+        writeln!(f, "Cell volume (Angstrom^3): {:.4}", self.cell_volume())?;
+        if self.species.is_empty() {
+            writeln!(
+                f,
+                "Species ranks: unknown, no `.cell`, `.castep` or `mapping`"
+            )?;
+        } else {
+            writeln!(f, "Species ranks:")?;
+            self.species
+                .iter()
+                .try_for_each(|(symbol, rank)| writeln!(f, "  {rank:>3}  {symbol}"))?;
+        }
+        writeln!(f, "Projected orbitals:")?;
+        self.ions.iter().try_for_each(|ion| {
+            let label = self
+                .species_symbol(ion.species_id)
+                .map(|symbol| format!("{symbol} {}", ion.ion_id))
+                .unwrap_or(format!("species {} ion {}", ion.species_id, ion.ion_id));
+            let orbitals = ion
+                .orbitals
+                .iter()
+                .map(|(am, count)| format!("{am} ({count})"))
+                .collect::<Vec<String>>()
+                .join(", ");
+            writeln!(f, "  {label:<16}{orbitals}")
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        fundamental::{AngularMomentum, BOHR_TO_ANGSTROM},
+        projectors::Mapping,
+        test_fixtures::{sample_bands_file, sample_pdos_weights_file},
+    };
+
+    use super::SeedSummary;
+
+    #[test]
+    fn test_summary() {
+        let mapping = [Mapping::new("Mo", 2), Mapping::new("S", 1)];
+        let summary = SeedSummary::new(
+            &sample_bands_file(2),
+            &sample_pdos_weights_file(2),
+            Some(&mapping),
+        );
+        assert_eq!(summary.kpoint_count, 3);
+        assert_eq!(summary.bands_per_spin, vec![4, 4]);
+        assert_eq!(summary.species[0], ("S".to_string(), 1));
+        assert_eq!(summary.ions.len(), 3);
+        assert_eq!(
+            summary.ions[0].orbitals,
+            vec![(AngularMomentum::S, 1), (AngularMomentum::P, 3)]
+        );
+        // 5 Bohr cubic cell
+        let volume = (5.0 * BOHR_TO_ANGSTROM).powi(3);
+        assert!((summary.cell_volume() - volume).abs() < 1e-9);
+        let text = summary.to_string();
+        assert!(text.contains("S 1             s (1), p (3)"), "{text}");
+        assert!(text.contains("Mo 2            s (1)"), "{text}");
+    }
+}
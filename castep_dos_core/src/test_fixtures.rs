@@ -0,0 +1,198 @@
+//! Synthetic seed files for tests
+
+use std::f64::consts::PI;
+
+use crate::{
+    bands::{BandsFile, BandsFileBuilder, Eigenvalues, ElectronCount, FermiEnergy, KPoint},
+    fundamental::{
+        AngularMomentum, BandStructure, EigenvalueVec, HeaderBuilder, KpointCoords, KpointVec,
+        KpointWeight, NumSpins, PDOSBinHeader, PDOSWeightsFile, SpinData, SpinIndex, SpinPolarized,
+        WeightsPerEigen, WeightsPerKPoint, WeightsPerSpin,
+    },
+};
+
+/// Fractional coordinates of the k-points in the fixtures
+pub(crate) const KPOINTS: [[f64; 3]; 3] = [[0.0, 0.0, 0.0], [0.25, 0.0, 0.0], [0.25, 0.25, 0.0]];
+/// Weights of the k-points in the fixtures
+pub(crate) const KPOINT_WEIGHTS: [f64; 3] = [0.25, 0.25, 0.5];
+/// Number of bands of every k-point in the fixtures
+pub(crate) const NBANDS: u32 = 4;
+
+/// Two species: species 1 has one ion with s and p orbitals,
+/// species 2 has two ions with s orbitals.
+/// The p orbitals are written with 2l+1 = 3 entries, as `CASTEP` does.
+pub(crate) fn sample_pdos_weights_file(num_spins: u32) -> PDOSWeightsFile {
+    let orbital_species = vec![1, 1, 1, 1, 2, 2];
+    let orbital_ion = vec![1, 1, 1, 1, 1, 2];
+    let orbital_am = vec![
+        AngularMomentum::S,
+        AngularMomentum::P,
+        AngularMomentum::P,
+        AngularMomentum::P,
+        AngularMomentum::S,
+        AngularMomentum::S,
+    ];
+    let num_orbitals = orbital_am.len();
+    let header = HeaderBuilder::default()
+        .total_kpoints(KPOINTS.len() as u32)
+        .num_spins(NumSpins::try_from(num_spins).unwrap())
+        .num_orbitals(num_orbitals as u32)
+        .max_bands(NBANDS)
+        .orbital_species(orbital_species)
+        .orbital_ion(orbital_ion)
+        .orbital_am(orbital_am)
+        .build()
+        .unwrap();
+    let kpoints = KPOINTS
+        .iter()
+        .enumerate()
+        .map(|(k, coords)| {
+            let spins = (1..=num_spins)
+                .map(|spin| {
+                    let bands = (0..NBANDS as usize)
+                        .map(|band| {
+                            // Deterministic weights, each band sums up to 0.9
+                            let raw = (0..num_orbitals)
+                                .map(|orb| ((k + 1) * (band + 2) * (orb + spin as usize)) % 5 + 1)
+                                .map(|w| w as f64)
+                                .collect::<Vec<f64>>();
+                            let total: f64 = raw.iter().sum();
+                            WeightsPerEigen::new(raw.iter().map(|w| 0.9 * w / total).collect())
+                        })
+                        .collect::<Vec<WeightsPerEigen>>();
+                    WeightsPerSpin::new(SpinIndex::try_from(spin).unwrap(), NBANDS, bands)
+                })
+                .collect::<Vec<WeightsPerSpin>>();
+            WeightsPerKPoint::new(k as u32 + 1, *coords, spins)
+        })
+        .collect::<Vec<WeightsPerKPoint>>();
+    PDOSWeightsFile::new(None, header, kpoints)
+}
+
+/// The leading records of `.pdos_bin`
+pub(crate) fn sample_pdos_bin_header() -> PDOSBinHeader {
+    let mut header = b"CASTEP 25.1 Mon, 30 Jun 2025 12:00:00 +0000".to_vec();
+    header.resize(80, b' ');
+    PDOSBinHeader::new(1.0, header)
+}
+
+/// `.bands` matching `sample_pdos_weights_file`: the lowest two bands
+/// are below the Fermi energy (0.0 Ha), the others are above.
+/// Spin down eigenvalues are shifted up by 2^-7 Ha. All values are
+/// dyadic so they survive the text round trip exactly.
+pub(crate) fn sample_bands_file(num_spins: u32) -> BandsFile {
+    let band_energies = [-0.5, -0.25, 0.0625, 0.3125];
+    let eigenvalues = |shift: f64| -> Vec<Vec<f64>> {
+        (0..KPOINTS.len())
+            .map(|k| {
+                band_energies
+                    .iter()
+                    .map(|e| e + 0.015625 * k as f64 + shift)
+                    .collect()
+            })
+            .collect()
+    };
+    let (spin_polarized, electron_count, fermi_energy, eigenvalues) = match num_spins {
+        1 => (
+            SpinPolarized::False,
+            ElectronCount::NonPolarized(4.0),
+            FermiEnergy::NonPolarized(0.0),
+            Eigenvalues::NonPolarized(eigenvalues(0.0)),
+        ),
+        _ => (
+            SpinPolarized::True,
+            ElectronCount::Polarized(2.0, 2.0),
+            FermiEnergy::Polarized(0.0, 0.0),
+            Eigenvalues::SpinPolarized([eigenvalues(0.0), eigenvalues(0.0078125)]),
+        ),
+    };
+    let kpoints = KPOINTS
+        .iter()
+        .zip(KPOINT_WEIGHTS)
+        .enumerate()
+        .map(|(k, (coords, weight))| KPoint::new(k + 1, *coords, weight))
+        .collect();
+    BandsFileBuilder::default()
+        .spin_polarized(spin_polarized)
+        .lattice_vectors([[5.0, 0.0, 0.0], [0.0, 5.0, 0.0], [0.0, 0.0, 5.0]])
+        .fermi_energy(fermi_energy)
+        .electron_count(electron_count)
+        .eigenvalues(eigenvalues)
+        .kpoints(kpoints)
+        .build()
+        .unwrap()
+}
+
+/// Two bands `-0.1 (cos 2πk1 + cos 2πk2)` and 0.3 Hartree above it on an
+/// `n x n x 1` Monkhorst-Pack grid (`n` even), reduced by time reversal
+pub(crate) fn sample_grid_band_structure(n: usize, spin_polarized: bool) -> BandStructure {
+    let offset = |r: usize| (2.0 * r as f64 - n as f64 - 1.0) / (2.0 * n as f64);
+    let coords = (1..=n)
+        .flat_map(|r1| (1..=n).map(move |r2| [offset(r1), offset(r2), 0.0]))
+        // One of each pair of k and -k
+        .filter(|k| k[0] > 0.0)
+        .collect::<Vec<[f64; 3]>>();
+    let kpoints: KpointVec<KpointCoords> = coords
+        .iter()
+        .enumerate()
+        .map(|(i, &k)| KpointCoords::new(i as u32 + 1, k))
+        .collect();
+    let kpoint_weights = coords
+        .iter()
+        .map(|_| KpointWeight(2.0 / (n * n) as f64))
+        .collect();
+    let eigenvalues = |shift: f64| -> KpointVec<EigenvalueVec<f64>> {
+        coords
+            .iter()
+            .map(|k| {
+                let band = -0.1 * ((2.0 * PI * k[0]).cos() + (2.0 * PI * k[1]).cos()) + shift;
+                EigenvalueVec(vec![band, band + 0.3])
+            })
+            .collect()
+    };
+    let lattice_vectors = [[10.0, 0.0, 0.0], [0.0, 10.0, 0.0], [0.0, 0.0, 10.0]];
+    // States on the Fermi energy, where cos 2πk1 = -cos 2πk2, count half
+    let occupied = |eigenvalues: &KpointVec<EigenvalueVec<f64>>| -> f64 {
+        eigenvalues
+            .iter()
+            .flat_map(|eigens| eigens.iter())
+            .map(|&e| {
+                if e.abs() < 1e-12 {
+                    0.5
+                } else if e < 0.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            })
+            .sum::<f64>()
+            * 2.0
+            / (n * n) as f64
+    };
+    let (spin, fermi_energy, electron_count, eigenvalues) = if spin_polarized {
+        let [up, down] = [eigenvalues(0.0), eigenvalues(0.01)];
+        (
+            SpinPolarized::True,
+            SpinData::SpinPolarized([0.0, 0.0]),
+            SpinData::SpinPolarized([occupied(&up), occupied(&down)]),
+            SpinData::SpinPolarized([up, down]),
+        )
+    } else {
+        let eigenvalues = eigenvalues(0.0);
+        (
+            SpinPolarized::False,
+            SpinData::NonPolarized(0.0),
+            SpinData::NonPolarized(2.0 * occupied(&eigenvalues)),
+            SpinData::NonPolarized(eigenvalues),
+        )
+    };
+    BandStructure::new(
+        spin,
+        fermi_energy,
+        electron_count,
+        lattice_vectors,
+        kpoints,
+        kpoint_weights,
+        eigenvalues,
+    )
+}
@@ -0,0 +1,458 @@
+use std::collections::HashMap;
+
+use crate::{
+    bands::{BandsFile, Eigenvalues, ElectronCount, FermiEnergy},
+    fundamental::{OrbitalState, PDOSWeightsFile, SpinData, SpinPolarized, align_kpoints},
+    pdos_compute::PDOSResult,
+    projectors::PDOSConfig,
+};
+
+use super::{Check, ValidationReport};
+
+/// `.bands` writes the k-point weights with 8 decimals
+const WEIGHT_SUM_TOLERANCE: f64 = 1e-5;
+/// Counting states below the Fermi energy is exact for insulators
+const ELECTRON_COUNT_TOLERANCE: f64 = 1e-3;
+/// Relative, the orbital projections miss the spilling of the basis,
+/// usually below 1%, and smearing moves states across the Fermi energy
+const SUM_RULE_TOLERANCE: f64 = 0.02;
+
+/// Check that `.bands` and `.pdos_weights` come from the same calculation
+pub fn validate_seed(
+    bands_file: &BandsFile,
+    pdos_weights_file: &PDOSWeightsFile,
+) -> ValidationReport {
+    ValidationReport::new(vec![
+        check_kpoint_count(bands_file, pdos_weights_file),
+        check_kpoint_matching(bands_file, pdos_weights_file),
+        check_spin_count(bands_file, pdos_weights_file),
+        check_band_count(bands_file, pdos_weights_file),
+        check_kpoint_weights(bands_file),
+        check_electron_count(bands_file),
+    ])
+}
+
+/// Check that every species, atom and sub-orbital selected in the config
+/// exists in `orbital_states`. `species_mapping` is `None` when the config has no
+/// `mapping` and no seed file provides one.
+pub fn validate_config(
+    config: &PDOSConfig,
+    species_mapping: Option<&HashMap<&str, u32>>,
+    orbital_states: &[OrbitalState],
+) -> ValidationReport {
+    let name = "config selections";
+    let Some(species_mapping) = species_mapping else {
+        return ValidationReport::new(vec![Check::fail(
+            name,
+            "no `mapping` in config and no `.cell` or `.castep` to derive it from",
+        )]);
+    };
+    let mut problems = Vec::new();
+    let mut selection_count = 0;
+    config
+        .projectors
+        .iter()
+        .enumerate()
+        .filter_map(|(i, projector)| {
+            projector.selections.as_ref().map(|selections| {
+                let projector_name = projector
+                    .name
+                    .clone()
+                    .unwrap_or(format!("setting_{}", i + 1));
+                (projector_name, selections)
+            })
+        })
+        .for_each(|(projector_name, selections)| {
+            selections.iter().for_each(|selection| {
+                selection_count += 1;
+                let symbol = selection.species().as_str();
+                let Some(&species_id) = species_mapping.get(symbol) else {
+                    problems.push(format!(
+                        "{projector_name}: species `{symbol}` is not in `mapping`"
+                    ));
+                    return;
+                };
+                if !orbital_states
+                    .iter()
+                    .any(|state| state.species_id == species_id)
+                {
+                    problems.push(format!(
+                        "{projector_name}: species `{symbol}` (rank {species_id}) has no orbitals"
+                    ));
+                    return;
+                }
+                let missing_atoms = selection
+                    .atoms()
+                    .map(|atoms| {
+                        atoms
+                            .iter()
+                            .filter(|&&atom| {
+                                !orbital_states.iter().any(|state| {
+                                    state.species_id == species_id && state.ion_id == atom
+                                })
+                            })
+                            .map(|atom| atom.to_string())
+                            .collect::<Vec<String>>()
+                    })
+                    .unwrap_or_default();
+                if !missing_atoms.is_empty() {
+                    problems.push(format!(
+                        "{projector_name}: species `{symbol}` has no atom {}",
+                        missing_atoms.join(", ")
+                    ));
+                }
+                let missing_orbitals = selection
+                    .orbitals()
+                    .map(|labels| {
+                        labels
+                            .iter()
+                            .filter(|label| {
+                                !orbital_states.iter().any(|state| {
+                                    state.species_id == species_id
+                                        && label.contains(state.angular_momentum, state.m_index)
+                                })
+                            })
+                            .map(|label| label.to_string())
+                            .collect::<Vec<String>>()
+                    })
+                    .unwrap_or_default();
+                if !missing_orbitals.is_empty() {
+                    problems.push(format!(
+                        "{projector_name}: species `{symbol}` has no {} orbitals",
+                        missing_orbitals.join(", ")
+                    ));
+                }
+            })
+        });
+    let check = if problems.is_empty() {
+        Check::pass(
+            name,
+            format!("all {selection_count} selections found in orbitals"),
+        )
+    } else {
+        Check::fail(name, problems.join("; "))
+    };
+    ValidationReport::new(vec![check])
+}
+
+/// Check that the states of `total_pdos` below the Fermi energy add up to
+/// `electron_count` of `.bands`. `total_pdos` is projected on all orbitals,
+/// `fermi_energy` is where the Fermi energy of each spin sits on
+/// `energy_grid` in eV, zero unless another `EnergyReference` is used.
+/// States missing from the grid or from the projections show up as a
+/// shortfall.
+pub fn check_sum_rule(
+    total_pdos: &SpinData<PDOSResult>,
+    energy_grid: &[f64],
+    fermi_energy: &SpinData<f64>,
+    electron_count: &SpinData<f64>,
+) -> Check {
+    let name = "electron sum rule";
+    let (expected, counted) = match (total_pdos, fermi_energy, electron_count) {
+        (
+            SpinData::NonPolarized(pdos),
+            SpinData::NonPolarized(fermi),
+            SpinData::NonPolarized(count),
+        ) => (vec![*count], vec![pdos.states_below(energy_grid, *fermi)]),
+        (
+            SpinData::SpinPolarized([up, down]),
+            SpinData::SpinPolarized([fermi_up, fermi_down]),
+            SpinData::SpinPolarized([count_up, count_down]),
+        ) => (
+            vec![*count_up, *count_down],
+            vec![
+                up.states_below(energy_grid, *fermi_up),
+                down.states_below(energy_grid, *fermi_down),
+            ],
+        ),
+        _ => {
+            return Check::fail(
+                name,
+                "spin polarization of the PDOS, the Fermi energy and the electron count disagree",
+            );
+        }
+    };
+    let max_diff = expected
+        .iter()
+        .zip(counted.iter())
+        .map(|(e, c)| (e - c).abs() / e.max(1.0))
+        .fold(0.0, f64::max);
+    let message = format!(
+        "`.bands` declares {}, projected states below Fermi energy hold {}",
+        format_counts(&expected),
+        format_counts(&counted)
+    );
+    if max_diff < SUM_RULE_TOLERANCE {
+        Check::pass(name, message)
+    } else {
+        Check::fail(
+            name,
+            format!("{message}; check the energy window and the projections"),
+        )
+    }
+}
+
+fn check_kpoint_count(bands_file: &BandsFile, pdos_weights_file: &PDOSWeightsFile) -> Check {
+    let name = "k-point count";
+    let bands = bands_file.kpoints.len();
+    let header = pdos_weights_file.header.total_kpoints as usize;
+    let records = pdos_weights_file.kpoints.len();
+    if bands == header && header == records {
+        Check::pass(name, format!("{bands} k-points"))
+    } else {
+        Check::fail(
+            name,
+            format!(
+                "`.bands` has {bands}, `.pdos_weights` header declares {header} and contains {records}"
+            ),
+        )
+    }
+}
+
+fn check_kpoint_matching(bands_file: &BandsFile, pdos_weights_file: &PDOSWeightsFile) -> Check {
+    let name = "k-point indices";
+    let mut band_structure = bands_file.clone().to_band_structure();
+    let mut pdos_weights = pdos_weights_file.to_pdos_weights();
+    match align_kpoints(&mut band_structure, &mut pdos_weights) {
+        Ok(()) => Check::pass(name, "indices and coordinates match"),
+        Err(e) => Check::fail(name, e.to_string()),
+    }
+}
+
+fn check_spin_count(bands_file: &BandsFile, pdos_weights_file: &PDOSWeightsFile) -> Check {
+    let name = "spin count";
+    let bands = match bands_file.spin_polarized {
+        SpinPolarized::True => 2,
+        SpinPolarized::False => 1,
+    };
+    let header = pdos_weights_file.header.num_spins.spin_count();
+    let inconsistent_kpoints = pdos_weights_file
+        .kpoints
+        .iter()
+        .filter(|kpt| kpt.spins.len() != header)
+        .count();
+    if bands != header {
+        Check::fail(
+            name,
+            format!("`.bands` has {bands} spin components, `.pdos_weights` has {header}"),
+        )
+    } else if inconsistent_kpoints > 0 {
+        Check::fail(
+            name,
+            format!(
+                "{inconsistent_kpoints} k-points of `.pdos_weights` do not have {header} spin components"
+            ),
+        )
+    } else {
+        Check::pass(name, format!("{bands} spin components"))
+    }
+}
+
+fn check_band_count(bands_file: &BandsFile, pdos_weights_file: &PDOSWeightsFile) -> Check {
+    let name = "band count";
+    let bands_max = match &bands_file.eigenvalues {
+        Eigenvalues::NonPolarized(kpts) => kpts.iter().map(|eigens| eigens.len()).max(),
+        Eigenvalues::SpinPolarized([up, down]) => up
+            .iter()
+            .chain(down.iter())
+            .map(|eigens| eigens.len())
+            .max(),
+    }
+    .unwrap_or(0);
+    let max_bands = pdos_weights_file.header.max_bands as usize;
+    let max_occ = pdos_weights_file
+        .kpoints
+        .iter()
+        .flat_map(|kpt| kpt.spins.iter().map(|spin| spin.nbands_occ as usize))
+        .max()
+        .unwrap_or(0);
+    if max_occ > max_bands {
+        Check::fail(
+            name,
+            format!("`nbands_occ` {max_occ} exceeds `max_bands` {max_bands} in `.pdos_weights`"),
+        )
+    } else if max_bands != bands_max {
+        Check::fail(
+            name,
+            format!("`.bands` has {bands_max} eigenvalues, `.pdos_weights` has {max_bands} bands"),
+        )
+    } else {
+        Check::pass(name, format!("{bands_max} bands"))
+    }
+}
+
+fn check_kpoint_weights(bands_file: &BandsFile) -> Check {
+    let name = "k-point weights";
+    let sum: f64 = bands_file.kpoints.iter().map(|kpt| kpt.weight).sum();
+    if (sum - 1.0).abs() < WEIGHT_SUM_TOLERANCE {
+        Check::pass(name, format!("sum to {sum:.8}"))
+    } else {
+        Check::fail(name, format!("sum to {sum:.8} instead of 1"))
+    }
+}
+
+/// Count the states below the Fermi energy, weighted by the k-point weights.
+fn check_electron_count(bands_file: &BandsFile) -> Check {
+    let name = "electron count";
+    let weight_sum: f64 = bands_file.kpoints.iter().map(|kpt| kpt.weight).sum();
+    let occupied = |kpts: &[Vec<f64>], fermi: f64| -> f64 {
+        kpts.iter()
+            .zip(bands_file.kpoints.iter())
+            .map(|(eigens, kpt)| kpt.weight * eigens.iter().filter(|&&e| e <= fermi).count() as f64)
+            .sum::<f64>()
+            / weight_sum
+    };
+    let (expected, counted) = match (
+        &bands_file.eigenvalues,
+        bands_file.fermi_energy,
+        bands_file.electron_count,
+    ) {
+        (
+            Eigenvalues::NonPolarized(kpts),
+            FermiEnergy::NonPolarized(fermi),
+            ElectronCount::NonPolarized(count),
+        ) => (vec![count], vec![2.0 * occupied(kpts, fermi)]),
+        (
+            Eigenvalues::SpinPolarized([up, down]),
+            FermiEnergy::Polarized(fermi_up, fermi_down),
+            ElectronCount::Polarized(count_up, count_down),
+        ) => (
+            vec![count_up, count_down],
+            vec![occupied(up, fermi_up), occupied(down, fermi_down)],
+        ),
+        _ => {
+            return Check::fail(
+                name,
+                "spin polarization of eigenvalues, Fermi energy and electron count disagree",
+            );
+        }
+    };
+    let max_diff = expected
+        .iter()
+        .zip(counted.iter())
+        .map(|(e, c)| (e - c).abs())
+        .fold(0.0, f64::max);
+    let message = format!(
+        "`.bands` declares {}, states below Fermi energy hold {}",
+        format_counts(&expected),
+        format_counts(&counted)
+    );
+    if max_diff < ELECTRON_COUNT_TOLERANCE {
+        Check::pass(name, message)
+    } else if max_diff < 1.0 {
+        Check::warn(
+            name,
+            format!("{message}; partially occupied bands, expected for metals"),
+        )
+    } else {
+        Check::fail(name, message)
+    }
+}
+
+fn format_counts(counts: &[f64]) -> String {
+    counts
+        .iter()
+        .map(|c| format!("{c:.3}"))
+        .collect::<Vec<String>>()
+        .join(" / ")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        bands::ElectronCount,
+        fundamental::{AngularChannels, EigenvalueVec},
+        pdos_compute::{BroadeningKernel, calculate_pdos},
+        projectors::PDOSConfig,
+        test_fixtures::{sample_bands_file, sample_grid_band_structure, sample_pdos_weights_file},
+        validation::{CheckStatus, check_sum_rule, validate_config, validate_seed},
+    };
+
+    #[test]
+    fn test_validate_seed() {
+        [1, 2].into_iter().for_each(|num_spins| {
+            let report = validate_seed(
+                &sample_bands_file(num_spins),
+                &sample_pdos_weights_file(num_spins),
+            );
+            assert_eq!(report.status(), CheckStatus::Pass, "{report}");
+        });
+        // `.bands` of another run
+        let mut bands_file = sample_bands_file(1);
+        bands_file.electron_count = ElectronCount::NonPolarized(6.0);
+        bands_file.kpoints[0].weight = 0.5;
+        let report = validate_seed(&bands_file, &sample_pdos_weights_file(2));
+        assert!(!report.passed());
+        assert_eq!(report.count(CheckStatus::Fail), 3, "{report}");
+    }
+
+    #[test]
+    fn test_validate_config() {
+        let config = toml::from_str::<PDOSConfig>(
+            r#"
+mapping=[{species="S", rank=1}, {species="Mo", rank=2}]
+[[projector]]
+[[projector.selections]]
+species = "Mo"
+atoms = [1, 2, 3]
+orbitals = ["s", "t2g"]
+[[projector.selections]]
+species = "W"
+"#,
+        )
+        .unwrap();
+        let orbital_states = sample_pdos_weights_file(1).header.extract_orbital_states();
+        let mapping = config.species_mapping(None);
+        let report = validate_config(&config, mapping.as_ref(), &orbital_states);
+        assert_eq!(report.status(), CheckStatus::Fail);
+        let message = &report.checks[0].message;
+        assert!(message.contains("no atom 3"), "{message}");
+        assert!(message.contains("`W` is not in `mapping`"), "{message}");
+        assert!(message.contains("has no t2g orbitals"), "{message}");
+        let report = validate_config(&config, None, &orbital_states);
+        assert_eq!(report.status(), CheckStatus::Fail);
+    }
+
+    #[test]
+    fn test_sum_rule() {
+        [false, true].into_iter().for_each(|spin_polarized| {
+            let band_structure = sample_grid_band_structure(8, spin_polarized);
+            // All orbitals: the weights of each state sum to one
+            let projected_weights = band_structure.eigenvalues.map(|kpts| {
+                kpts.iter()
+                    .map(|eigens| {
+                        EigenvalueVec(vec![AngularChannels::new(0.5, 0.5, 0.0, 0.0); eigens.len()])
+                    })
+                    .collect()
+            });
+            let check = |min: f64| {
+                let energy_grid = (0..=2400)
+                    .map(|i| min + 0.01 * i as f64)
+                    .collect::<Vec<f64>>();
+                let pdos = calculate_pdos(
+                    &band_structure,
+                    &projected_weights,
+                    &energy_grid,
+                    &BroadeningKernel::Gaussian,
+                    0.05,
+                )
+                .unwrap();
+                let fermi_energy = band_structure.fermi_energy.map(|_| 0.0);
+                check_sum_rule(
+                    &pdos,
+                    &energy_grid,
+                    &fermi_energy,
+                    &band_structure.electron_count,
+                )
+            };
+            assert_eq!(
+                check(-8.0).status,
+                CheckStatus::Pass,
+                "{}",
+                check(-8.0).message
+            );
+            // The window misses the bottom of the lower band
+            assert_eq!(check(-3.0).status, CheckStatus::Fail);
+        });
+    }
+}
@@ -0,0 +1,136 @@
+#![warn(missing_docs)]
+#![allow(dead_code)]
+//! Cross-check the seed files of a PDOS calculation for consistency.
+
+use std::fmt::Display;
+
+mod checks;
+
+pub use checks::{check_sum_rule, validate_config, validate_seed};
+
+/// Outcome of a single check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CheckStatus {
+    /// Consistent
+    Pass,
+    /// Suspicious but the calculation can go on
+    Warn,
+    /// The files do not belong together, or the config is invalid
+    Fail,
+}
+
+impl Display for CheckStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheckStatus::Pass => write!(f, "PASS"),
+            CheckStatus::Warn => write!(f, "WARN"),
+            CheckStatus::Fail => write!(f, "FAIL"),
+        }
+    }
+}
+
+/// A named check and what it found
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    /// Short name of the check
+    pub name: String,
+    /// Outcome
+    pub status: CheckStatus,
+    /// Human readable details
+    pub message: String,
+}
+
+impl Check {
+    /// Constructor
+    pub fn new(name: &str, status: CheckStatus, message: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status,
+            message: message.into(),
+        }
+    }
+
+    /// Passed check
+    pub fn pass(name: &str, message: impl Into<String>) -> Self {
+        Self::new(name, CheckStatus::Pass, message)
+    }
+
+    /// Check with a warning
+    pub fn warn(name: &str, message: impl Into<String>) -> Self {
+        Self::new(name, CheckStatus::Warn, message)
+    }
+
+    /// Failed check
+    pub fn fail(name: &str, message: impl Into<String>) -> Self {
+        Self::new(name, CheckStatus::Fail, message)
+    }
+}
+
+/// Collection of checks, displayed as a human readable report
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValidationReport {
+    /// Checks in the order they were run
+    pub checks: Vec<Check>,
+}
+
+impl ValidationReport {
+    /// Constructor
+    pub fn new(checks: Vec<Check>) -> Self {
+        Self { checks }
+    }
+
+    /// Append the checks of another report
+    pub fn merge(&mut self, other: ValidationReport) {
+        self.checks.extend(other.checks);
+    }
+
+    /// The worst status among the checks, `Pass` for an empty report
+    pub fn status(&self) -> CheckStatus {
+        self.checks
+            .iter()
+            .map(|check| check.status)
+            .max()
+            .unwrap_or(CheckStatus::Pass)
+    }
+
+    /// No check has failed
+    pub fn passed(&self) -> bool {
+        self.status() != CheckStatus::Fail
+    }
+
+    /// Number of checks with the given status
+    pub fn count(&self, status: CheckStatus) -> usize {
+        self.checks
+            .iter()
+            .filter(|check| check.status == status)
+            .count()
+    }
+}
+
+impl Display for ValidationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name_width = self
+            .checks
+            .iter()
+            .map(|check| check.name.len())
+            .max()
+            .unwrap_or(0);
+        self.checks.iter().try_for_each(|check| {
+            writeln!(
+                f,
+                "[{}] {:<width$}  {}",
+                check.status,
+                check.name,
+                check.message,
+                width = name_width
+            )
+        })?;
+        write!(
+            f,
+            "{} passed, {} warnings, {} failed",
+            self.count(CheckStatus::Pass),
+            self.count(CheckStatus::Warn),
+            self.count(CheckStatus::Fail)
+        )
+    }
+}